@@ -5,8 +5,8 @@ layout (location = 1) in vec2 vInUv;
 
 layout (location = 0) out vec4 vOutColor;
 
-layout (binding = 10) uniform sampler2D pixels; 
+layout (binding = 10) uniform sampler2D pixels;
 
 void main() {
     vOutColor = texture(pixels, vInUv);
-}
\ No newline at end of file
+}
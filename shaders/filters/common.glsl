@@ -0,0 +1,19 @@
+// Shared by every filter: 16x16 workgroups reading `input_image` and writing
+// `output_image`, both RGBA8.
+
+layout (local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+layout (set = 0, binding = 0, rgba8) uniform readonly image2D input_image;
+layout (set = 0, binding = 1, rgba8) uniform image2D output_image;
+
+// Input pixel, positions outside the image repeat the edge.
+vec4 load_clamped(ivec2 position)
+{
+    return imageLoad(input_image, clamp(position, ivec2(0), imageSize(input_image) - ivec2(1)));
+}
+
+// Rec. 709 weights.
+float luminance(vec3 color)
+{
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
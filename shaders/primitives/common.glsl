@@ -0,0 +1,9 @@
+// Shared by every primitive: one workgroup per block of BLOCK_SIZE values.
+// `Primitives` defines BLOCK_SIZE when it compiles the kernels, it must be a
+// power of two of at least 16.
+
+#ifndef BLOCK_SIZE
+#define BLOCK_SIZE 256
+#endif
+
+layout (local_size_x = BLOCK_SIZE, local_size_y = 1, local_size_z = 1) in;
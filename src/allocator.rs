@@ -0,0 +1,743 @@
+//! Sub-allocation of device memory.
+//!
+//! Drivers only guarantee `maxMemoryAllocationCount` (4096 on most desktop GPUs)
+//! live `vkAllocateMemory` calls, so buffers and images share large blocks, one
+//! pool of blocks per memory type. Resources bigger than half a block get a
+//! dedicated allocation instead.
+//!
+//! Linear resources (buffers, linear images) and optimal images must not share a
+//! `bufferImageGranularity` page, so optimal images are given whole pages.
+
+use crate::device::Device;
+use crate::objects::DeviceMemory;
+use ash::version::DeviceV1_0;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::cell::RefCell;
+use std::fmt;
+use std::os::raw::c_void;
+use std::rc::Rc;
+
+const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 << 20;
+
+/// What is going to be bound to an allocation, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResourceKind {
+    Linear,
+    OptimalImage,
+}
+
+/// Bytes used by live allocations and bytes reserved from the driver in one heap.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapStats {
+    pub heap_index: u32,
+    pub used: vk::DeviceSize,
+    pub reserved: vk::DeviceSize,
+    pub block_count: u32,
+    pub dedicated_count: u32,
+    pub allocation_count: u32,
+}
+
+pub struct AllocatorStats {
+    pub heaps: Vec<HeapStats>,
+    /// Live `vkAllocateMemory` allocations, and the device limit.
+    pub device_memory_count: u32,
+    pub max_memory_allocation_count: u32,
+}
+
+impl fmt::Display for AllocatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "device memory allocations: {}/{}",
+            self.device_memory_count, self.max_memory_allocation_count
+        )?;
+        for heap in &self.heaps {
+            writeln!(
+                f,
+                "heap {}: {} KiB used / {} KiB reserved, {} allocations in {} blocks + {} dedicated",
+                heap.heap_index,
+                heap.used >> 10,
+                heap.reserved >> 10,
+                heap.allocation_count,
+                heap.block_count,
+                heap.dedicated_count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Block {
+    memory: DeviceMemory,
+    size: vk::DeviceSize,
+    // Holds a single allocation and is freed with it.
+    dedicated: bool,
+    free_ranges: FreeRanges,
+    allocation_count: u32,
+    mapped: *mut c_void,
+    map_count: u32,
+}
+
+impl Block {
+    /// First fit; returns the offset of the allocation.
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let offset = self.free_ranges.allocate(size, alignment)?;
+        self.allocation_count += 1;
+        Some(offset)
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        self.free_ranges.free(offset, size);
+        self.allocation_count -= 1;
+    }
+}
+
+/// Free `(offset, size)` ranges of a block, sorted by offset, adjacent ranges
+/// merged.
+struct FreeRanges(Vec<(vk::DeviceSize, vk::DeviceSize)>);
+
+impl FreeRanges {
+    fn new(size: vk::DeviceSize) -> Self {
+        FreeRanges(vec![(0, size)])
+    }
+
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let (index, offset) =
+            self.0
+                .iter()
+                .enumerate()
+                .find_map(|(index, (range_offset, range_size))| {
+                    let offset = align_up(*range_offset, alignment);
+                    if offset + size <= range_offset + range_size {
+                        Some((index, offset))
+                    } else {
+                        None
+                    }
+                })?;
+        let (range_offset, range_size) = self.0.remove(index);
+        let range_end = range_offset + range_size;
+        if offset + size < range_end {
+            self.0
+                .insert(index, (offset + size, range_end - offset - size));
+        }
+        if range_offset < offset {
+            self.0.insert(index, (range_offset, offset - range_offset));
+        }
+        Some(offset)
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self
+            .0
+            .iter()
+            .position(|(range_offset, _)| *range_offset > offset)
+            .unwrap_or(self.0.len());
+        self.0.insert(index, (offset, size));
+        if index + 1 < self.0.len() {
+            let (next_offset, next_size) = self.0[index + 1];
+            if offset + size == next_offset {
+                self.0[index].1 += next_size;
+                self.0.remove(index + 1);
+            }
+        }
+        if index > 0 {
+            let (previous_offset, previous_size) = self.0[index - 1];
+            if previous_offset + previous_size == offset {
+                self.0[index - 1].1 += self.0[index].1;
+                self.0.remove(index);
+            }
+        }
+    }
+}
+
+/// Size and alignment to allocate for `requirements`: optimal images are
+/// padded to whole `bufferImageGranularity` pages.
+fn placement(
+    requirements: &vk::MemoryRequirements,
+    kind: ResourceKind,
+    buffer_image_granularity: vk::DeviceSize,
+) -> (vk::DeviceSize, vk::DeviceSize) {
+    match kind {
+        ResourceKind::Linear => (requirements.size, requirements.alignment.max(1)),
+        ResourceKind::OptimalImage => {
+            let granularity = buffer_image_granularity.max(1);
+            (
+                align_up(requirements.size, granularity),
+                requirements.alignment.max(granularity),
+            )
+        }
+    }
+}
+
+struct AllocatorState {
+    device: Rc<Device>,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: vk::DeviceSize,
+    non_coherent_atom_size: vk::DeviceSize,
+    max_memory_allocation_count: u32,
+    block_size: vk::DeviceSize,
+    // Indexed by memory type; freed slots stay `None` so indices remain valid.
+    pools: Vec<Vec<Option<Block>>>,
+    device_memory_count: u32,
+    heaps: Vec<HeapStats>,
+}
+
+impl AllocatorState {
+    fn heap_of(&mut self, memory_type_index: u32) -> &mut HeapStats {
+        let heap_index = self.memory_properties.memory_types[memory_type_index as usize].heap_index;
+        &mut self.heaps[heap_index as usize]
+    }
+
+    /// Blocks are capped to an eighth of their heap, for small heaps like the
+    /// 256 MiB host visible device local one.
+    fn block_size_for(&self, memory_type_index: u32) -> vk::DeviceSize {
+        let heap_index = self.memory_properties.memory_types[memory_type_index as usize].heap_index;
+        let heap_size = self.memory_properties.memory_heaps[heap_index as usize].size;
+        self.block_size.min(heap_size / 8).max(1 << 20)
+    }
+
+    unsafe fn allocate_device_memory(
+        &mut self,
+        size: vk::DeviceSize,
+        memory_type_index: u32,
+    ) -> anyhow::Result<DeviceMemory> {
+        if self.device_memory_count >= self.max_memory_allocation_count {
+            anyhow::bail!(
+                "Cannot allocate device memory: maxMemoryAllocationCount ({}) reached",
+                self.max_memory_allocation_count
+            );
+        }
+        let memory_allocate_info = vk::MemoryAllocateInfo {
+            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+            p_next: std::ptr::null(),
+            allocation_size: size,
+            memory_type_index,
+        };
+        let memory = DeviceMemory::new(&self.device, &memory_allocate_info)?;
+        self.device_memory_count += 1;
+        self.heap_of(memory_type_index).reserved += size;
+        Ok(memory)
+    }
+
+    /// Puts `block` in the first free slot of its pool.
+    fn insert_block(&mut self, memory_type_index: u32, block: Block) -> usize {
+        let heap = self.heap_of(memory_type_index);
+        if block.dedicated {
+            heap.dedicated_count += 1;
+        } else {
+            heap.block_count += 1;
+        }
+        let pool = &mut self.pools[memory_type_index as usize];
+        match pool.iter().position(Option::is_none) {
+            Some(slot) => {
+                pool[slot] = Some(block);
+                slot
+            }
+            None => {
+                pool.push(Some(block));
+                pool.len() - 1
+            }
+        }
+    }
+
+    fn remove_block(&mut self, memory_type_index: u32, slot: usize) {
+        let block = self.pools[memory_type_index as usize][slot].take().unwrap();
+        self.device_memory_count -= 1;
+        let heap = self.heap_of(memory_type_index);
+        heap.reserved -= block.size;
+        if block.dedicated {
+            heap.dedicated_count -= 1;
+        } else {
+            heap.block_count -= 1;
+        }
+    }
+
+    fn block(&mut self, memory_type_index: u32, slot: usize) -> &mut Block {
+        self.pools[memory_type_index as usize][slot]
+            .as_mut()
+            .unwrap()
+    }
+}
+
+/// Hands out `Allocation`s from per memory type pools of blocks.
+///
+/// Allocations keep the allocator state alive, so they can outlive it.
+pub struct DeviceMemoryAllocator {
+    state: Rc<RefCell<AllocatorState>>,
+}
+
+impl DeviceMemoryAllocator {
+    /// # Safety
+    ///
+    /// `device` must outlive the allocator and its allocations.
+    pub unsafe fn new(device: &Rc<Device>) -> Self {
+        let instance = device.instance();
+        let memory_properties = instance.get_physical_device_memory_properties(device.gpu());
+        let limits = instance.get_physical_device_properties(device.gpu()).limits;
+        let heaps = (0..memory_properties.memory_heap_count)
+            .map(|heap_index| HeapStats {
+                heap_index,
+                ..Default::default()
+            })
+            .collect();
+        DeviceMemoryAllocator {
+            state: Rc::new(RefCell::new(AllocatorState {
+                device: device.clone(),
+                memory_properties,
+                buffer_image_granularity: limits.buffer_image_granularity,
+                non_coherent_atom_size: limits.non_coherent_atom_size,
+                max_memory_allocation_count: limits.max_memory_allocation_count,
+                block_size: DEFAULT_BLOCK_SIZE,
+                pools: (0..memory_properties.memory_type_count)
+                    .map(|_| Vec::new())
+                    .collect(),
+                device_memory_count: 0,
+                heaps,
+            })),
+        }
+    }
+
+    /// Size of the blocks allocated from now on (64 MiB by default).
+    pub fn set_block_size(&self, block_size: vk::DeviceSize) {
+        self.state.borrow_mut().block_size = block_size;
+    }
+
+    pub fn memory_properties(&self) -> vk::PhysicalDeviceMemoryProperties {
+        self.state.borrow().memory_properties
+    }
+
+    /// Allocates memory for `requirements` from the first allowed memory type
+    /// having all of `properties`.
+    ///
+    /// # Safety
+    ///
+    /// The allocation must be freed before the allocator's device is destroyed.
+    pub unsafe fn allocate(
+        &self,
+        requirements: &vk::MemoryRequirements,
+        properties: vk::MemoryPropertyFlags,
+        kind: ResourceKind,
+    ) -> anyhow::Result<Allocation> {
+        let mut state = self.state.borrow_mut();
+        let memory_type_index = (0..state.memory_properties.memory_type_count)
+            .find(|index| {
+                requirements.memory_type_bits & (1 << index) != 0
+                    && state.memory_properties.memory_types[*index as usize]
+                        .property_flags
+                        .contains(properties)
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Cannot find a memory type with {:?} in bits {:#b}",
+                    properties,
+                    requirements.memory_type_bits
+                )
+            })?;
+
+        let (size, alignment) = placement(requirements, kind, state.buffer_image_granularity);
+        let block_size = state.block_size_for(memory_type_index);
+        let dedicated = size > block_size / 2;
+        let pool = &mut state.pools[memory_type_index as usize];
+        let found = if dedicated {
+            None
+        } else {
+            pool.iter_mut().enumerate().find_map(|(slot, block)| {
+                let block = block.as_mut().filter(|block| !block.dedicated)?;
+                let offset = block.allocate(size, alignment)?;
+                Some((slot, offset))
+            })
+        };
+        let (slot, offset) = match found {
+            Some(found) => found,
+            None => {
+                let memory_size = if dedicated { size } else { block_size };
+                let memory = state.allocate_device_memory(memory_size, memory_type_index)?;
+                let mut block = Block {
+                    memory,
+                    size: memory_size,
+                    dedicated,
+                    free_ranges: FreeRanges::new(memory_size),
+                    allocation_count: 0,
+                    mapped: std::ptr::null_mut(),
+                    map_count: 0,
+                };
+                let offset = block.allocate(size, alignment).unwrap();
+                (state.insert_block(memory_type_index, block), offset)
+            }
+        };
+        let memory = state.block(memory_type_index, slot).memory.handle();
+        let heap = state.heap_of(memory_type_index);
+        heap.used += size;
+        heap.allocation_count += 1;
+        Ok(Allocation {
+            state: self.state.clone(),
+            memory,
+            memory_type_index,
+            slot,
+            offset,
+            size,
+        })
+    }
+
+    /// Allocates and binds memory for `buffer`.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must come from the allocator's device and must not be bound yet.
+    pub unsafe fn allocate_for_buffer(
+        &self,
+        buffer: vk::Buffer,
+        properties: vk::MemoryPropertyFlags,
+    ) -> anyhow::Result<Allocation> {
+        let device = self.state.borrow().device.clone();
+        let requirements = device.get_buffer_memory_requirements(buffer);
+        let allocation = self.allocate(&requirements, properties, ResourceKind::Linear)?;
+        device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?;
+        Ok(allocation)
+    }
+
+    /// Allocates and binds memory for `image`, created with `tiling`.
+    ///
+    /// # Safety
+    ///
+    /// `image` must come from the allocator's device, must not be bound yet and must have been
+    /// created with `tiling`.
+    pub unsafe fn allocate_for_image(
+        &self,
+        image: vk::Image,
+        tiling: vk::ImageTiling,
+        properties: vk::MemoryPropertyFlags,
+    ) -> anyhow::Result<Allocation> {
+        let device = self.state.borrow().device.clone();
+        let requirements = device.get_image_memory_requirements(image);
+        let kind = if tiling == vk::ImageTiling::LINEAR {
+            ResourceKind::Linear
+        } else {
+            ResourceKind::OptimalImage
+        };
+        let allocation = self.allocate(&requirements, properties, kind)?;
+        device.bind_image_memory(image, allocation.memory(), allocation.offset())?;
+        Ok(allocation)
+    }
+
+    /// Gives empty blocks back to the driver.
+    pub fn trim(&self) {
+        let mut state = self.state.borrow_mut();
+        for memory_type_index in 0..state.pools.len() {
+            for slot in 0..state.pools[memory_type_index].len() {
+                let is_empty = match &state.pools[memory_type_index][slot] {
+                    Some(block) => block.allocation_count == 0,
+                    None => false,
+                };
+                if is_empty {
+                    state.remove_block(memory_type_index as u32, slot);
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        let state = self.state.borrow();
+        AllocatorStats {
+            heaps: state.heaps.clone(),
+            device_memory_count: state.device_memory_count,
+            max_memory_allocation_count: state.max_memory_allocation_count,
+        }
+    }
+}
+
+/// A range of device memory, given back to its pool when dropped.
+pub struct Allocation {
+    state: Rc<RefCell<AllocatorState>>,
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    slot: usize,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+}
+
+impl Allocation {
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn memory_type_index(&self) -> u32 {
+        self.memory_type_index
+    }
+
+    pub fn property_flags(&self) -> vk::MemoryPropertyFlags {
+        self.state.borrow().memory_properties.memory_types[self.memory_type_index as usize]
+            .property_flags
+    }
+
+    /// Pointer to the start of the allocation. The whole `VkDeviceMemory` is
+    /// mapped, since it can only be mapped once at a time; it is unmapped when
+    /// every `map` has been matched by an `unmap`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer is valid for `size()` bytes until the matching `unmap`. The device must not
+    /// access the range while the host does.
+    pub unsafe fn map(&self) -> anyhow::Result<*mut u8> {
+        if !self
+            .property_flags()
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+        {
+            anyhow::bail!("Cannot map memory which is not host visible");
+        }
+        let mut state = self.state.borrow_mut();
+        let device = state.device.clone();
+        let block = state.block(self.memory_type_index, self.slot);
+        if block.map_count == 0 {
+            block.mapped =
+                device.map_memory(self.memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())?;
+        }
+        block.map_count += 1;
+        Ok((block.mapped as *mut u8).add(self.offset as usize))
+    }
+
+    /// # Safety
+    ///
+    /// Must match an earlier successful `map`; pointers returned by it must not be used afterwards.
+    pub unsafe fn unmap(&self) {
+        let mut state = self.state.borrow_mut();
+        let device = state.device.clone();
+        let block = state.block(self.memory_type_index, self.slot);
+        block.map_count -= 1;
+        if block.map_count == 0 {
+            device.unmap_memory(self.memory);
+            block.mapped = std::ptr::null_mut();
+        }
+    }
+
+    pub fn is_coherent(&self) -> bool {
+        self.property_flags()
+            .contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+    }
+
+    /// Makes host writes to the mapped allocation visible to the device.
+    /// Does nothing on coherent memory.
+    ///
+    /// # Safety
+    ///
+    /// The allocation must be mapped.
+    pub unsafe fn flush(&self) -> anyhow::Result<()> {
+        self.flush_range(0, self.size)
+    }
+
+    /// Like `flush`, for `size` bytes at `offset` in the allocation.
+    ///
+    /// # Safety
+    ///
+    /// The allocation must be mapped and the range must lie within it.
+    pub unsafe fn flush_range(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> anyhow::Result<()> {
+        if let Some(range) = self.non_coherent_range(offset, size) {
+            let device = self.state.borrow().device.clone();
+            device.flush_mapped_memory_ranges(&[range])?;
+        }
+        Ok(())
+    }
+
+    /// Makes device writes visible to the mapped allocation. Does nothing on
+    /// coherent memory.
+    ///
+    /// # Safety
+    ///
+    /// The allocation must be mapped and the device writes must be done, e.g. after waiting for a
+    /// fence.
+    pub unsafe fn invalidate(&self) -> anyhow::Result<()> {
+        self.invalidate_range(0, self.size)
+    }
+
+    /// Like `invalidate`, for `size` bytes at `offset` in the allocation.
+    ///
+    /// # Safety
+    ///
+    /// Same as `invalidate`, and the range must lie within the allocation.
+    pub unsafe fn invalidate_range(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> anyhow::Result<()> {
+        if let Some(range) = self.non_coherent_range(offset, size) {
+            let device = self.state.borrow().device.clone();
+            device.invalidate_mapped_memory_ranges(&[range])?;
+        }
+        Ok(())
+    }
+
+    /// The range widened to `nonCoherentAtomSize`, as flushes and
+    /// invalidations require.
+    fn non_coherent_range(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Option<vk::MappedMemoryRange> {
+        if self.is_coherent() {
+            return None;
+        }
+        let mut state = self.state.borrow_mut();
+        let atom_size = state.non_coherent_atom_size.max(1);
+        let memory_size = state.block(self.memory_type_index, self.slot).size;
+        let start = (self.offset + offset) / atom_size * atom_size;
+        let end = align_up(self.offset + offset + size, atom_size);
+        Some(vk::MappedMemoryRange {
+            s_type: vk::StructureType::MAPPED_MEMORY_RANGE,
+            p_next: std::ptr::null(),
+            memory: self.memory,
+            offset: start,
+            size: if end >= memory_size {
+                vk::WHOLE_SIZE
+            } else {
+                end - start
+            },
+        })
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        let heap = state.heap_of(self.memory_type_index);
+        heap.used -= self.size;
+        heap.allocation_count -= 1;
+        let block = state.block(self.memory_type_index, self.slot);
+        block.free(self.offset, self.size);
+        if block.dedicated {
+            state.remove_block(self.memory_type_index, self.slot);
+        }
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirements(size: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::MemoryRequirements {
+        vk::MemoryRequirements {
+            size,
+            alignment,
+            memory_type_bits: !0,
+        }
+    }
+
+    /// Allocates `requirements` as `kind` with a 1 KiB granularity.
+    fn allocate(
+        free_ranges: &mut FreeRanges,
+        requirements: vk::MemoryRequirements,
+        kind: ResourceKind,
+    ) -> Option<vk::DeviceSize> {
+        let (size, alignment) = placement(&requirements, kind, 1024);
+        free_ranges.allocate(size, alignment)
+    }
+
+    #[test]
+    fn alignment_pads_the_offset() {
+        let mut free_ranges = FreeRanges::new(4096);
+        assert_eq!(free_ranges.allocate(100, 1), Some(0));
+        assert_eq!(free_ranges.allocate(100, 256), Some(256));
+        // The padding stays free for smaller alignments.
+        assert_eq!(free_ranges.0, vec![(100, 156), (356, 3740)]);
+        assert_eq!(free_ranges.allocate(50, 4), Some(100));
+    }
+
+    #[test]
+    fn allocation_splits_a_free_range() {
+        let mut free_ranges = FreeRanges::new(1024);
+        free_ranges.0 = vec![(0, 256), (512, 512)];
+        assert_eq!(free_ranges.allocate(300, 1), Some(512));
+        assert_eq!(free_ranges.0, vec![(0, 256), (812, 212)]);
+        assert_eq!(free_ranges.allocate(256, 1), Some(0));
+        assert_eq!(free_ranges.0, vec![(812, 212)]);
+        assert_eq!(free_ranges.allocate(256, 1), None);
+    }
+
+    #[test]
+    fn free_merges_with_the_left_neighbour() {
+        let mut free_ranges = FreeRanges::new(1024);
+        free_ranges.0 = vec![(0, 256), (768, 256)];
+        free_ranges.free(256, 128);
+        assert_eq!(free_ranges.0, vec![(0, 384), (768, 256)]);
+    }
+
+    #[test]
+    fn free_merges_with_the_right_neighbour() {
+        let mut free_ranges = FreeRanges::new(1024);
+        free_ranges.0 = vec![(0, 256), (768, 256)];
+        free_ranges.free(640, 128);
+        assert_eq!(free_ranges.0, vec![(0, 256), (640, 384)]);
+    }
+
+    #[test]
+    fn free_merges_with_both_neighbours() {
+        let mut free_ranges = FreeRanges::new(1024);
+        free_ranges.0 = vec![(0, 256), (768, 256)];
+        free_ranges.free(256, 512);
+        assert_eq!(free_ranges.0, vec![(0, 1024)]);
+    }
+
+    #[test]
+    fn free_keeps_a_range_between_allocations() {
+        let mut free_ranges = FreeRanges::new(1024);
+        free_ranges.0 = vec![(0, 128), (768, 256)];
+        free_ranges.free(256, 128);
+        assert_eq!(free_ranges.0, vec![(0, 128), (256, 128), (768, 256)]);
+    }
+
+    #[test]
+    fn optimal_images_get_whole_granularity_pages() {
+        let mut free_ranges = FreeRanges::new(8192);
+        let linear = requirements(100, 16);
+        assert_eq!(
+            allocate(&mut free_ranges, linear, ResourceKind::Linear),
+            Some(0)
+        );
+        let image = requirements(1000, 256);
+        assert_eq!(
+            placement(&image, ResourceKind::OptimalImage, 1024),
+            (1024, 1024)
+        );
+        assert_eq!(
+            allocate(&mut free_ranges, image, ResourceKind::OptimalImage),
+            Some(1024)
+        );
+        // Linear resources fill the first page but cannot reach the image's.
+        assert_eq!(
+            allocate(&mut free_ranges, requirements(64, 16), ResourceKind::Linear),
+            Some(112)
+        );
+        assert_eq!(
+            allocate(
+                &mut free_ranges,
+                requirements(900, 16),
+                ResourceKind::Linear
+            ),
+            Some(2048)
+        );
+    }
+}
@@ -1,156 +1,104 @@
 extern crate ash;
+extern crate sdl2;
+extern crate vkcore;
 
-use ash::version::DeviceV1_0;
-use ash::version::EntryV1_0;
 use ash::version::InstanceV1_0;
-use std::ffi::CStr;
-use std::ffi::CString;
-use std::os::raw::c_char;
+use ash::vk::Handle;
 
-#[allow(dead_code)]
-unsafe fn print_instance_layers(entry: &ash::Entry) {
-    //&dyn ash::version::EntryV1_1<Instance = ash::vk::Instance>) {
-    let instance_layers_properties = entry
-        .enumerate_instance_layer_properties()
-        .expect("Cannot find instance layer properties");
+const REPORT_USAGE: &str =
+    "usage: 000_validation_layers report [--json] [--surface] [--output <file>]";
 
-    for i in 0..instance_layers_properties.len() {
-        let layer_name: [c_char; 256] = instance_layers_properties[i].layer_name;
-        let c_str = CStr::from_ptr(layer_name.as_ptr())
-            .to_str()
-            .expect("Cannot convert instance layer");
-        println!("instance layer name = {}", c_str);
+/// `report` subcommand: dumps what every physical device supports, like `vulkaninfo`.
+/// `--surface` opens a hidden SDL window to also report surface capabilities.
+unsafe fn report(v_args: &[String]) {
+    let mut json = false;
+    let mut with_surface = false;
+    let mut output_path = None;
+    let mut args = v_args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--surface" => with_surface = true,
+            "--output" => output_path = Some(args.next().expect(REPORT_USAGE).clone()),
+            _ => panic!("Unknown argument {}\n{}", arg, REPORT_USAGE),
+        }
     }
-}
 
-#[allow(dead_code)]
-unsafe fn print_instance_extensions(entry: &ash::Entry) {
-    let instance_extensions = entry
-        .enumerate_instance_extension_properties()
-        .expect("Cannot retrieve instance extension properties");
-    println!("instance extensions: {}", instance_extensions.len());
-    for i in 0..instance_extensions.len() {
-        let extension_properties: ash::vk::ExtensionProperties = instance_extensions[i];
-        let extension_name = extension_properties.extension_name;
-        let c_str = CStr::from_ptr(extension_name.as_ptr())
-            .to_str()
-            .expect("Cannot convert instance extensions");
-        println!("instance extensions name = {}", c_str);
+    let entry = ash::Entry::new().expect("Cannot create entry");
+    let sdl_context;
+    let mut window = None;
+    let mut instance_builder =
+        vkcore::instance::InstanceBuilder::new("000_validation_layers report");
+    if with_surface {
+        sdl_context = sdl2::init().expect("Cannot init SDL");
+        let video_subsystem = sdl_context.video().expect("Cannot init SDL video");
+        let hidden_window = video_subsystem
+            .window("report", 64, 64)
+            .vulkan()
+            .hidden()
+            .build()
+            .expect("Cannot build window!");
+        instance_builder = instance_builder.extensions(
+            &hidden_window
+                .vulkan_instance_extensions()
+                .expect("Cannot get instance extensions!"),
+        );
+        window = Some(hidden_window);
     }
-}
-
-fn create_application() -> ash::vk::ApplicationInfo {
-    let application = ash::vk::ApplicationInfo {
-        s_type: ash::vk::StructureType::APPLICATION_INFO,
-        p_next: std::ptr::null(),
-        p_application_name: std::ffi::CString::new("My 1st Vulkan Rust application")
-            .expect("CString::new failed")
-            .as_ptr(),
-        application_version: ash::vk_make_version!(0, 0, 1),
-        p_engine_name: CString::new("Unreal Engine 4")
-            .expect("CString::new failed")
-            .as_ptr(),
-        engine_version: ash::vk_make_version!(0, 0, 1),
-        api_version: ash::vk_make_version!(1, 0, 0),
-    };
-    application
-}
-
-unsafe fn create_instance(
-    entry: &ash::Entry,
-    application_info: ash::vk::ApplicationInfo,
-) -> ash::Instance {
-    let layers = vec![CString::new("VK_LAYER_KHRONOS_validation").expect("Cannot create c-string")];
-    let instance_create_info = ash::vk::InstanceCreateInfo {
-        s_type: ash::vk::StructureType::INSTANCE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        p_application_info: &application_info,
-        enabled_layer_count: layers.len() as u32,
-        pp_enabled_layer_names: layers.as_ptr() as *const *const c_char,
-        enabled_extension_count: 0,
-        pp_enabled_extension_names: std::ptr::null(),
-    };
-    let instance: ash::Instance = entry
-        .create_instance(&instance_create_info, None)
+    let instance = instance_builder
+        .build(&entry)
         .expect("Instance creation error");
-    instance
-}
+    let surface = window.as_ref().map(|window| {
+        let surface_handle = window
+            .vulkan_create_surface(instance.handle().as_raw() as usize)
+            .expect("Cannot create surface");
+        vkcore::swapchain::Surface::from_raw(
+            &instance,
+            ash::vk::SurfaceKHR::from_raw(surface_handle),
+        )
+    });
 
-unsafe fn pick_up_one_gpu(
-    instance: &ash::Instance,
-) -> Result<ash::vk::PhysicalDevice, &'static str> {
-    let physical_devices = instance
-        .enumerate_physical_devices()
-        .expect("Cannot enumerate physical devices");
-    if physical_devices.is_empty() {
-        Err("Cannot get physical devices because none was found")
+    let report = vkcore::report::instance_report(&instance, surface.as_ref());
+    let text = if json {
+        report.to_pretty_string() + "\n"
     } else {
-        Ok(physical_devices[0])
-    }
-}
-
-fn pick_up_one_queue_family(
-    queue_families_properties: Vec<ash::vk::QueueFamilyProperties>,
-    flags: ash::vk::QueueFlags,
-) -> Result<usize, &'static str> {
-    for i in 0..queue_families_properties.len() {
-        if queue_families_properties[i].queue_flags & flags == flags {
-            return Ok(i);
-        }
-    }
-    Err("No suitable queue family found")
-}
-
-unsafe fn create_logical_device(
-    instance: &ash::Instance,
-    gpu: ash::vk::PhysicalDevice,
-    index_of_queue_family: usize,
-) -> ash::Device {
-    let queue_priority = 1.0_f32;
-    let device_queue_create_info = ash::vk::DeviceQueueCreateInfo {
-        s_type: ash::vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        queue_family_index: index_of_queue_family as u32,
-        queue_count: 1,
-        p_queue_priorities: &queue_priority as *const f32,
+        vkcore::report::to_text(&report)
     };
-    let device_features = instance.get_physical_device_features(gpu);
-    let device_create_info = ash::vk::DeviceCreateInfo {
-        s_type: ash::vk::StructureType::DEVICE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        queue_create_info_count: 1 as u32,
-        p_queue_create_infos: &device_queue_create_info as *const ash::vk::DeviceQueueCreateInfo,
-        enabled_layer_count: 0 as u32,
-        pp_enabled_layer_names: std::ptr::null(),
-        enabled_extension_count: 0 as u32,
-        pp_enabled_extension_names: std::ptr::null(),
-        p_enabled_features: &device_features as *const ash::vk::PhysicalDeviceFeatures,
-    };
-    let logical_device: ash::Device = instance
-        .create_device(gpu, &device_create_info, None)
-        .expect("Cannot create device");
-    logical_device
+    match output_path {
+        Some(path) => std::fs::write(&path, text).expect("Cannot write report"),
+        None => print!("{}", text),
+    }
 }
 
 fn main() {
+    let v_args: Vec<String> = std::env::args().skip(1).collect();
     unsafe {
-        let entry = ash::Entry::new().expect("Cannot create entry");
-        // print_instance_extensions(&entry);
-        // print_instance_layers(&entry);
-        let instance = create_instance(&entry, create_application());
-        let gpu = pick_up_one_gpu(&instance).expect("Cannot get physical device");
-        let queue_families_properties = instance.get_physical_device_queue_family_properties(gpu);
-        let index_of_queue_family =
-            pick_up_one_queue_family(queue_families_properties, ash::vk::QueueFlags::COMPUTE)
-                .expect("Cannot get queue family property");
-        let logical_device = create_logical_device(&instance, gpu, index_of_queue_family);
+        if v_args.first().map(String::as_str) == Some("report") {
+            report(&v_args[1..]);
+            return;
+        }
 
-        // line is commented to trigger error from validation layer
-        // logical_device.destroy_device(None);
-        
-        instance.destroy_instance(None);
+        let entry = ash::Entry::new().expect("Cannot create entry");
+        let instance =
+            match vkcore::instance::InstanceBuilder::new("My 1st Vulkan Rust application")
+                .engine_name("Unreal Engine 4")
+                .layer(vkcore::instance::VALIDATION_LAYER_NAME)
+                .build(&entry)
+            {
+                Ok(instance) => instance,
+                Err(e) => {
+                    vkcore::instance::print_instance_layers(&entry);
+                    vkcore::instance::print_instance_extensions(&entry);
+                    panic!("Instance creation error: {}", e);
+                }
+            };
+        let selected_device = vkcore::selector::DeviceSelector::new(ash::vk::QueueFlags::COMPUTE)
+            .select(&instance)
+            .expect("Cannot select physical device");
+        let gpu = selected_device.gpu;
+        let index_of_queue_family = selected_device.index_of_queue_family;
+        let _logical_device = vkcore::device::DeviceBuilder::new(gpu, index_of_queue_family)
+            .build(&instance)
+            .expect("Cannot create device");
     }
 }
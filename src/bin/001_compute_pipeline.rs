@@ -1,232 +1,27 @@
 extern crate ash;
+extern crate vkcore;
 
 use ash::version::DeviceV1_0;
-use ash::version::DeviceV1_1;
-use ash::version::EntryV1_0;
-use ash::version::InstanceV1_0;
 use ash::vk;
-use ash::vk::Device;
-use ash::vk::PhysicalDevice;
 use std::default::Default;
 use std::ffi::CString;
+use std::rc::Rc;
+use vkcore::device::Device;
 
-use ash::extensions::khr::Swapchain;
-
-use std::ffi::CStr;
-use std::fs::File;
-use std::io;
-use std::io::prelude::*;
-use std::os::raw::c_char;
-
-unsafe fn print_instance_layers(entry: &ash::Entry) {
-    let instance_layers_properties = entry
-        .enumerate_instance_layer_properties()
-        .expect("Cannot find instance layer properties");
-
-    for i in 0..instance_layers_properties.len() {
-        let layer_name: [c_char; 256] = instance_layers_properties[i].layer_name;
-        let c_str = CStr::from_ptr(layer_name.as_ptr())
-            .to_str()
-            .expect("Cannot convert instance layer");
-        println!("instance layer name = {}", c_str);
-    }
-}
-
-unsafe fn print_instance_extensions(entry: &ash::Entry) {
-    let instance_extensions = entry
-        .enumerate_instance_extension_properties()
-        .expect("Cannot retrieve instance extension properties");
-    println!("instance extensions: {}", instance_extensions.len());
-    for i in 0..instance_extensions.len() {
-        let extension_properties: ash::vk::ExtensionProperties = instance_extensions[i];
-        let extension_name = extension_properties.extension_name;
-        let c_str = CStr::from_ptr(extension_name.as_ptr())
-            .to_str()
-            .expect("Cannot convert instance extensions");
-        println!("instance xtensions name = {}", c_str);
-    }
-}
-
-fn create_application() -> vk::ApplicationInfo {
-    let application = vk::ApplicationInfo {
-        s_type: vk::StructureType::APPLICATION_INFO,
-        p_next: std::ptr::null(),
-        p_application_name: CString::new("My 1st Vulkan Rust application")
-            .expect("CString::new failed")
-            .as_ptr(),
-        application_version: ash::vk_make_version!(0, 0, 1),
-        p_engine_name: CString::new("Unreal Engine 4")
-            .expect("CString::new failed")
-            .as_ptr(),
-        engine_version: ash::vk_make_version!(0, 0, 1),
-        api_version: ash::vk_make_version!(1, 0, 0),
-    };
-    application
-}
-
-unsafe fn create_instance(
-    entry: &ash::Entry,
-    application_info: vk::ApplicationInfo,
-) -> ash::Instance {
-    let layers = vec![CString::new("VK_LAYER_KHRONOS_validation").expect("Cannot create c-string")];
-    let instance_create_info = ash::vk::InstanceCreateInfo {
-        s_type: ash::vk::StructureType::INSTANCE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        p_application_info: &application_info,
-        enabled_layer_count: layers.len() as u32,
-        pp_enabled_layer_names: layers.as_ptr() as *const *const c_char,
-        enabled_extension_count: 0,
-        pp_enabled_extension_names: std::ptr::null(),
-    };
-    let instance: ash::Instance = entry
-        .create_instance(&instance_create_info, None)
-        .expect("Instance creation error");
-    instance
-}
-
-unsafe fn pick_up_one_gpu(instance: &ash::Instance) -> Result<vk::PhysicalDevice, &'static str> {
-    let physical_devices = instance
-        .enumerate_physical_devices()
-        .expect("Cannot enumerate physical devices");
-    println!("number of physical device {}", physical_devices.len());
-    if physical_devices.is_empty() {
-        Err("Cannot get physical devices because none was found")
-    } else {
-        Ok(physical_devices[0])
-    }
-}
-
-fn pick_up_one_queue_family(
-    queue_families_properties: Vec<vk::QueueFamilyProperties>,
-    flags: ash::vk::QueueFlags,
-) -> Result<usize, &'static str> {
-    for i in 0..queue_families_properties.len() {
-        if queue_families_properties[i].queue_flags & flags == flags {
-            return Ok(i);
-        }
-    }
-    Err("No suitable queue family found")
-}
-
-unsafe fn create_logical_device(
-    instance: &ash::Instance,
-    gpu: ash::vk::PhysicalDevice,
-    index_of_queue_family: usize,
-) -> ash::Device {
-    let queue_priority = 1.0_f32;
-    let device_queue_create_info = vk::DeviceQueueCreateInfo {
-        s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        queue_family_index: index_of_queue_family as u32,
-        queue_count: 1,
-        p_queue_priorities: &queue_priority as *const f32,
-    };
-    let device_features = instance.get_physical_device_features(gpu);
-    let device_create_info = vk::DeviceCreateInfo {
-        s_type: vk::StructureType::DEVICE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        queue_create_info_count: 1 as u32,
-        p_queue_create_infos: &device_queue_create_info as *const vk::DeviceQueueCreateInfo,
-        enabled_layer_count: 0 as u32,
-        pp_enabled_layer_names: std::ptr::null(),
-        enabled_extension_count: 0 as u32,
-        pp_enabled_extension_names: std::ptr::null(),
-        p_enabled_features: &device_features as *const vk::PhysicalDeviceFeatures,
-    };
-    let logical_device: ash::Device = instance
-        .create_device(gpu, &device_create_info, None)
-        .expect("Cannot create device");
-    logical_device
-}
-
-unsafe fn get_memory_type_index(
-    flags: vk::MemoryPropertyFlags,
-    instance: &ash::Instance,
-    gpu: vk::PhysicalDevice,
-) -> Result<usize, &'static str> {
-    let memory_properties = instance.get_physical_device_memory_properties(gpu);
-    for i in 0..memory_properties.memory_types.len() {
-        let memory_type: &vk::MemoryType = &memory_properties.memory_types[i];
-        if memory_type.property_flags.contains(flags) {
-            return Ok(i);
-        }
-    }
-    return Err("Cannot find memory type!");
-}
-
-struct Buffer {
-    vk_buffer_handle: ash::vk::Buffer,
-    size: usize,
-}
-
-impl Buffer {
-    unsafe fn new(logical_device: &ash::Device, size: usize) -> Self {
-        let buffer_create_info = vk::BufferCreateInfo {
-            s_type: vk::StructureType::BUFFER_CREATE_INFO,
-            p_next: std::ptr::null(),
-            flags: vk::BufferCreateFlags::empty(),
-            size: size as u64,
-            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            queue_family_index_count: 0,
-            p_queue_family_indices: std::ptr::null(),
-        };
-        let buffer = logical_device
-            .create_buffer(&buffer_create_info, None)
-            .expect("Failed to create Vertex Buffer");
-        Buffer {
-            vk_buffer_handle: buffer,
-            size: size,
-        }
-    }
-}
-
-struct DeviceMemoryAllocator<'a> {
-    size: usize,
-    memory: vk::DeviceMemory,
-    logical_device: &'a ash::Device,
-}
-
-impl<'a> DeviceMemoryAllocator<'a> {
-    unsafe fn new(size: usize, memory_type_index: usize, logical_device: &'a ash::Device) -> Self {
-        let memory_allocate_info = vk::MemoryAllocateInfo {
-            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
-            p_next: std::ptr::null(),
-            allocation_size: size as u64,
-            memory_type_index: memory_type_index as u32,
-        };
-        let memory: vk::DeviceMemory = logical_device
-            .allocate_memory(&memory_allocate_info, None)
-            .expect("Cannot allocate device memomry!");
-        DeviceMemoryAllocator {
-            size: size,
-            memory: memory,
-            logical_device: logical_device,
-        }
-    }
-    unsafe fn bind(&self, buffer: ash::vk::Buffer, offset: usize) {
-        println!("size aaa {}", self.size);
-        self.logical_device
-            .bind_buffer_memory(buffer, self.memory, offset as u64)
-            .expect("Cannot bind memory");
-    }
-}
+// Workgroup size, specialization constant 0 of the shader.
+const WORKGROUP_SIZE: u32 = 256;
 
 unsafe fn create_command_pool(
-    logical_device: &ash::Device,
+    logical_device: &Rc<Device>,
     index_of_queue_family: usize,
-) -> ash::vk::CommandPool {
+) -> vkcore::objects::CommandPool {
     let command_pool_create_info = vk::CommandPoolCreateInfo {
         s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
         p_next: std::ptr::null(),
         flags: Default::default(),
         queue_family_index: index_of_queue_family as u32,
     };
-    logical_device
-        .create_command_pool(&command_pool_create_info, None)
+    vkcore::objects::CommandPool::new(logical_device, &command_pool_create_info)
         .expect("Cannot create command pool")
 }
 
@@ -238,7 +33,7 @@ unsafe fn allocate_command_buffers(
     let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
         s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
         p_next: std::ptr::null(),
-        command_pool: command_pool,
+        command_pool,
         level: ash::vk::CommandBufferLevel::PRIMARY,
         command_buffer_count: count,
     };
@@ -248,8 +43,8 @@ unsafe fn allocate_command_buffers(
 }
 
 unsafe fn create_descriptor_set_layout(
-    logical_device: &ash::Device,
-) -> ash::vk::DescriptorSetLayout {
+    logical_device: &Rc<Device>,
+) -> vkcore::objects::DescriptorSetLayout {
     let descriptor_set_layout_binding = vk::DescriptorSetLayoutBinding {
         binding: 5,
         descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
@@ -264,76 +59,36 @@ unsafe fn create_descriptor_set_layout(
         binding_count: 1,
         p_bindings: &descriptor_set_layout_binding as *const vk::DescriptorSetLayoutBinding,
     };
-    logical_device
-        .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+    vkcore::objects::DescriptorSetLayout::new(logical_device, &descriptor_set_layout_create_info)
         .expect("Cannot create descriptor set layout")
 }
 
-unsafe fn create_pipeline_layout(
-    logical_device: &ash::Device,
-    descriptor_set_layout: ash::vk::DescriptorSetLayout,
-) -> vk::PipelineLayout {
-    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
-        s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: vk::PipelineLayoutCreateFlags::default(),
-        set_layout_count: 1,
-        p_set_layouts: &descriptor_set_layout,
-        /*as *const DescriptorSetLayout*/
-        push_constant_range_count: 0,
-        p_push_constant_ranges: std::ptr::null(),
-        /*as *const PushConstantRange*/
-    };
-    logical_device
-        .create_pipeline_layout(&pipeline_layout_create_info, None)
-        .expect("Cannot create pipeline layout")
-}
-
 unsafe fn create_compute_pipeline(
-    logical_device: &ash::Device,
+    logical_device: &Rc<Device>,
+    pipeline_cache: &vkcore::pipeline_cache::DiskPipelineCache,
     pipeline_layout: vk::PipelineLayout,
-) -> vk::Pipeline {
-    let mut file = std::fs::File::open(
-        "shaders/001_compute_pipeline.comp.spv",
-    )
-    .expect("Something went wrong opening the shader");
-    let spirv_data =
-        ash::util::read_spv(&mut file).expect("Failed to read compute shader spv file");
-    let shader_module_create_info =
-        vk::ShaderModuleCreateInfo::builder().code(spirv_data.as_slice());
-    let shader_module = logical_device
-        .create_shader_module(&shader_module_create_info, None)
-        .expect("Cannot create shader module");
+) -> vkcore::objects::Pipeline {
+    let shader_module =
+        vkcore::shader::create_shader_module(logical_device, "shaders/001_compute_pipeline.comp")
+            .expect("Cannot create shader module");
     let shader_function_name = CString::new("main").expect("Shader function name not valid");
-    let pipeline_shader_create_info = vk::PipelineShaderStageCreateInfo {
-        s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        stage: vk::ShaderStageFlags::COMPUTE,
-        module: shader_module,
-        p_name: shader_function_name.as_ptr(),
-        p_specialization_info: std::ptr::null(),
-    };
-    let compute_pipeline_create_info = vk::ComputePipelineCreateInfo {
-        s_type: vk::StructureType::COMPUTE_PIPELINE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: vk::PipelineCreateFlags::DISABLE_OPTIMIZATION,
-        stage: pipeline_shader_create_info,
-        layout: pipeline_layout,
-        base_pipeline_handle: vk::Pipeline::null(),
-        base_pipeline_index: -1,
-    };
-    let compute_pipeline_create_infos = &[compute_pipeline_create_info];
-    logical_device
-        .create_compute_pipelines(
-            vk::PipelineCache::null(),
-            compute_pipeline_create_infos,
-            None,
-        )
-        .expect("Cannot get compute pipelines")[0]
+    let specialization_constants =
+        vkcore::pipeline::SpecializationConstants::new().set(0, WORKGROUP_SIZE);
+    pipeline_cache
+        .timed(|| {
+            vkcore::pipeline::create_compute_pipeline(
+                logical_device,
+                pipeline_cache.handle(),
+                pipeline_layout,
+                &shader_module,
+                &shader_function_name,
+                &specialization_constants,
+            )
+        })
+        .expect("Cannot get compute pipelines")
 }
 
-unsafe fn create_descriptor_pool(logical_device: &ash::Device) -> ash::vk::DescriptorPool {
+unsafe fn create_descriptor_pool(logical_device: &Rc<Device>) -> vkcore::objects::DescriptorPool {
     let descriptor_pool_size = ash::vk::DescriptorPoolSize {
         ty: ash::vk::DescriptorType::STORAGE_BUFFER,
         descriptor_count: 1,
@@ -346,10 +101,8 @@ unsafe fn create_descriptor_pool(logical_device: &ash::Device) -> ash::vk::Descr
         pool_size_count: 1,
         p_pool_sizes: &descriptor_pool_size,
     };
-    let descriptor_pool = logical_device
-        .create_descriptor_pool(&descriptor_pool_create_info, None)
-        .expect("Cannot create descriptor pool");
-    descriptor_pool
+    vkcore::objects::DescriptorPool::new(logical_device, &descriptor_pool_create_info)
+        .expect("Cannot create descriptor pool")
 }
 
 unsafe fn allocate_descriptor_set(
@@ -360,7 +113,7 @@ unsafe fn allocate_descriptor_set(
     let descriptor_set_allocate_infos = ash::vk::DescriptorSetAllocateInfo {
         s_type: ash::vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
         p_next: std::ptr::null(),
-        descriptor_pool: descriptor_pool,
+        descriptor_pool,
         descriptor_set_count: 1,
         p_set_layouts: &descriptor_set_layout,
     };
@@ -369,38 +122,73 @@ unsafe fn allocate_descriptor_set(
         .expect("Cannot allocate descriptor set")[0]
 }
 
+/// What the shader writes, computed on the CPU for `--verify`.
+fn expected_values(number_of_elements: usize) -> Vec<u32> {
+    (0..number_of_elements as u32).collect()
+}
+
 fn main() {
-    unsafe {
+    let success = unsafe {
         let entry = ash::Entry::new().expect("Cannot create entry");
-        let instance = create_instance(&entry, create_application());
+        let instance = vkcore::instance::InstanceBuilder::new("My 1st Vulkan Rust application")
+            .engine_name("Unreal Engine 4")
+            .build(&entry)
+            .expect("Instance creation error");
         // print_instance_extensions(&entry);
         // print_instance_layers(&entry);
-        let gpu = pick_up_one_gpu(&instance).expect("Cannot get physical device");
-        let queue_families_properties = instance.get_physical_device_queue_family_properties(gpu);
-        let index_of_queue_family =
-            pick_up_one_queue_family(queue_families_properties, ash::vk::QueueFlags::COMPUTE)
-                .expect("Cannot get queue family property");
-        let logical_device = create_logical_device(&instance, gpu, index_of_queue_family);
+        let selected_device = vkcore::selector::DeviceSelector::new(ash::vk::QueueFlags::COMPUTE)
+            .select(&instance)
+            .expect("Cannot select physical device");
+        let gpu = selected_device.gpu;
+        let index_of_queue_family = selected_device.index_of_queue_family;
+        let logical_device = vkcore::device::DeviceBuilder::new(gpu, index_of_queue_family)
+            .build(&instance)
+            .expect("Cannot create device");
         let number_of_elements = 1024;
-        let buffer_size_bytes = number_of_elements * std::mem::size_of::<u32>();
-        let buffer = Buffer::new(&logical_device, buffer_size_bytes);
-        let memory_type_index =
-            get_memory_type_index(vk::MemoryPropertyFlags::HOST_VISIBLE, &instance, gpu)
-                .expect("no memory type found");
-        let allocator = DeviceMemoryAllocator::new(buffer.size, memory_type_index, &logical_device);
-        allocator.bind(buffer.vk_buffer_handle, 0);
+        let allocator = vkcore::allocator::DeviceMemoryAllocator::new(&logical_device);
+        let buffer = vkcore::buffer::TypedBuffer::<u32>::new(
+            &logical_device,
+            &allocator,
+            number_of_elements,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )
+        .expect("Cannot create buffer");
         let command_pool = create_command_pool(&logical_device, index_of_queue_family);
-        let command_buffers = allocate_command_buffers(&logical_device, command_pool, 1);
+        let command_buffers = allocate_command_buffers(&logical_device, command_pool.handle(), 1);
         let dispatch_command_buffer = command_buffers[0];
         let queue = logical_device.get_device_queue(index_of_queue_family as u32, 0);
         let descriptor_set_layout = create_descriptor_set_layout(&logical_device);
-        let pipeline_layout = create_pipeline_layout(&logical_device, descriptor_set_layout);
-        let compute_pipeline = create_compute_pipeline(&logical_device, pipeline_layout);
+        let pipeline_layout = vkcore::pipeline::create_pipeline_layout(
+            &logical_device,
+            &[descriptor_set_layout.handle()],
+            &[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: std::mem::size_of::<u32>() as u32,
+            }],
+        )
+        .expect("Cannot create pipeline layout");
+        let pipeline_cache = vkcore::pipeline_cache::DiskPipelineCache::load(
+            &logical_device,
+            "001_compute_pipeline",
+            &vkcore::pipeline_cache::PipelineCacheOptions::from_args(),
+        )
+        .expect("Cannot load pipeline cache");
+        let compute_pipeline =
+            create_compute_pipeline(&logical_device, &pipeline_cache, pipeline_layout.handle());
+        println!("{}", pipeline_cache);
+        if let Err(e) = pipeline_cache.save() {
+            eprintln!("Cannot save pipeline cache: {:#}", e);
+        }
         let descriptor_pool = create_descriptor_pool(&logical_device);
-        let descriptor_set =
-            allocate_descriptor_set(&logical_device, descriptor_pool, descriptor_set_layout);
+        let descriptor_set = allocate_descriptor_set(
+            &logical_device,
+            descriptor_pool.handle(),
+            descriptor_set_layout.handle(),
+        );
         let descriptor_buffer_info = ash::vk::DescriptorBufferInfo {
-            buffer: buffer.vk_buffer_handle,
+            buffer: buffer.handle(),
             offset: 0,
             range: ash::vk::WHOLE_SIZE,
         };
@@ -417,6 +205,18 @@ fn main() {
             p_texel_buffer_view: std::ptr::null(),
         };
         logical_device.update_descriptor_sets(&[descriptor_write], &[]);
+        // A single dispatch, the window is one sample.
+        let profiler = vkcore::profiler::ProfilerOptions::from_args().map(|options| {
+            let profiler = vkcore::profiler::GpuProfiler::new(
+                &logical_device,
+                index_of_queue_family as u32,
+                1,
+                1,
+                1,
+            )
+            .expect("Cannot create profiler");
+            (profiler, options)
+        });
         let command_buffer_begin_info = ash::vk::CommandBufferBeginInfo {
             s_type: ash::vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
             p_next: std::ptr::null(),
@@ -426,20 +226,41 @@ fn main() {
         logical_device
             .begin_command_buffer(dispatch_command_buffer, &command_buffer_begin_info)
             .expect("Cannot begin command buffer");
+        let dispatch_scope = profiler.as_ref().map(|(profiler, _)| {
+            profiler.record_reset(dispatch_command_buffer, 0);
+            profiler.begin_scope(dispatch_command_buffer, 0, "dispatch")
+        });
         logical_device.cmd_bind_pipeline(
             dispatch_command_buffer,
             ash::vk::PipelineBindPoint::COMPUTE,
-            compute_pipeline,
+            compute_pipeline.handle(),
         );
         logical_device.cmd_bind_descriptor_sets(
             dispatch_command_buffer,
             ash::vk::PipelineBindPoint::COMPUTE,
-            pipeline_layout,
+            pipeline_layout.handle(),
             0,
             &[descriptor_set],
             &[],
         );
-        logical_device.cmd_dispatch(dispatch_command_buffer, 1, 1, 1);
+        // The element count is a push constant, the last group may be partial.
+        let element_count = number_of_elements as u32;
+        logical_device.cmd_push_constants(
+            dispatch_command_buffer,
+            pipeline_layout.handle(),
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            vkcore::buffer::as_bytes(&[element_count]),
+        );
+        logical_device.cmd_dispatch(
+            dispatch_command_buffer,
+            element_count.div_ceil(WORKGROUP_SIZE),
+            1,
+            1,
+        );
+        if let (Some((profiler, _)), Some(scope)) = (&profiler, dispatch_scope) {
+            profiler.end_scope(dispatch_command_buffer, scope);
+        }
         logical_device
             .end_command_buffer(dispatch_command_buffer)
             .expect("Cannot end command buffer");
@@ -454,24 +275,51 @@ fn main() {
             signal_semaphore_count: 0,
             p_signal_semaphores: std::ptr::null(),
         };
+        let fence_create_info = vk::FenceCreateInfo {
+            s_type: vk::StructureType::FENCE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+        };
+        let fence = vkcore::objects::Fence::new(&logical_device, &fence_create_info)
+            .expect("Cannot create fence");
         logical_device
-            .queue_submit(queue, &[queue_submit_info], ash::vk::Fence::null())
+            .queue_submit(queue, &[queue_submit_info], fence.handle())
             .expect("Cannot submit queue");
         logical_device
-            .queue_wait_idle(queue)
-            .expect("Cannot wait for queue");
-        let p_memory = logical_device
-            .map_memory(
-                allocator.memory,
-                0,
-                ash::vk::WHOLE_SIZE,
-                ash::vk::MemoryMapFlags::empty(),
-            )
-            .expect("Cannot map memory");
-        println!("pointer value = {}", p_memory as u64);
-        let gpu_array = std::slice::from_raw_parts(p_memory as *const u32, number_of_elements);
-        for a in gpu_array {
-            println!("value is {}", a);
+            .wait_for_fences(&[fence.handle()], true, u64::MAX)
+            .expect("Cannot wait for fence");
+        if let Some((profiler, options)) = &profiler {
+            profiler.collect(0).expect("Cannot read timestamps");
+            print!("{}", profiler);
+            if let Some(path) = &options.trace_path {
+                profiler
+                    .write_chrome_trace(path)
+                    .expect("Cannot write trace");
+            }
+        }
+        let gpu_array = buffer.map_read(&fence).expect("Cannot map memory");
+        match vkcore::verify::VerifyOptions::from_args() {
+            Some(options) => {
+                let comparison = vkcore::verify::compare(
+                    "buffer",
+                    &expected_values(number_of_elements),
+                    &gpu_array,
+                    &options,
+                );
+                print!("{}", comparison);
+                comparison.is_ok()
+            }
+            None => {
+                for a in gpu_array.iter() {
+                    println!("value is {}", a);
+                }
+                print!("{}", allocator.stats());
+                true
+            }
         }
+    };
+    // Exit once every Vulkan object has been destroyed.
+    if !success {
+        std::process::exit(1);
     }
 }
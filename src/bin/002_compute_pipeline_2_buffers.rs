@@ -1,232 +1,29 @@
 extern crate ash;
+extern crate vkcore;
 
 use ash::version::DeviceV1_0;
-use ash::version::EntryV1_0;
-use ash::version::InstanceV1_0;
 use ash::vk;
 use std::default::Default;
 use std::ffi::CString;
+use std::rc::Rc;
+use vkcore::buffer::Pod;
+use vkcore::buffer::TypedBuffer;
+use vkcore::device::Device;
 
-use std::ffi::CStr;
-use std::os::raw::c_char;
-
-#[allow(dead_code)]
-unsafe fn print_instance_layers(entry: &ash::Entry) {
-    let instance_layers_properties = entry
-        .enumerate_instance_layer_properties()
-        .expect("Cannot find instance layer properties");
-
-    for i in 0..instance_layers_properties.len() {
-        let layer_name: [c_char; 256] = instance_layers_properties[i].layer_name;
-        let c_str = CStr::from_ptr(layer_name.as_ptr())
-            .to_str()
-            .expect("Cannot convert instance layer");
-        println!("instance layer name = {}", c_str);
-    }
-}
-
-#[allow(dead_code)]
-unsafe fn print_instance_extensions(entry: &ash::Entry) {
-    let instance_extensions = entry
-        .enumerate_instance_extension_properties()
-        .expect("Cannot retrieve instance extension properties");
-    println!("instance extensions: {}", instance_extensions.len());
-    for i in 0..instance_extensions.len() {
-        let extension_properties: ash::vk::ExtensionProperties = instance_extensions[i];
-        let extension_name = extension_properties.extension_name;
-        let c_str = CStr::from_ptr(extension_name.as_ptr())
-            .to_str()
-            .expect("Cannot convert instance extensions");
-        println!("instance xtensions name = {}", c_str);
-    }
-}
-
-fn create_application() -> vk::ApplicationInfo {
-    let application = vk::ApplicationInfo {
-        s_type: vk::StructureType::APPLICATION_INFO,
-        p_next: std::ptr::null(),
-        p_application_name: CString::new("My 1st Vulkan Rust application")
-            .expect("CString::new failed")
-            .as_ptr(),
-        application_version: ash::vk_make_version!(0, 0, 1),
-        p_engine_name: CString::new("Unreal Engine 4")
-            .expect("CString::new failed")
-            .as_ptr(),
-        engine_version: ash::vk_make_version!(0, 0, 1),
-        api_version: ash::vk_make_version!(1, 0, 0),
-    };
-    application
-}
-
-unsafe fn create_instance(
-    entry: &ash::Entry,
-    application_info: vk::ApplicationInfo,
-) -> ash::Instance {
-    let layers = vec![CString::new("VK_LAYER_KHRONOS_validation").expect("Cannot create c-string")];
-    let instance_create_info = ash::vk::InstanceCreateInfo {
-        s_type: ash::vk::StructureType::INSTANCE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        p_application_info: &application_info,
-        enabled_layer_count: layers.len() as u32,
-        pp_enabled_layer_names: layers.as_ptr() as *const *const c_char,
-        enabled_extension_count: 0,
-        pp_enabled_extension_names: std::ptr::null(),
-    };
-    let instance: ash::Instance = entry
-        .create_instance(&instance_create_info, None)
-        .expect("Instance creation error");
-    instance
-}
-
-unsafe fn pick_up_one_gpu(instance: &ash::Instance) -> Result<vk::PhysicalDevice, &'static str> {
-    let physical_devices = instance
-        .enumerate_physical_devices()
-        .expect("Cannot enumerate physical devices");
-    println!("number of physical device {}", physical_devices.len());
-    if physical_devices.is_empty() {
-        Err("Cannot get physical devices because none was found")
-    } else {
-        Ok(physical_devices[0])
-    }
-}
-
-fn pick_up_one_queue_family(
-    queue_families_properties: Vec<vk::QueueFamilyProperties>,
-    flags: ash::vk::QueueFlags,
-) -> Result<usize, &'static str> {
-    for i in 0..queue_families_properties.len() {
-        if queue_families_properties[i].queue_flags & flags == flags {
-            return Ok(i);
-        }
-    }
-    Err("No suitable queue family found")
-}
-
-unsafe fn create_logical_device(
-    instance: &ash::Instance,
-    gpu: ash::vk::PhysicalDevice,
-    index_of_queue_family: usize,
-) -> ash::Device {
-    let queue_priority = 1.0_f32;
-    let device_queue_create_info = vk::DeviceQueueCreateInfo {
-        s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        queue_family_index: index_of_queue_family as u32,
-        queue_count: 1,
-        p_queue_priorities: &queue_priority as *const f32,
-    };
-    let device_features = instance.get_physical_device_features(gpu);
-    let device_create_info = vk::DeviceCreateInfo {
-        s_type: vk::StructureType::DEVICE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        queue_create_info_count: 1 as u32,
-        p_queue_create_infos: &device_queue_create_info as *const vk::DeviceQueueCreateInfo,
-        enabled_layer_count: 0 as u32,
-        pp_enabled_layer_names: std::ptr::null(),
-        enabled_extension_count: 0 as u32,
-        pp_enabled_extension_names: std::ptr::null(),
-        p_enabled_features: &device_features as *const vk::PhysicalDeviceFeatures,
-    };
-    let logical_device: ash::Device = instance
-        .create_device(gpu, &device_create_info, None)
-        .expect("Cannot create device");
-    logical_device
-}
-
-unsafe fn get_memory_type_index(
-    flags: vk::MemoryPropertyFlags,
-    instance: &ash::Instance,
-    gpu: vk::PhysicalDevice,
-) -> Result<usize, &'static str> {
-    let memory_properties = instance.get_physical_device_memory_properties(gpu);
-    for i in 0..memory_properties.memory_types.len() {
-        let memory_type: &vk::MemoryType = &memory_properties.memory_types[i];
-        if memory_type.property_flags.contains(flags) {
-            return Ok(i);
-        }
-    }
-    return Err("Cannot find memory type!");
-}
-
-struct Buffer<T> {
-    vk_handle: ash::vk::Buffer,
-    size_in_bytes: usize,
-    phantom: std::marker::PhantomData<T>,
-}
-
-impl<T> Buffer<T> {
-    unsafe fn new(logical_device: &ash::Device, number_of_elements: usize) -> Self {
-        let size_in_bytes = number_of_elements * std::mem::size_of::<T>();
-        let buffer_create_info = vk::BufferCreateInfo {
-            s_type: vk::StructureType::BUFFER_CREATE_INFO,
-            p_next: std::ptr::null(),
-            flags: vk::BufferCreateFlags::empty(),
-            size: size_in_bytes as u64,
-            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            queue_family_index_count: 0,
-            p_queue_family_indices: std::ptr::null(),
-        };
-        let buffer = logical_device
-            .create_buffer(&buffer_create_info, None)
-            .expect("Failed to create Vertex Buffer");
-        Buffer {
-            vk_handle: buffer,
-            size_in_bytes: size_in_bytes,
-            phantom: std::marker::PhantomData,
-        }
-    }
-
-    fn number_of_elements(&self) -> usize {
-        return self.size_in_bytes / std::mem::size_of::<T>();
-    }
-}
-
-struct DeviceMemoryAllocator<'a> {
-    size: usize,
-    memory: vk::DeviceMemory,
-    logical_device: &'a ash::Device,
-}
-
-impl<'a> DeviceMemoryAllocator<'a> {
-    unsafe fn new(size: usize, memory_type_index: usize, logical_device: &'a ash::Device) -> Self {
-        let memory_allocate_info = vk::MemoryAllocateInfo {
-            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
-            p_next: std::ptr::null(),
-            allocation_size: size as u64,
-            memory_type_index: memory_type_index as u32,
-        };
-        let memory: vk::DeviceMemory = logical_device
-            .allocate_memory(&memory_allocate_info, None)
-            .expect("Cannot allocate device memomry!");
-        DeviceMemoryAllocator {
-            size: size,
-            memory: memory,
-            logical_device: logical_device,
-        }
-    }
-    unsafe fn bind<T>(&self, buffer: &Buffer<T>, offset: usize) {
-        self.logical_device
-            .bind_buffer_memory(buffer.vk_handle, self.memory, offset as u64)
-            .expect("Cannot bind memory");
-    }
-}
+// Workgroup size, specialization constant 0 of the shader.
+const WORKGROUP_SIZE: u32 = 256;
 
 unsafe fn create_command_pool(
-    logical_device: &ash::Device,
+    logical_device: &Rc<Device>,
     index_of_queue_family: usize,
-) -> ash::vk::CommandPool {
+) -> vkcore::objects::CommandPool {
     let command_pool_create_info = vk::CommandPoolCreateInfo {
         s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
         p_next: std::ptr::null(),
         flags: Default::default(),
         queue_family_index: index_of_queue_family as u32,
     };
-    logical_device
-        .create_command_pool(&command_pool_create_info, None)
+    vkcore::objects::CommandPool::new(logical_device, &command_pool_create_info)
         .expect("Cannot create command pool")
 }
 
@@ -238,7 +35,7 @@ unsafe fn allocate_command_buffers(
     let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
         s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
         p_next: std::ptr::null(),
-        command_pool: command_pool,
+        command_pool,
         level: ash::vk::CommandBufferLevel::PRIMARY,
         command_buffer_count: count,
     };
@@ -248,9 +45,9 @@ unsafe fn allocate_command_buffers(
 }
 
 unsafe fn create_descriptor_set_layouts(
-    logical_device: &ash::Device,
-) -> Vec<ash::vk::DescriptorSetLayout> {
-    let descriptor_set_layout_bindings = vec![
+    logical_device: &Rc<Device>,
+) -> Vec<vkcore::objects::DescriptorSetLayout> {
+    let descriptor_set_layout_bindings = [
         vk::DescriptorSetLayoutBinding {
             binding: 5,
             descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
@@ -266,7 +63,7 @@ unsafe fn create_descriptor_set_layouts(
             p_immutable_samplers: std::ptr::null(),
         },
     ];
-    let descriptor_set_layout_create_infos = vec![
+    let descriptor_set_layout_create_infos = [
         vk::DescriptorSetLayoutCreateInfo {
             s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
             p_next: std::ptr::null(),
@@ -283,78 +80,47 @@ unsafe fn create_descriptor_set_layouts(
         },
     ];
     vec![
-        logical_device
-            .create_descriptor_set_layout(&descriptor_set_layout_create_infos[0], None)
-            .expect("Cannot create descriptor set layout 1"),
-        logical_device
-            .create_descriptor_set_layout(&descriptor_set_layout_create_infos[1], None)
-            .expect("Cannot create descriptor set layout 2"),
+        vkcore::objects::DescriptorSetLayout::new(
+            logical_device,
+            &descriptor_set_layout_create_infos[0],
+        )
+        .expect("Cannot create descriptor set layout 1"),
+        vkcore::objects::DescriptorSetLayout::new(
+            logical_device,
+            &descriptor_set_layout_create_infos[1],
+        )
+        .expect("Cannot create descriptor set layout 2"),
     ]
 }
 
-unsafe fn create_pipeline_layout(
-    logical_device: &ash::Device,
-    descriptor_set_layouts: &[ash::vk::DescriptorSetLayout],
-) -> vk::PipelineLayout {
-    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
-        s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: vk::PipelineLayoutCreateFlags::default(),
-        set_layout_count: descriptor_set_layouts.len() as u32,
-        p_set_layouts: &descriptor_set_layouts[0],
-        push_constant_range_count: 0,
-        p_push_constant_ranges: std::ptr::null(),
-    };
-    logical_device
-        .create_pipeline_layout(&pipeline_layout_create_info, None)
-        .expect("Cannot create pipeline layout")
-}
-
 unsafe fn create_compute_pipeline(
-    logical_device: &ash::Device,
+    logical_device: &Rc<Device>,
+    pipeline_cache: &vkcore::pipeline_cache::DiskPipelineCache,
     pipeline_layout: vk::PipelineLayout,
-) -> vk::Pipeline {
-    let mut file = std::fs::File::open(
-        "shaders/002_compute_pipeline_2_buffers.comp.spv",
+) -> vkcore::objects::Pipeline {
+    let shader_module = vkcore::shader::create_shader_module(
+        logical_device,
+        "shaders/002_compute_pipeline_2_buffers.comp",
     )
-    .expect("Something went wrong opening the shader");
-    let spirv_data =
-        ash::util::read_spv(&mut file).expect("Failed to read compute shader spv file");
-    let shader_module_create_info =
-        vk::ShaderModuleCreateInfo::builder().code(spirv_data.as_slice());
-    let shader_module = logical_device
-        .create_shader_module(&shader_module_create_info, None)
-        .expect("Cannot create shader module");
+    .expect("Cannot create shader module");
     let shader_function_name = CString::new("main").expect("Shader function name not valid");
-    let pipeline_shader_create_info = vk::PipelineShaderStageCreateInfo {
-        s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        stage: vk::ShaderStageFlags::COMPUTE,
-        module: shader_module,
-        p_name: shader_function_name.as_ptr(),
-        p_specialization_info: std::ptr::null(),
-    };
-    let compute_pipeline_create_info = vk::ComputePipelineCreateInfo {
-        s_type: vk::StructureType::COMPUTE_PIPELINE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: vk::PipelineCreateFlags::DISABLE_OPTIMIZATION,
-        stage: pipeline_shader_create_info,
-        layout: pipeline_layout,
-        base_pipeline_handle: vk::Pipeline::null(),
-        base_pipeline_index: -1,
-    };
-    let compute_pipeline_create_infos = &[compute_pipeline_create_info];
-    logical_device
-        .create_compute_pipelines(
-            vk::PipelineCache::null(),
-            compute_pipeline_create_infos,
-            None,
-        )
-        .expect("Cannot get compute pipelines")[0]
+    let specialization_constants =
+        vkcore::pipeline::SpecializationConstants::new().set(0, WORKGROUP_SIZE);
+    pipeline_cache
+        .timed(|| {
+            vkcore::pipeline::create_compute_pipeline(
+                logical_device,
+                pipeline_cache.handle(),
+                pipeline_layout,
+                &shader_module,
+                &shader_function_name,
+                &specialization_constants,
+            )
+        })
+        .expect("Cannot get compute pipelines")
 }
 
-unsafe fn create_descriptor_pool(logical_device: &ash::Device) -> ash::vk::DescriptorPool {
+unsafe fn create_descriptor_pool(logical_device: &Rc<Device>) -> vkcore::objects::DescriptorPool {
     let descriptor_pool_size = ash::vk::DescriptorPoolSize {
         ty: ash::vk::DescriptorType::STORAGE_BUFFER,
         descriptor_count: 2,
@@ -367,10 +133,8 @@ unsafe fn create_descriptor_pool(logical_device: &ash::Device) -> ash::vk::Descr
         pool_size_count: 1,
         p_pool_sizes: &descriptor_pool_size,
     };
-    let descriptor_pool = logical_device
-        .create_descriptor_pool(&descriptor_pool_create_info, None)
-        .expect("Cannot create descriptor pool");
-    descriptor_pool
+    vkcore::objects::DescriptorPool::new(logical_device, &descriptor_pool_create_info)
+        .expect("Cannot create descriptor pool")
 }
 
 unsafe fn allocate_descriptor_set(
@@ -381,7 +145,7 @@ unsafe fn allocate_descriptor_set(
     let descriptor_set_allocate_infos = ash::vk::DescriptorSetAllocateInfo {
         s_type: ash::vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
         p_next: std::ptr::null(),
-        descriptor_pool: descriptor_pool,
+        descriptor_pool,
         descriptor_set_count: 1,
         p_set_layouts: &descriptor_set_layout,
     };
@@ -390,74 +154,110 @@ unsafe fn allocate_descriptor_set(
         .expect("Cannot allocate descriptor set")[0]
 }
 
-unsafe fn print_buffer_content<T>(
-    logical_device: &ash::Device,
-    device_memory: ash::vk::DeviceMemory,
-    offset: usize,
-    buffer: &Buffer<T>,
+fn print_buffer_content<T: Pod + std::fmt::Display>(
+    buffer: &TypedBuffer<T>,
+    fence: &vkcore::objects::Fence,
 ) {
-    let p_memory = logical_device
-        .map_memory(
-            device_memory,
-            offset as u64,
-            buffer.size_in_bytes as u64,
-            ash::vk::MemoryMapFlags::empty(),
-        )
-        .expect("Cannot map memory");
-    let gpu_array = std::slice::from_raw_parts(p_memory as *const u32, buffer.number_of_elements());
-    println!("n = {}", buffer.number_of_elements());
-    for a in gpu_array {
+    let gpu_array = buffer.map_read(fence).expect("Cannot map memory");
+    println!("n = {}", buffer.len());
+    for a in gpu_array.iter() {
         println!("value is {}", a);
     }
-    logical_device.unmap_memory(device_memory);
+}
+
+/// What the shader writes in both buffers, computed on the CPU for `--verify`.
+fn expected_values(number_of_pairs: usize) -> (Vec<u32>, Vec<u32>) {
+    let values1 = (0..number_of_pairs as u32).collect();
+    let values2 = (0..number_of_pairs as u32)
+        .flat_map(|index| [index, index * 1000])
+        .collect();
+    (values1, values2)
 }
 
 fn main() {
-    unsafe {
+    let success = unsafe {
         let entry = ash::Entry::new().expect("Cannot create entry");
-        let instance = create_instance(&entry, create_application());
+        let instance = vkcore::instance::InstanceBuilder::new("My 1st Vulkan Rust application")
+            .engine_name("Unreal Engine 4")
+            .build(&entry)
+            .expect("Instance creation error");
         // print_instance_extensions(&entry);
         // print_instance_layers(&entry);
-        let gpu = pick_up_one_gpu(&instance).expect("Cannot get physical device");
-        let queue_families_properties = instance.get_physical_device_queue_family_properties(gpu);
-        let index_of_queue_family =
-            pick_up_one_queue_family(queue_families_properties, ash::vk::QueueFlags::COMPUTE)
-                .expect("Cannot get queue family property");
-        let logical_device = create_logical_device(&instance, gpu, index_of_queue_family);
-        let buffer1 = Buffer::<u32>::new(&logical_device, 128);
-        let buffer2 = Buffer::<u32>::new(&logical_device, 256);
-        let memory_type_index =
-            get_memory_type_index(vk::MemoryPropertyFlags::HOST_VISIBLE, &instance, gpu)
-                .expect("no memory type found");
-        let allocator = DeviceMemoryAllocator::new(
-            buffer1.size_in_bytes + buffer2.size_in_bytes,
-            memory_type_index,
+        let selected_device = vkcore::selector::DeviceSelector::new(ash::vk::QueueFlags::COMPUTE)
+            .select(&instance)
+            .expect("Cannot select physical device");
+        let gpu = selected_device.gpu;
+        let index_of_queue_family = selected_device.index_of_queue_family;
+        let logical_device = vkcore::device::DeviceBuilder::new(gpu, index_of_queue_family)
+            .build(&instance)
+            .expect("Cannot create device");
+        let allocator = vkcore::allocator::DeviceMemoryAllocator::new(&logical_device);
+        // The 2nd buffer holds a pair of values per element of the 1st one.
+        let number_of_pairs = 128;
+        let buffer1 = TypedBuffer::<u32>::new(
             &logical_device,
-        );
-        allocator.bind(&buffer1, 0);
-        allocator.bind(&buffer2, buffer1.size_in_bytes);
+            &allocator,
+            number_of_pairs,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )
+        .expect("Cannot create 1st buffer");
+        let buffer2 = TypedBuffer::<u32>::new(
+            &logical_device,
+            &allocator,
+            number_of_pairs * 2,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )
+        .expect("Cannot create 2nd buffer");
         let command_pool = create_command_pool(&logical_device, index_of_queue_family);
-        let command_buffers = allocate_command_buffers(&logical_device, command_pool, 1);
+        let command_buffers = allocate_command_buffers(&logical_device, command_pool.handle(), 1);
         let dispatch_command_buffer = command_buffers[0];
         let queue = logical_device.get_device_queue(index_of_queue_family as u32, 0);
         let descriptor_set_layouts = create_descriptor_set_layouts(&logical_device);
-        let descriptor_set_layout1 = descriptor_set_layouts[0];
-        let descriptor_set_layout2 = descriptor_set_layouts[1];
-        let pipeline_layout = create_pipeline_layout(&logical_device, &descriptor_set_layouts);
-        let compute_pipeline = create_compute_pipeline(&logical_device, pipeline_layout);
+        let descriptor_set_layout1 = descriptor_set_layouts[0].handle();
+        let descriptor_set_layout2 = descriptor_set_layouts[1].handle();
+        let pipeline_layout = vkcore::pipeline::create_pipeline_layout(
+            &logical_device,
+            &[descriptor_set_layout1, descriptor_set_layout2],
+            &[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: std::mem::size_of::<u32>() as u32,
+            }],
+        )
+        .expect("Cannot create pipeline layout");
+        let pipeline_cache = vkcore::pipeline_cache::DiskPipelineCache::load(
+            &logical_device,
+            "002_compute_pipeline_2_buffers",
+            &vkcore::pipeline_cache::PipelineCacheOptions::from_args(),
+        )
+        .expect("Cannot load pipeline cache");
+        let compute_pipeline =
+            create_compute_pipeline(&logical_device, &pipeline_cache, pipeline_layout.handle());
+        println!("{}", pipeline_cache);
+        if let Err(e) = pipeline_cache.save() {
+            eprintln!("Cannot save pipeline cache: {:#}", e);
+        }
         let descriptor_pool = create_descriptor_pool(&logical_device);
-        let descriptor_set1 =
-            allocate_descriptor_set(&logical_device, descriptor_pool, descriptor_set_layout1);
-        let descriptor_set2 =
-            allocate_descriptor_set(&logical_device, descriptor_pool, descriptor_set_layout2);
-        let descriptor_buffer_infos = vec![
+        let descriptor_set1 = allocate_descriptor_set(
+            &logical_device,
+            descriptor_pool.handle(),
+            descriptor_set_layout1,
+        );
+        let descriptor_set2 = allocate_descriptor_set(
+            &logical_device,
+            descriptor_pool.handle(),
+            descriptor_set_layout2,
+        );
+        let descriptor_buffer_infos = [
             ash::vk::DescriptorBufferInfo {
-                buffer: buffer1.vk_handle,
+                buffer: buffer1.handle(),
                 offset: 0,
                 range: ash::vk::WHOLE_SIZE,
             },
             ash::vk::DescriptorBufferInfo {
-                buffer: buffer2.vk_handle,
+                buffer: buffer2.handle(),
                 offset: 0,
                 range: ash::vk::WHOLE_SIZE,
             },
@@ -501,17 +301,31 @@ fn main() {
         logical_device.cmd_bind_pipeline(
             dispatch_command_buffer,
             ash::vk::PipelineBindPoint::COMPUTE,
-            compute_pipeline,
+            compute_pipeline.handle(),
         );
         logical_device.cmd_bind_descriptor_sets(
             dispatch_command_buffer,
             ash::vk::PipelineBindPoint::COMPUTE,
-            pipeline_layout,
+            pipeline_layout.handle(),
             0,
             &[descriptor_set1, descriptor_set2],
             &[],
         );
-        logical_device.cmd_dispatch(dispatch_command_buffer, 1, 1, 1);
+        // The element count is a push constant, the last group may be partial.
+        let element_count = number_of_pairs as u32;
+        logical_device.cmd_push_constants(
+            dispatch_command_buffer,
+            pipeline_layout.handle(),
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            vkcore::buffer::as_bytes(&[element_count]),
+        );
+        logical_device.cmd_dispatch(
+            dispatch_command_buffer,
+            element_count.div_ceil(WORKGROUP_SIZE),
+            1,
+            1,
+        );
         logical_device
             .end_command_buffer(dispatch_command_buffer)
             .expect("Cannot end command buffer");
@@ -526,20 +340,49 @@ fn main() {
             signal_semaphore_count: 0,
             p_signal_semaphores: std::ptr::null(),
         };
+        let fence_create_info = vk::FenceCreateInfo {
+            s_type: vk::StructureType::FENCE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+        };
+        let fence = vkcore::objects::Fence::new(&logical_device, &fence_create_info)
+            .expect("Cannot create fence");
         logical_device
-            .queue_submit(queue, &[queue_submit_info], ash::vk::Fence::null())
+            .queue_submit(queue, &[queue_submit_info], fence.handle())
             .expect("Cannot submit queue");
         logical_device
-            .queue_wait_idle(queue)
-            .expect("Cannot wait for queue");
-        println!("****** 1st buffer content");
-        print_buffer_content(&logical_device, allocator.memory, 0, &buffer1);
-        println!("****** 2nd  buffer content");
-        print_buffer_content(
-            &logical_device,
-            allocator.memory,
-            buffer1.size_in_bytes,
-            &buffer2,
-        );
+            .wait_for_fences(&[fence.handle()], true, u64::MAX)
+            .expect("Cannot wait for fence");
+        match vkcore::verify::VerifyOptions::from_args() {
+            Some(options) => {
+                let (expected1, expected2) = expected_values(number_of_pairs);
+                let comparison1 = vkcore::verify::compare(
+                    "1st buffer",
+                    &expected1,
+                    &buffer1.map_read(&fence).expect("Cannot map memory"),
+                    &options,
+                );
+                let comparison2 = vkcore::verify::compare(
+                    "2nd buffer",
+                    &expected2,
+                    &buffer2.map_read(&fence).expect("Cannot map memory"),
+                    &options,
+                );
+                print!("{}{}", comparison1, comparison2);
+                comparison1.is_ok() && comparison2.is_ok()
+            }
+            None => {
+                println!("****** 1st buffer content");
+                print_buffer_content(&buffer1, &fence);
+                println!("****** 2nd  buffer content");
+                print_buffer_content(&buffer2, &fence);
+                print!("{}", allocator.stats());
+                true
+            }
+        }
+    };
+    // Exit once every Vulkan object has been destroyed.
+    if !success {
+        std::process::exit(1);
     }
 }
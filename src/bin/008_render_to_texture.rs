@@ -3,8 +3,10 @@ extern crate core;
 extern crate nalgebra_glm as glm;
 extern crate num;
 extern crate sdl2;
+extern crate vkcore;
 
 use sdl2::event::Event;
+use sdl2::event::WindowEvent;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use std::time::Duration;
@@ -14,167 +16,33 @@ use std::ffi::CString;
 use core::convert::Into;
 
 use ash::version::DeviceV1_0;
-use ash::version::EntryV1_0;
 use ash::version::InstanceV1_0;
 use ash::vk::Handle;
+use vkcore::reflection::VertexLayout;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 struct MyPointData {
     position: glm::Vec3,
     color: glm::Vec3,
 }
 
+vkcore::vertex_layout!(MyPointData { position, color });
+
+unsafe impl vkcore::buffer::Pod for MyPointData {}
+
 #[repr(C)]
+#[derive(Clone, Copy)]
 struct MyUniformBuffer {
     m_model: glm::Mat4,
     m_view: glm::Mat4,
     m_projection: glm::Mat4,
 }
 
-unsafe fn create_instance(entry: &ash::Entry, v_extensions: Vec<&str>) -> ash::Instance {
-    let v_layers =
-        vec![CString::new("VK_LAYER_KHRONOS_validation").expect("Cannot validation layer name")];
-    let application_name = CString::new("003_swapchain").expect("Cannot create application name");
-    let engine_name = CString::new("Not Unreal Engine 4").expect("Cannot create engine name");
-    let application_info = ash::vk::ApplicationInfo {
-        s_type: ash::vk::StructureType::APPLICATION_INFO,
-        p_next: std::ptr::null(),
-        p_application_name: application_name.as_ptr(),
-        application_version: ash::vk_make_version!(1, 0, 0),
-        p_engine_name: engine_name.as_ptr(),
-        engine_version: ash::vk_make_version!(0, 0, 1),
-        api_version: ash::vk_make_version!(1, 0, 0),
-    };
-    let v_extensions_c: Vec<*const u8> = v_extensions.iter().map(|ss| ss.as_ptr()).collect();
-    let instance_create_info = ash::vk::InstanceCreateInfo {
-        s_type: ash::vk::StructureType::INSTANCE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        p_application_info: &application_info,
-        enabled_layer_count: v_layers.len() as u32,
-        pp_enabled_layer_names: v_layers.as_ptr() as *const *const i8,
-        enabled_extension_count: v_extensions_c.len() as u32,
-        pp_enabled_extension_names: v_extensions_c.as_ptr() as *const *const i8,
-    };
-    entry
-        .create_instance(&instance_create_info, None)
-        .expect("Cannot create instance")
-}
+unsafe impl vkcore::buffer::Pod for MyUniformBuffer {}
 
-unsafe fn pick_up_one_gpu(instance: &ash::Instance) -> Option<ash::vk::PhysicalDevice> {
-    match instance.enumerate_physical_devices() {
-        Ok(ref gpus) if gpus.len() > 0 => Some(gpus[0]),
-        Ok(_) => None,
-        Err(_e) => None,
-    }
-}
-
-unsafe fn lookup_queue_family_index(
-    instance: &ash::Instance,
-    gpu: &ash::vk::PhysicalDevice,
-) -> Result<usize, &'static str> {
-    let queue_family_properties = instance.get_physical_device_queue_family_properties(*gpu);
-    for i in 0..queue_family_properties.len() {
-        if queue_family_properties[i]
-            .queue_flags
-            .contains(ash::vk::QueueFlags::GRAPHICS)
-        {
-            return Ok(i);
-        }
-    }
-    Err("Queue family not found")
-}
-
-unsafe fn create_logical_device(
-    instance: &ash::Instance,
-    gpu: &ash::vk::PhysicalDevice,
-    index_of_queue_family: usize,
-) -> Result<ash::Device, ash::vk::Result> {
-    let priority = 1.0_f32;
-    let queue_create_info = ash::vk::DeviceQueueCreateInfo {
-        s_type: ash::vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        queue_family_index: index_of_queue_family as u32,
-        queue_count: 1,
-        p_queue_priorities: &priority,
-    };
-
-    let mut v_extensions = Vec::new();
-    v_extensions.push(ash::extensions::khr::Swapchain::name());
-    let v_extensions_c = v_extensions.iter().map(|e| e.as_ptr() as *const i8);
-    let device_create_info = ash::vk::DeviceCreateInfo {
-        s_type: ash::vk::StructureType::DEVICE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: Default::default(),
-        queue_create_info_count: 1,
-        p_queue_create_infos: &queue_create_info,
-        enabled_layer_count: 0,
-        pp_enabled_layer_names: std::ptr::null(),
-        enabled_extension_count: v_extensions_c.len() as u32,
-        pp_enabled_extension_names: v_extensions.as_ptr() as *const *const i8,
-        p_enabled_features: std::ptr::null(),
-    };
-    instance.create_device(*gpu, &device_create_info, None)
-}
-
-unsafe fn create_shader_module(
-    logical_device: &ash::Device,
-    shaderPath: &str,
-) -> ash::vk::ShaderModule {
-    let mut shader_files =
-        std::fs::File::open(shaderPath).expect("Something went wrong when opening shader");
-    let shader_instructions =
-        ash::util::read_spv(&mut shader_files).expect("Failed to read shader spv file");
-    let shader_module_create_infos =
-        ash::vk::ShaderModuleCreateInfo::builder().code(shader_instructions.as_slice());
-    logical_device
-        .create_shader_module(&shader_module_create_infos, None)
-        .expect("Cannot create shader module")
-}
-
-unsafe fn search_physical_device_memory_type(
-    instance: &ash::Instance,
-    gpu: &ash::vk::PhysicalDevice,
-    requirements: &ash::vk::MemoryRequirements,
-    type_to_find: ash::vk::MemoryPropertyFlags,
-) -> Result<usize, &'static str> {
-    let memory_properties = instance.get_physical_device_memory_properties(*gpu);
-    for (index, memory_type) in memory_properties.memory_types.iter().enumerate() {
-        if requirements.memory_type_bits & (1 << index) > 0
-            && memory_type.property_flags.contains(type_to_find)
-        {
-            return Ok(index);
-        }
-    }
-    Err("Cannot find device memory type")
-}
-
-fn search_format(
-    v_surface_formats: &Vec<ash::vk::SurfaceFormatKHR>,
-) -> Result<&ash::vk::SurfaceFormatKHR, &'static str> {
-    for format in v_surface_formats {
-        if format.format == ash::vk::Format::B8G8R8A8_UNORM
-            && format.color_space == ash::vk::ColorSpaceKHR::SRGB_NONLINEAR
-        {
-            return Ok(format);
-        }
-    }
-    Err("Cannot find surface format")
-}
-
-fn choose_swapchain_present_mode(
-    v_present_modes: &Vec<ash::vk::PresentModeKHR>,
-) -> ash::vk::PresentModeKHR {
-    return match v_present_modes.iter().find(|mode| {
-        return **mode == ash::vk::PresentModeKHR::MAILBOX;
-    }) {
-        Some(mode) => *mode,
-        None => ash::vk::PresentModeKHR::FIFO,
-    };
-}
-
-fn handle_events(event_pump: &mut sdl2::EventPump) -> bool {
+/// Returns `false` to quit, sets `swapchain_outdated` when the window size changes.
+fn handle_events(event_pump: &mut sdl2::EventPump, swapchain_outdated: &mut bool) -> bool {
     for event in event_pump.poll_iter() {
         match event {
             Event::Quit { .. }
@@ -182,35 +50,29 @@ fn handle_events(event_pump: &mut sdl2::EventPump) -> bool {
                 keycode: Some(Keycode::Escape),
                 ..
             } => return false,
-            _ => return true,
+            Event::Window {
+                win_event: WindowEvent::SizeChanged(..),
+                ..
+            } => *swapchain_outdated = true,
+            _ => {}
         }
     }
     true
 }
 
 unsafe fn update_uniform_buffer(
-    logical_device: &ash::Device,
-    memory: &ash::vk::DeviceMemory,
+    uniform_buffer: &vkcore::buffer::DynamicUniformBuffer<MyUniformBuffer>,
+    slot: usize,
     matrices: &mut MyUniformBuffer,
 ) {
     matrices.m_model = glm::rotate(&matrices.m_model, 0.01, &glm::vec3(0.0, 1.0, 0.0));
-    let p_data = logical_device
-        .map_memory(
-            *memory,
-            0,
-            std::mem::size_of::<MyUniformBuffer>() as ash::vk::DeviceSize,
-            Default::default(),
-        )
-        .expect("Cannot map device memory");
-    std::ptr::copy_nonoverlapping(
-        matrices as *const MyUniformBuffer as *const std::ffi::c_void,
-        p_data,
-        std::mem::size_of::<MyUniformBuffer>(),
-    );
-    logical_device.unmap_memory(*memory);
+    uniform_buffer
+        .write(slot, matrices)
+        .expect("Cannot write uniform buffer");
 }
 
-const FRAME_COUNT: usize = 2;
+const VERTEX_SHADER_PATH: &str = "shaders/006_spinning_triangle.vert";
+const FRAGMENT_SHADER_PATH: &str = "shaders/006_spinning_triangle.frag";
 fn main() {
     unsafe {
         let sdl_context = sdl2::init().unwrap();
@@ -221,83 +83,69 @@ fn main() {
         let window = video_subsystem
             .window("rust-sdl2 demo", window_width, window_height)
             .vulkan()
+            .resizable()
             .position_centered()
             .build()
             .expect("Cannot build window!");
 
         let entry = ash::Entry::new().expect("Cannot create entry");
-        let instance = create_instance(
-            &entry,
-            window
-                .vulkan_instance_extensions()
-                .expect("Cannot get instance extensions!"),
-        );
-        let gpu = pick_up_one_gpu(&instance).expect("Cannot find GPU");
-        let index_of_queue_family =
-            lookup_queue_family_index(&instance, &gpu).expect("Cannot find graphics queue family");
-        let logical_device = create_logical_device(&instance, &gpu, index_of_queue_family)
-            .expect("Cannot create logical device");
-        let queue = logical_device.get_device_queue(index_of_queue_family as u32, 0);
-
-        let surface_loader = ash::extensions::khr::Surface::new(&entry, &instance);
+        let instance = vkcore::instance::InstanceBuilder::new("008_render_to_texture")
+            .extensions(
+                &window
+                    .vulkan_instance_extensions()
+                    .expect("Cannot get instance extensions!"),
+            )
+            .build(&entry)
+            .expect("Cannot create instance");
         let surface_handle = window
             .vulkan_create_surface(instance.handle().as_raw() as usize)
             .expect("Cannot create surface");
-        let surface = ash::vk::SurfaceKHR::from_raw(surface_handle);
-
-        let presentation_supported = surface_loader.get_physical_device_surface_support(
-            gpu,
-            index_of_queue_family as u32,
-            surface,
+        let surface = vkcore::swapchain::Surface::from_raw(
+            &instance,
+            ash::vk::SurfaceKHR::from_raw(surface_handle),
         );
-        if !presentation_supported {
-            println!("Presentation not supported !");
-            return;
-        }
+        let surface_loader = surface.loader();
+        let selected_device = vkcore::selector::DeviceSelector::new(ash::vk::QueueFlags::GRAPHICS)
+            .surface(&surface)
+            .extension(ash::extensions::khr::Swapchain::name())
+            .select(&instance)
+            .expect("Cannot select physical device");
+        let gpu = selected_device.gpu;
+        let index_of_queue_family = selected_device.index_of_queue_family;
+        let logical_device = vkcore::device::DeviceBuilder::new(gpu, index_of_queue_family)
+            .extension(ash::extensions::khr::Swapchain::name())
+            .optional_features(vkcore::pipeline_statistics::PipelineStatistics::features())
+            .dedicated_transfer_queue(true)
+            .build(&instance)
+            .expect("Cannot create logical device");
+        let queue = logical_device.main_queue().handle;
 
         let surface_capabilities = surface_loader
-            .get_physical_device_surface_capabilities(gpu, surface)
+            .get_physical_device_surface_capabilities(gpu, surface.handle())
             .expect("Cannot get surface capabilities");
         let v_surface_formats = surface_loader
-            .get_physical_device_surface_formats(gpu, surface)
+            .get_physical_device_surface_formats(gpu, surface.handle())
             .expect("Cannot get physical device surface formats");
         let v_surface_present_modes = surface_loader
-            .get_physical_device_surface_present_modes(gpu, surface)
+            .get_physical_device_surface_present_modes(gpu, surface.handle())
             .expect("Cannot get surface present mode");
-        let available_format =
-            search_format(&v_surface_formats).expect("Cannot find surface format");
-        let image_count = if surface_capabilities.max_image_count > 0
-            && surface_capabilities.min_image_count + 1 > surface_capabilities.max_image_count
-        {
-            surface_capabilities.max_image_count
-        } else {
-            surface_capabilities.min_image_count + 1
-        };
+        let available_format = vkcore::swapchain::search_format(&v_surface_formats)
+            .expect("Cannot find surface format");
+        let present_settings = vkcore::swapchain::PresentSettings::negotiate(
+            &vkcore::swapchain::PresentOptions::from_args(),
+            &surface_capabilities,
+            &v_surface_present_modes,
+        );
 
-        let extent = if surface_capabilities.current_extent.width != !(0 as u32) {
-            surface_capabilities.current_extent
-        } else {
-            ash::vk::Extent2D {
-                width: num::clamp(
-                    window_width,
-                    surface_capabilities.min_image_extent.width,
-                    surface_capabilities.max_image_extent.width,
-                ),
-                height: num::clamp(
-                    window_height,
-                    surface_capabilities.min_image_extent.height,
-                    surface_capabilities.max_image_extent.height,
-                ),
-            }
-        };
+        let extent =
+            vkcore::swapchain::choose_extent(&surface_capabilities, window.vulkan_drawable_size());
 
-        let swapchain_loader = ash::extensions::khr::Swapchain::new(&instance, &logical_device);
         let swapchain_create_info = ash::vk::SwapchainCreateInfoKHR {
             s_type: ash::vk::StructureType::SWAPCHAIN_CREATE_INFO_KHR,
             p_next: std::ptr::null(),
             flags: Default::default(), //ash::vk::SwapchainCreateFlagsKHR::SPLIT_INSTANCE_BIND_REGIONS,
-            surface: surface,
-            min_image_count: image_count,
+            surface: surface.handle(),
+            min_image_count: present_settings.image_count,
             image_format: available_format.format,
             image_color_space: available_format.color_space,
             image_extent: extent,
@@ -308,32 +156,17 @@ fn main() {
             p_queue_family_indices: std::ptr::null(),
             pre_transform: surface_capabilities.current_transform,
             composite_alpha: ash::vk::CompositeAlphaFlagsKHR::OPAQUE,
-            present_mode: choose_swapchain_present_mode(&v_surface_present_modes),
+            present_mode: present_settings.present_mode,
             clipped: ash::vk::TRUE,
             old_swapchain: ash::vk::SwapchainKHR::null(),
         };
 
-        let swapchain = swapchain_loader
-            .create_swapchain(&swapchain_create_info, None)
-            .expect("Cannot create swapchain");
-        let v_swapchain_images = swapchain_loader
-            .get_swapchain_images(swapchain)
-            .expect("Cannot get swapchain images");
-        let swapchain_size = v_swapchain_images.len();
-        let component_mapping = ash::vk::ComponentMapping {
-            r: ash::vk::ComponentSwizzle::IDENTITY,
-            g: ash::vk::ComponentSwizzle::IDENTITY,
-            b: ash::vk::ComponentSwizzle::IDENTITY,
-            a: ash::vk::ComponentSwizzle::IDENTITY,
-        };
-
-        let subresource_range = ash::vk::ImageSubresourceRange {
-            aspect_mask: ash::vk::ImageAspectFlags::COLOR,
-            base_mip_level: 0,
-            level_count: 1,
-            base_array_layer: 0,
-            layer_count: 1,
-        };
+        let vertex_shader_module =
+            vkcore::shader::create_shader_module(&logical_device, VERTEX_SHADER_PATH)
+                .expect("Cannot create shader module");
+        let fragment_shader_module =
+            vkcore::shader::create_shader_module(&logical_device, FRAGMENT_SHADER_PATH)
+                .expect("Cannot create shader module");
         let shader_entry_name =
             CString::new("main").expect("Cannot create vertex shader entry name");
         let v_pipeline_shader_stage_create_infos = [
@@ -342,10 +175,7 @@ fn main() {
                 p_next: std::ptr::null(),
                 flags: Default::default(),
                 stage: ash::vk::ShaderStageFlags::VERTEX,
-                module: create_shader_module(
-                    &logical_device,
-                    "shaders/006_spinning_triangle.vert.spv",
-                ),
+                module: vertex_shader_module.handle(),
                 p_name: shader_entry_name.as_ptr(),
                 p_specialization_info: std::ptr::null(),
             },
@@ -354,35 +184,24 @@ fn main() {
                 p_next: std::ptr::null(),
                 flags: Default::default(),
                 stage: ash::vk::ShaderStageFlags::FRAGMENT,
-                module: create_shader_module(
-                    &logical_device,
-                    "shaders/006_spinning_triangle.frag.spv",
-                ),
+                module: fragment_shader_module.handle(),
                 p_name: shader_entry_name.as_ptr(),
                 p_specialization_info: std::ptr::null(),
             },
         ];
 
-        let vertex_input_binding_description = ash::vk::VertexInputBindingDescription {
-            binding: 0,
-            stride: std::mem::size_of::<MyPointData>() as u32,
-            input_rate: ash::vk::VertexInputRate::VERTEX,
-        };
+        let pipeline_reflection = vkcore::reflection::PipelineReflection::from_files(&[
+            VERTEX_SHADER_PATH,
+            FRAGMENT_SHADER_PATH,
+        ])
+        .expect("Cannot reflect shaders")
+        .dynamic_buffer("matrices");
 
-        let v_vertex_input_attribute_description = &[
-            ash::vk::VertexInputAttributeDescription {
-                location: 1,
-                binding: 0,
-                format: ash::vk::Format::R32G32B32_SFLOAT,
-                offset: 0,
-            },
-            ash::vk::VertexInputAttributeDescription {
-                location: 2,
-                binding: 0,
-                format: ash::vk::Format::R32G32B32_SFLOAT,
-                offset: std::mem::size_of::<glm::Vec3>() as u32,
-            },
-        ];
+        let vertex_input_binding_description = MyPointData::binding_description(0);
+
+        let v_vertex_input_attribute_description = pipeline_reflection
+            .vertex_input_attributes::<MyPointData>(0)
+            .expect("Cannot match MyPointData with the vertex shader inputs");
 
         let vertex_input_state_create_info = ash::vk::PipelineVertexInputStateCreateInfo {
             s_type: ash::vk::StructureType::PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
@@ -402,31 +221,15 @@ fn main() {
             primitive_restart_enable: ash::vk::FALSE,
         };
 
-        let viewport = ash::vk::Viewport {
-            x: 0f32,
-            y: 0f32,
-            width: window_width as f32,
-            height: window_height as f32,
-            min_depth: 0.0,
-            max_depth: 1.0,
-        };
-
-        let scissor = ash::vk::Rect2D {
-            offset: ash::vk::Offset2D { x: 0, y: 0 },
-            extent: ash::vk::Extent2D {
-                width: extent.width,
-                height: extent.height,
-            },
-        };
-
         let viewport_state_create_info = ash::vk::PipelineViewportStateCreateInfo {
             s_type: ash::vk::StructureType::PIPELINE_VIEWPORT_STATE_CREATE_INFO,
             p_next: std::ptr::null(),
             flags: Default::default(),
             viewport_count: 1,
-            p_viewports: &viewport,
+            // Dynamic, set to the extent of the swapchain when recording.
+            p_viewports: std::ptr::null(),
             scissor_count: 1,
-            p_scissors: &scissor,
+            p_scissors: std::ptr::null(),
         };
 
         let rasterization_state_create_info = ash::vk::PipelineRasterizationStateCreateInfo {
@@ -497,77 +300,76 @@ fn main() {
             blend_constants: [0f32; 4],
         };
 
+        let v_dynamic_states = [
+            ash::vk::DynamicState::VIEWPORT,
+            ash::vk::DynamicState::SCISSOR,
+        ];
         let dynamic_state_create_info = ash::vk::PipelineDynamicStateCreateInfo {
             s_type: ash::vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
             p_next: std::ptr::null(),
             flags: Default::default(),
-            dynamic_state_count: 0 as u32,
-            p_dynamic_states: std::ptr::null(),
+            dynamic_state_count: v_dynamic_states.len() as u32,
+            p_dynamic_states: v_dynamic_states.as_ptr(),
         };
 
-        let uniform_buffer_binding_number = 5;
-        let descriptor_set_layout_binding = ash::vk::DescriptorSetLayoutBinding {
-            binding: uniform_buffer_binding_number,
-            descriptor_type: ash::vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: 1,
-            stage_flags: ash::vk::ShaderStageFlags::VERTEX,
-            p_immutable_samplers: std::ptr::null(),
-        };
+        let uniform_buffer_binding_number = pipeline_reflection
+            .binding("matrices")
+            .expect("Cannot find the matrices in the shaders")
+            .binding;
+        let v_descriptor_set_layout_binding = pipeline_reflection.descriptor_set_layout_bindings(0);
 
         let descriptor_set_layout_create_info = ash::vk::DescriptorSetLayoutCreateInfo {
             s_type: ash::vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
             p_next: std::ptr::null(),
             flags: Default::default(),
-            binding_count: 1,
-            p_bindings: &descriptor_set_layout_binding,
+            binding_count: v_descriptor_set_layout_binding.len() as u32,
+            p_bindings: v_descriptor_set_layout_binding.as_ptr(),
         };
 
-        let descriptor_set_layout = logical_device
-            .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
-            .expect("Cannot create descriptor set layout");
+        let descriptor_set_layout = vkcore::objects::DescriptorSetLayout::new(
+            &logical_device,
+            &descriptor_set_layout_create_info,
+        )
+        .expect("Cannot create descriptor set layout");
 
-        let descriptor_pool_size = ash::vk::DescriptorPoolSize {
-            ty: ash::vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: swapchain_size as u32,
-        };
+        let v_descriptor_pool_size = pipeline_reflection.descriptor_pool_sizes(0, 1);
 
         let descriptor_pool_create_info = ash::vk::DescriptorPoolCreateInfo {
             s_type: ash::vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
             p_next: std::ptr::null(),
             flags: ash::vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
-            max_sets: swapchain_size as u32,
-            pool_size_count: 1,
-            p_pool_sizes: &descriptor_pool_size,
+            max_sets: 1,
+            pool_size_count: v_descriptor_pool_size.len() as u32,
+            p_pool_sizes: v_descriptor_pool_size.as_ptr(),
         };
-        let descriptor_pool = logical_device
-            .create_descriptor_pool(&descriptor_pool_create_info, None)
-            .expect("Cannot create descriptor pool");
+        let descriptor_pool =
+            vkcore::objects::DescriptorPool::new(&logical_device, &descriptor_pool_create_info)
+                .expect("Cannot create descriptor pool");
 
-        let v_descriptor_set_layout_refs = vec![descriptor_set_layout; swapchain_size];
+        let descriptor_set_layout_handle = descriptor_set_layout.handle();
         let descriptor_set_allocate_info = ash::vk::DescriptorSetAllocateInfo {
             s_type: ash::vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
             p_next: std::ptr::null(),
-            descriptor_pool: descriptor_pool,
-            descriptor_set_count: v_descriptor_set_layout_refs.len() as u32,
-            p_set_layouts: v_descriptor_set_layout_refs.as_ptr(),
+            descriptor_pool: descriptor_pool.handle(),
+            descriptor_set_count: 1,
+            p_set_layouts: &descriptor_set_layout_handle,
         };
-        let v_descriptor_sets = logical_device
+        let descriptor_set = logical_device
             .allocate_descriptor_sets(&descriptor_set_allocate_info)
-            .expect("Cannot allocate descriptor set");
-
+            .expect("Cannot allocate descriptor set")[0];
         let pipeline_layout_create_info = ash::vk::PipelineLayoutCreateInfo {
             s_type: ash::vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
             p_next: std::ptr::null(),
             flags: Default::default(),
             set_layout_count: 1,
-            p_set_layouts: &descriptor_set_layout,
+            p_set_layouts: &descriptor_set_layout_handle,
             push_constant_range_count: 0,
             p_push_constant_ranges: std::ptr::null(),
         };
 
-        let pipeline_layout = logical_device
-            .create_pipeline_layout(&pipeline_layout_create_info, None)
-            .expect("Cannot create pipeline layout");
+        let pipeline_layout =
+            vkcore::objects::PipelineLayout::new(&logical_device, &pipeline_layout_create_info)
+                .expect("Cannot create pipeline layout");
 
         let attachment_description = ash::vk::AttachmentDescription {
             flags: Default::default(),
@@ -610,9 +412,9 @@ fn main() {
             dependency_count: 0,
             p_dependencies: std::ptr::null(),
         };
-        let render_pass = logical_device
-            .create_render_pass(&render_pass_create_info, None)
-            .expect("Cannot create render pass");
+        let render_pass =
+            vkcore::objects::RenderPass::new(&logical_device, &render_pass_create_info)
+                .expect("Cannot create render pass");
 
         let graphics_pipeline_create_info = ash::vk::GraphicsPipelineCreateInfo {
             s_type: ash::vk::StructureType::GRAPHICS_PIPELINE_CREATE_INFO,
@@ -629,60 +431,31 @@ fn main() {
             p_depth_stencil_state: &depth_stencil_state_create_info,
             p_color_blend_state: &color_blend_state_create_info,
             p_dynamic_state: &dynamic_state_create_info,
-            layout: pipeline_layout,
-            render_pass: render_pass,
+            layout: pipeline_layout.handle(),
+            render_pass: render_pass.handle(),
             subpass: 0,
             base_pipeline_handle: ash::vk::Pipeline::null(),
             base_pipeline_index: -1,
         };
 
-        let v_graphics_pipelines = logical_device
-            .create_graphics_pipelines(
-                ash::vk::PipelineCache::null(),
-                &[graphics_pipeline_create_info],
-                None,
-            )
+        let pipeline_cache = vkcore::pipeline_cache::DiskPipelineCache::load(
+            &logical_device,
+            "008_render_to_texture",
+            &vkcore::pipeline_cache::PipelineCacheOptions::from_args(),
+        )
+        .expect("Cannot load pipeline cache");
+        let mut graphics_pipeline = pipeline_cache
+            .timed(|| {
+                vkcore::objects::Pipeline::new_graphics(
+                    &logical_device,
+                    pipeline_cache.handle(),
+                    &graphics_pipeline_create_info,
+                )
+            })
             .expect("Cannot create graphics pipeline");
-
-        let graphics_pipeline = v_graphics_pipelines[0];
-
-        let mut v_image_views = Vec::with_capacity(v_swapchain_images.len());
-        for image in &v_swapchain_images {
-            let image_view_create_info = ash::vk::ImageViewCreateInfo {
-                s_type: ash::vk::StructureType::IMAGE_VIEW_CREATE_INFO,
-                p_next: std::ptr::null(),
-                flags: Default::default(),
-                image: *image,
-                view_type: ash::vk::ImageViewType::TYPE_2D,
-                format: available_format.format,
-                components: component_mapping,
-                subresource_range: subresource_range,
-            };
-            v_image_views.push(
-                logical_device
-                    .create_image_view(&image_view_create_info, None)
-                    .expect("Cannot create image view"),
-            );
-        }
-
-        let mut v_framebuffers = Vec::with_capacity(swapchain_size);
-        for i in 0..swapchain_size {
-            let framebuffer_create_info = ash::vk::FramebufferCreateInfo {
-                s_type: ash::vk::StructureType::FRAMEBUFFER_CREATE_INFO,
-                p_next: std::ptr::null(),
-                flags: Default::default(),
-                render_pass: render_pass,
-                attachment_count: 1,
-                p_attachments: &v_image_views[i],
-                width: extent.width,
-                height: extent.height,
-                layers: 1,
-            };
-            v_framebuffers.push(
-                logical_device
-                    .create_framebuffer(&framebuffer_create_info, None)
-                    .expect("Cannot create framebuffer"),
-            );
+        println!("{}", pipeline_cache);
+        if let Err(e) = pipeline_cache.save() {
+            eprintln!("Cannot save pipeline cache: {:#}", e);
         }
 
         let command_pool_create_info = ash::vk::CommandPoolCreateInfo {
@@ -692,23 +465,22 @@ fn main() {
             queue_family_index: index_of_queue_family as u32,
         };
 
-        let command_pool = logical_device
-            .create_command_pool(&command_pool_create_info, None)
-            .expect("Cannot create command pool");
-
-        let command_buffer_allocate_info = ash::vk::CommandBufferAllocateInfo {
-            s_type: ash::vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
-            p_next: std::ptr::null(),
-            command_pool: command_pool,
-            level: ash::vk::CommandBufferLevel::PRIMARY,
-            command_buffer_count: swapchain_size as u32,
-        };
-
-        let v_command_buffers = logical_device
-            .allocate_command_buffers(&command_buffer_allocate_info)
-            .expect("Cannot allocate command buffer");
+        let command_pool =
+            vkcore::objects::CommandPool::new(&logical_device, &command_pool_create_info)
+                .expect("Cannot create command pool");
 
-        let vertex_buffer_bytes_size = std::mem::size_of::<MyPointData>() * 3;
+        let mut swapchain_framebuffers = vkcore::swapchain::SwapchainFramebuffers::new(
+            &logical_device,
+            &swapchain_create_info,
+            render_pass.handle(),
+            command_pool.handle(),
+        )
+        .expect("Cannot create swapchain");
+        let mut swapchain_size = swapchain_framebuffers.image_count();
+        println!(
+            "{}, {} swapchain images created",
+            present_settings, swapchain_size
+        );
 
         let vertex_buffer_content = vec![
             MyPointData {
@@ -726,293 +498,175 @@ fn main() {
         ];
 
         // STAGING BUFFER CREATION
-        let staging_buffer_create_info = ash::vk::BufferCreateInfo {
-            s_type: ash::vk::StructureType::BUFFER_CREATE_INFO,
-            p_next: std::ptr::null(),
-            flags: Default::default(),
-            size: vertex_buffer_bytes_size as u64,
-            usage: ash::vk::BufferUsageFlags::TRANSFER_SRC,
-            sharing_mode: ash::vk::SharingMode::EXCLUSIVE,
-            queue_family_index_count: 0,
-            p_queue_family_indices: std::ptr::null(),
-        };
-
-        let staging_buffer = logical_device
-            .create_buffer(&staging_buffer_create_info, None)
-            .expect("Cannot staging buffer");
-
-        let staging_buffer_memory_requirements =
-            logical_device.get_buffer_memory_requirements(staging_buffer);
-        let staging_buffer_memory_type_index = search_physical_device_memory_type(
-            &instance,
-            &gpu,
-            &staging_buffer_memory_requirements,
-            ash::vk::MemoryPropertyFlags::HOST_COHERENT
-                | ash::vk::MemoryPropertyFlags::HOST_VISIBLE,
+        let allocator = vkcore::allocator::DeviceMemoryAllocator::new(&logical_device);
+        let staging_buffer = vkcore::buffer::TypedBuffer::from_slice(
+            &logical_device,
+            &allocator,
+            &vertex_buffer_content,
+            ash::vk::BufferUsageFlags::TRANSFER_SRC,
         )
-        .unwrap();
-
-        let memory_allocate_info_for_staging_buffer = ash::vk::MemoryAllocateInfo {
-            s_type: ash::vk::StructureType::MEMORY_ALLOCATE_INFO,
-            p_next: std::ptr::null(),
-            allocation_size: staging_buffer_memory_requirements.size,
-            memory_type_index: staging_buffer_memory_type_index as u32,
-        };
-        let device_memory_for_staging_buffer = logical_device
-            .allocate_memory(&memory_allocate_info_for_staging_buffer, None)
-            .expect("Cannot allocate memory for staging buffer");
-
-        let vertex_buffer_offset = 0 as ash::vk::DeviceSize;
-        logical_device
-            .bind_buffer_memory(
-                staging_buffer,
-                device_memory_for_staging_buffer,
-                vertex_buffer_offset,
-            )
-            .expect("Cannot bind memory for staging buffer");
-
-        let p_data = logical_device
-            .map_memory(
-                device_memory_for_staging_buffer,
-                vertex_buffer_offset,
-                staging_buffer_create_info.size,
-                Default::default(),
-            )
-            .expect("Cannot map memory");
-        std::ptr::copy_nonoverlapping(
-            vertex_buffer_content.as_ptr() as *const std::ffi::c_void,
-            p_data,
-            vertex_buffer_bytes_size,
-        );
-        logical_device.unmap_memory(device_memory_for_staging_buffer);
+        .expect("Cannot create staging buffer");
 
         // VERTEX BUFFER CREATION
-        let vertex_buffer_create_info = ash::vk::BufferCreateInfo {
-            s_type: ash::vk::StructureType::BUFFER_CREATE_INFO,
-            p_next: std::ptr::null(),
-            flags: Default::default(),
-            size: vertex_buffer_bytes_size as u64,
-            usage: ash::vk::BufferUsageFlags::VERTEX_BUFFER
-                | ash::vk::BufferUsageFlags::TRANSFER_DST,
-            sharing_mode: ash::vk::SharingMode::EXCLUSIVE,
-            queue_family_index_count: 0,
-            p_queue_family_indices: std::ptr::null(),
-        };
-
-        let vertex_buffer = logical_device
-            .create_buffer(&vertex_buffer_create_info, None)
-            .expect("Cannot create vertex buffer");
-
-        let vertex_buffer_memory_requirements =
-            logical_device.get_buffer_memory_requirements(vertex_buffer);
-
-        let vertex_buffer_memory_type_index = search_physical_device_memory_type(
-            &instance,
-            &gpu,
-            &vertex_buffer_memory_requirements,
+        let vertex_buffer = vkcore::buffer::TypedBuffer::<MyPointData>::new(
+            &logical_device,
+            &allocator,
+            vertex_buffer_content.len(),
+            ash::vk::BufferUsageFlags::VERTEX_BUFFER | ash::vk::BufferUsageFlags::TRANSFER_DST,
             ash::vk::MemoryPropertyFlags::DEVICE_LOCAL,
         )
-        .unwrap();
+        .expect("Cannot create vertex buffer");
 
-        let memory_allocate_info_for_vertex_buffer = ash::vk::MemoryAllocateInfo {
-            s_type: ash::vk::StructureType::MEMORY_ALLOCATE_INFO,
-            p_next: std::ptr::null(),
-            allocation_size: vertex_buffer_memory_requirements.size,
-            memory_type_index: vertex_buffer_memory_type_index as u32,
-        };
-
-        let device_memory_for_vertex_buffer = logical_device
-            .allocate_memory(&memory_allocate_info_for_vertex_buffer, None)
-            .expect("Cannot allocate memory for vertex buffer");
-        logical_device
-            .bind_buffer_memory(
-                vertex_buffer,
-                device_memory_for_vertex_buffer,
-                vertex_buffer_offset,
+        let uploader =
+            vkcore::transfer::Uploader::new(&logical_device).expect("Cannot create uploader");
+        let buffer_copy = ash::vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size: vertex_buffer.size_in_bytes(),
+        };
+        uploader
+            .copy_buffer(
+                staging_buffer.handle(),
+                vertex_buffer.handle(),
+                &[buffer_copy],
+                ash::vk::PipelineStageFlags::VERTEX_INPUT,
+                ash::vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
             )
-            .expect("Cannot bind memory for vertex buffer");
-
-        let copy_command_buffer_allocate_info = ash::vk::CommandBufferAllocateInfo {
-            s_type: ash::vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
-            p_next: std::ptr::null(),
-            command_pool: command_pool,
-            level: ash::vk::CommandBufferLevel::PRIMARY,
-            command_buffer_count: 1,
-        };
+            .expect("Cannot copy staging buffer");
+        drop(staging_buffer);
 
-        let command_buffer_copy_buffer = logical_device
-            .allocate_command_buffers(&copy_command_buffer_allocate_info)
-            .expect("Cannot allocate command buffer to copy staging buffer")[0];
-        let command_buffer_begin_info = ash::vk::CommandBufferBeginInfo {
-            s_type: ash::vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
-            p_next: std::ptr::null(),
-            flags: ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
-            p_inheritance_info: std::ptr::null(),
-        };
-        logical_device
-            .begin_command_buffer(command_buffer_copy_buffer, &command_buffer_begin_info)
-            .expect("Cannot begin command buffer to copy staging buffer");
-        let buffer_copy = ash::vk::BufferCopy {
-            src_offset: vertex_buffer_offset,
-            dst_offset: vertex_buffer_offset,
-            size: vertex_buffer_bytes_size as u64,
-        };
-        logical_device.cmd_copy_buffer(
-            command_buffer_copy_buffer,
-            staging_buffer,
-            vertex_buffer,
-            &[buffer_copy],
-        );
-        logical_device
-            .end_command_buffer(command_buffer_copy_buffer)
-            .expect("Cannot end command buffer to copy staging buffer");
-        let copy_buffer_submit_info = ash::vk::SubmitInfo {
-            s_type: ash::vk::StructureType::SUBMIT_INFO,
+        // UNIFORM BUFFERS
+        // One persistently mapped buffer with a slot per swapchain image, selected
+        // at bind time with a dynamic offset.
+        let mut uniform_buffer = vkcore::buffer::DynamicUniformBuffer::<MyUniformBuffer>::new(
+            &logical_device,
+            &allocator,
+            swapchain_size,
+        )
+        .expect("Cannot create uniform buffer");
+        let descriptor_buffer_info = uniform_buffer.descriptor_buffer_info();
+        let descriptor_write = ash::vk::WriteDescriptorSet {
+            s_type: ash::vk::StructureType::WRITE_DESCRIPTOR_SET,
             p_next: std::ptr::null(),
-            wait_semaphore_count: 0,
-            p_wait_semaphores: std::ptr::null(),
-            p_wait_dst_stage_mask: std::ptr::null(),
-            command_buffer_count: 1,
-            p_command_buffers: &command_buffer_copy_buffer,
-            signal_semaphore_count: 0,
-            p_signal_semaphores: std::ptr::null(),
+            dst_set: descriptor_set,
+            dst_binding: uniform_buffer_binding_number,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: ash::vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            p_image_info: std::ptr::null(),
+            p_buffer_info: &descriptor_buffer_info,
+            p_texel_buffer_view: std::ptr::null(),
         };
-        logical_device
-            .queue_submit(queue, &[copy_buffer_submit_info], ash::vk::Fence::null())
-            .expect("Cannot submit command buffer to copy staging buffer");
-        logical_device
-            .queue_wait_idle(queue)
-            .expect("Cannot wait for queue to copy staging buffer");
+        logical_device.update_descriptor_sets(&[descriptor_write], &[]);
 
-        logical_device.free_command_buffers(command_pool, &[command_buffer_copy_buffer]);
-        logical_device.destroy_buffer(staging_buffer, None);
-        logical_device.free_memory(device_memory_for_staging_buffer, None);
-
-        // UNIFORM BUFFERS
-        let uniform_buffer_size = std::mem::size_of::<MyUniformBuffer>();
-        let mut v_uniform_buffers = Vec::with_capacity(swapchain_size);
-        let mut v_memory_uniform_buffers = Vec::with_capacity(swapchain_size);
-        for i in 0..swapchain_size {
-            let buffer_create_info = ash::vk::BufferCreateInfo {
-                s_type: ash::vk::StructureType::BUFFER_CREATE_INFO,
-                p_next: std::ptr::null(),
-                flags: Default::default(),
-                size: uniform_buffer_size as ash::vk::DeviceSize,
-                usage: ash::vk::BufferUsageFlags::UNIFORM_BUFFER,
-                sharing_mode: ash::vk::SharingMode::EXCLUSIVE,
-                queue_family_index_count: 0,
-                p_queue_family_indices: std::ptr::null(),
-            };
-            v_uniform_buffers.push(
-                logical_device
-                    .create_buffer(&buffer_create_info, None)
-                    .expect("Cannot create uniform buffer"),
-            );
-            let buffer_requirements =
-                logical_device.get_buffer_memory_requirements(v_uniform_buffers[i]);
-            let memory_allocate_info = ash::vk::MemoryAllocateInfo {
-                s_type: ash::vk::StructureType::MEMORY_ALLOCATE_INFO,
-                p_next: std::ptr::null(),
-                allocation_size: buffer_requirements.size,
-                memory_type_index: search_physical_device_memory_type(
-                    &instance,
-                    &gpu,
-                    &buffer_requirements,
-                    ash::vk::MemoryPropertyFlags::HOST_VISIBLE
-                        | ash::vk::MemoryPropertyFlags::HOST_COHERENT,
+        let pipeline_statistics =
+            vkcore::pipeline_statistics::StatisticsOptions::from_args().map(|options| {
+                vkcore::pipeline_statistics::PipelineStatistics::new(
+                    &logical_device,
+                    swapchain_size,
+                    &options,
                 )
-                .expect("Cannot find memory type for uniform buffer memory")
-                    as u32,
-            };
-            v_memory_uniform_buffers.push(
-                logical_device
-                    .allocate_memory(&memory_allocate_info, None)
-                    .expect("Cannot allocate memory for uniform buffer"),
-            );
-            logical_device
-                .bind_buffer_memory(v_uniform_buffers[i], v_memory_uniform_buffers[i], 0)
-                .expect("Cannot bind uniform buffer to its memory");
-            let descriptor_buffer_info = ash::vk::DescriptorBufferInfo {
-                buffer: v_uniform_buffers[i],
-                offset: 0,
-                range: ash::vk::WHOLE_SIZE,
-            };
-            let descriptor_write = ash::vk::WriteDescriptorSet {
-                s_type: ash::vk::StructureType::WRITE_DESCRIPTOR_SET,
-                p_next: std::ptr::null(),
-                dst_set: v_descriptor_sets[i],
-                dst_binding: uniform_buffer_binding_number,
-                dst_array_element: 0,
-                descriptor_count: 1,
-                descriptor_type: ash::vk::DescriptorType::UNIFORM_BUFFER,
-                p_image_info: std::ptr::null(),
-                p_buffer_info: &descriptor_buffer_info,
-                p_texel_buffer_view: std::ptr::null(),
-            };
-            logical_device.update_descriptor_sets(&[descriptor_write], &[]);
-        }
-
-        for (index, command_buffer) in (&v_command_buffers).iter().enumerate() {
-            let render_area = ash::vk::Rect2D {
-                offset: ash::vk::Offset2D { x: 0, y: 0 },
-                extent: extent,
+                .expect("Cannot create pipeline statistics")
+            });
+
+        // Also called again when the shaders are reloaded or the swapchain is
+        // recreated.
+        let record_command_buffers =
+            |graphics_pipeline: ash::vk::Pipeline,
+             swapchain_framebuffers: &vkcore::swapchain::SwapchainFramebuffers,
+             uniform_buffer: &vkcore::buffer::DynamicUniformBuffer<MyUniformBuffer>| {
+                let extent = swapchain_framebuffers.extent();
+                for (index, command_buffer) in
+                    swapchain_framebuffers.command_buffers().iter().enumerate()
+                {
+                    let render_area = ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: 0, y: 0 },
+                        extent: extent,
+                    };
+                    let clear_values = ash::vk::ClearValue {
+                        color: ash::vk::ClearColorValue {
+                            float32: [1.0, 0.0, 1.0, 1.0],
+                        },
+                    };
+                    let command_buffer_begin_info = ash::vk::CommandBufferBeginInfo {
+                        s_type: ash::vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+                        p_next: std::ptr::null(),
+                        flags: Default::default(),
+                        p_inheritance_info: std::ptr::null(),
+                    };
+
+                    logical_device
+                        .begin_command_buffer(*command_buffer, &command_buffer_begin_info)
+                        .expect("Cannot begin command buffer");
+                    if let Some(pipeline_statistics) = &pipeline_statistics {
+                        pipeline_statistics.begin(*command_buffer, index);
+                    }
+
+                    let render_pass_begin_info = ash::vk::RenderPassBeginInfo {
+                        s_type: ash::vk::StructureType::RENDER_PASS_BEGIN_INFO,
+                        p_next: std::ptr::null(),
+                        render_pass: render_pass.handle(),
+                        framebuffer: swapchain_framebuffers.framebuffer(index),
+                        render_area: render_area,
+                        clear_value_count: 1,
+                        p_clear_values: &clear_values,
+                    };
+
+                    logical_device.cmd_begin_render_pass(
+                        *command_buffer,
+                        &render_pass_begin_info,
+                        ash::vk::SubpassContents::INLINE,
+                    );
+                    logical_device.cmd_bind_descriptor_sets(
+                        *command_buffer,
+                        ash::vk::PipelineBindPoint::GRAPHICS,
+                        pipeline_layout.handle(),
+                        0,
+                        &[descriptor_set],
+                        &[uniform_buffer.dynamic_offset(index)],
+                    );
+                    logical_device.cmd_bind_pipeline(
+                        *command_buffer,
+                        ash::vk::PipelineBindPoint::GRAPHICS,
+                        graphics_pipeline,
+                    );
+                    let viewport = ash::vk::Viewport {
+                        x: 0f32,
+                        y: 0f32,
+                        width: extent.width as f32,
+                        height: extent.height as f32,
+                        min_depth: 0.0,
+                        max_depth: 1.0,
+                    };
+                    logical_device.cmd_set_viewport(*command_buffer, 0, &[viewport]);
+                    logical_device.cmd_set_scissor(*command_buffer, 0, &[render_area]);
+                    logical_device.cmd_bind_vertex_buffers(
+                        *command_buffer,
+                        0,
+                        &[vertex_buffer.handle()],
+                        &[0],
+                    );
+                    logical_device.cmd_draw(
+                        *command_buffer,
+                        vertex_buffer_content.len() as u32,
+                        1,
+                        0,
+                        0,
+                    );
+                    logical_device.cmd_end_render_pass(*command_buffer);
+                    if let Some(pipeline_statistics) = &pipeline_statistics {
+                        pipeline_statistics.end(*command_buffer, index);
+                    }
+                    logical_device
+                        .end_command_buffer(*command_buffer)
+                        .expect("Cannot end command buffer");
+                }
             };
-            let clear_values = ash::vk::ClearValue {
-                color: ash::vk::ClearColorValue {
-                    float32: [1.0, 0.0, 1.0, 1.0],
-                },
-            };
-            let command_buffer_begin_info = ash::vk::CommandBufferBeginInfo {
-                s_type: ash::vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
-                p_next: std::ptr::null(),
-                flags: Default::default(),
-                p_inheritance_info: std::ptr::null(),
-            };
-
-            logical_device
-                .begin_command_buffer(*command_buffer, &command_buffer_begin_info)
-                .expect("Cannot begin command buffer");
-
-            let render_pass_begin_info = ash::vk::RenderPassBeginInfo {
-                s_type: ash::vk::StructureType::RENDER_PASS_BEGIN_INFO,
-                p_next: std::ptr::null(),
-                render_pass: render_pass,
-                framebuffer: v_framebuffers[index],
-                render_area: render_area,
-                clear_value_count: 1,
-                p_clear_values: &clear_values,
-            };
-
-            logical_device.cmd_begin_render_pass(
-                *command_buffer,
-                &render_pass_begin_info,
-                ash::vk::SubpassContents::INLINE,
-            );
-            logical_device.cmd_bind_descriptor_sets(
-                *command_buffer,
-                ash::vk::PipelineBindPoint::GRAPHICS,
-                pipeline_layout,
-                0,
-                &[v_descriptor_sets[index]],
-                &[],
-            );
-            logical_device.cmd_bind_pipeline(
-                *command_buffer,
-                ash::vk::PipelineBindPoint::GRAPHICS,
-                graphics_pipeline,
-            );
-            logical_device.cmd_bind_vertex_buffers(
-                *command_buffer,
-                0,
-                &[vertex_buffer],
-                &[vertex_buffer_offset],
-            );
-            logical_device.cmd_draw(*command_buffer, vertex_buffer_content.len() as u32, 1, 0, 0);
-            logical_device.cmd_end_render_pass(*command_buffer);
-            logical_device
-                .end_command_buffer(*command_buffer)
-                .expect("Cannot end command buffer");
-        }
+        record_command_buffers(
+            graphics_pipeline.handle(),
+            &swapchain_framebuffers,
+            &uniform_buffer,
+        );
 
         let fence_create_info = ash::vk::FenceCreateInfo {
             s_type: ash::vk::StructureType::FENCE_CREATE_INFO,
@@ -1032,28 +686,30 @@ fn main() {
             flags: Default::default(),
         };
 
-        let v_fences_wait_gpu = [
-            logical_device
-                .create_fence(&fence_create_info, None)
-                .expect("Cannot create fence"),
-            logical_device
-                .create_fence(&fence_create_info, None)
-                .expect("Cannot create fence"),
-        ];
+        let v_fences_wait_gpu: Vec<_> = (0..present_settings.frames_in_flight)
+            .map(|_| {
+                vkcore::objects::Fence::new(&logical_device, &fence_create_info)
+                    .expect("Cannot create fence")
+            })
+            .collect();
         let mut v_fences_ref_wait_gpu = vec![ash::vk::Fence::null(); swapchain_size];
-        let mut v_semaphores_acquired_image = Vec::with_capacity(FRAME_COUNT);
-        let mut v_semaphores_pipeline_done = Vec::with_capacity(FRAME_COUNT);
+        let mut v_semaphores_acquired_image = Vec::with_capacity(present_settings.frames_in_flight);
+        let mut v_semaphores_pipeline_done = Vec::with_capacity(present_settings.frames_in_flight);
 
-        for _ in 0..FRAME_COUNT {
+        for _ in 0..present_settings.frames_in_flight {
             v_semaphores_acquired_image.push(
-                logical_device
-                    .create_semaphore(&semaphore_acquired_image_create_info, None)
-                    .expect("Cannot create sempahore"),
+                vkcore::objects::Semaphore::new(
+                    &logical_device,
+                    &semaphore_acquired_image_create_info,
+                )
+                .expect("Cannot create sempahore"),
             );
             v_semaphores_pipeline_done.push(
-                logical_device
-                    .create_semaphore(&semaphore_pipeline_done_create_info, None)
-                    .expect("Cannot create sempahore"),
+                vkcore::objects::Semaphore::new(
+                    &logical_device,
+                    &semaphore_pipeline_done_create_info,
+                )
+                .expect("Cannot create sempahore"),
             );
         }
 
@@ -1067,24 +723,146 @@ fn main() {
                 &glm::vec3(0.0, 0.0, 0.0),
                 &glm::vec3(0.0, 1.0, 0.0),
             ),
-            m_projection: glm::perspective(16.0f32 / 9.0f32, 45.0f32, 1.0f32, 100.0f32),
+            m_projection: glm::perspective(
+                extent.width as f32 / extent.height as f32,
+                45.0f32,
+                1.0f32,
+                100.0f32,
+            ),
         };
 
+        let mut shader_watcher =
+            vkcore::hot_reload::ShaderWatcher::new(&[VERTEX_SHADER_PATH, FRAGMENT_SHADER_PATH]);
+        let mut swapchain_outdated = false;
         while go {
-            go = handle_events(&mut event_pump);
+            go = handle_events(&mut event_pump, &mut swapchain_outdated);
+
+            if shader_watcher.poll() {
+                match vkcore::hot_reload::rebuild_graphics_pipeline(
+                    &logical_device,
+                    pipeline_cache.handle(),
+                    &graphics_pipeline_create_info,
+                    &[
+                        (ash::vk::ShaderStageFlags::VERTEX, VERTEX_SHADER_PATH),
+                        (ash::vk::ShaderStageFlags::FRAGMENT, FRAGMENT_SHADER_PATH),
+                    ],
+                    &shader_entry_name,
+                ) {
+                    Ok(pipeline) => {
+                        logical_device
+                            .device_wait_idle()
+                            .expect("Cannot wait for device idle");
+                        graphics_pipeline = pipeline;
+                        record_command_buffers(
+                            graphics_pipeline.handle(),
+                            &swapchain_framebuffers,
+                            &uniform_buffer,
+                        );
+                        println!("Shaders reloaded");
+                    }
+                    Err(e) => eprintln!(
+                        "Cannot reload shaders, keeping the previous pipeline: {:#}",
+                        e
+                    ),
+                }
+            }
+
+            let minimized = window.window_flags()
+                & sdl2::sys::SDL_WindowFlags::SDL_WINDOW_MINIMIZED as u32
+                != 0;
+            if minimized {
+                // Nothing to draw to until the window is restored.
+                std::thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            if swapchain_outdated {
+                logical_device
+                    .device_wait_idle()
+                    .expect("Cannot wait for device idle");
+                match swapchain_framebuffers
+                    .recreate(
+                        &surface,
+                        &swapchain_create_info,
+                        window.vulkan_drawable_size(),
+                        render_pass.handle(),
+                    )
+                    .expect("Cannot recreate swapchain")
+                {
+                    Some(new_swapchain_framebuffers) => {
+                        swapchain_framebuffers = new_swapchain_framebuffers;
+                        if swapchain_framebuffers.image_count() != swapchain_size {
+                            swapchain_size = swapchain_framebuffers.image_count();
+                            println!("Swapchain recreated with {} images", swapchain_size);
+                            uniform_buffer = vkcore::buffer::DynamicUniformBuffer::new(
+                                &logical_device,
+                                &allocator,
+                                swapchain_size,
+                            )
+                            .expect("Cannot create uniform buffer");
+                            let descriptor_buffer_info = uniform_buffer.descriptor_buffer_info();
+                            logical_device.update_descriptor_sets(
+                                &[ash::vk::WriteDescriptorSet {
+                                    p_buffer_info: &descriptor_buffer_info,
+                                    ..descriptor_write
+                                }],
+                                &[],
+                            );
+                            if let Some(pipeline_statistics) = &pipeline_statistics {
+                                pipeline_statistics
+                                    .resize(swapchain_size)
+                                    .expect("Cannot resize pipeline statistics");
+                            }
+                        }
+                        // The device is idle and the images are new: nothing to
+                        // wait for or to read back before reusing an image.
+                        v_fences_ref_wait_gpu = vec![ash::vk::Fence::null(); swapchain_size];
+                        record_command_buffers(
+                            graphics_pipeline.handle(),
+                            &swapchain_framebuffers,
+                            &uniform_buffer,
+                        );
+                        let extent = swapchain_framebuffers.extent();
+                        matrices.m_projection = glm::perspective(
+                            extent.width as f32 / extent.height as f32,
+                            45.0f32,
+                            1.0f32,
+                            100.0f32,
+                        );
+                        swapchain_outdated = false;
+                    }
+                    None => {
+                        std::thread::sleep(Duration::from_millis(100));
+                        continue;
+                    }
+                }
+            }
 
             logical_device
-                .wait_for_fences(&[v_fences_wait_gpu[current_frame]], true, !(0 as u64))
+                .wait_for_fences(
+                    &[v_fences_wait_gpu[current_frame].handle()],
+                    true,
+                    !(0 as u64),
+                )
                 .expect("Cannot wait for fences");
 
-            let infos_of_acquired_image = swapchain_loader
-                .acquire_next_image(
-                    swapchain,
+            let semaphore_acquired_image = v_semaphores_acquired_image[current_frame].handle();
+            let semaphore_pipeline_done = v_semaphores_pipeline_done[current_frame].handle();
+
+            let infos_of_acquired_image =
+                match swapchain_framebuffers.swapchain().acquire_next_image(
                     !(0 as u64),
-                    v_semaphores_acquired_image[current_frame],
+                    semaphore_acquired_image,
                     ash::vk::Fence::null(),
-                )
-                .expect("Cannot acquire next image");
+                ) {
+                    Ok(infos_of_acquired_image) => infos_of_acquired_image,
+                    Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                        swapchain_outdated = true;
+                        continue;
+                    }
+                    Err(e) => panic!("Cannot acquire next image: {}", e),
+                };
+            // Suboptimal: still presentable, recreated after this frame.
+            swapchain_outdated |= infos_of_acquired_image.1;
 
             let index_of_acquired_image = infos_of_acquired_image.0 as usize;
 
@@ -1096,52 +874,70 @@ fn main() {
                         !(0 as u64),
                     )
                     .expect("Cannot wait for fences");
+                if let Some(pipeline_statistics) = &pipeline_statistics {
+                    let counters = pipeline_statistics
+                        .collect(index_of_acquired_image)
+                        .expect("Cannot read pipeline statistics");
+                    if counters.is_some()
+                        && pipeline_statistics.frame_count() % pipeline_statistics.interval() as u64
+                            == 0
+                    {
+                        print!("{}", pipeline_statistics);
+                    }
+                }
             }
 
-            v_fences_ref_wait_gpu[index_of_acquired_image] = v_fences_wait_gpu[current_frame];
+            v_fences_ref_wait_gpu[index_of_acquired_image] =
+                v_fences_wait_gpu[current_frame].handle();
 
             logical_device
-                .reset_fences(&[v_fences_ref_wait_gpu[current_frame]])
+                .reset_fences(&[v_fences_wait_gpu[current_frame].handle()])
                 .expect("Cannot reset fences");
 
-            update_uniform_buffer(
-                &logical_device,
-                &v_memory_uniform_buffers[index_of_acquired_image],
-                &mut matrices,
-            );
+            update_uniform_buffer(&uniform_buffer, index_of_acquired_image, &mut matrices);
 
             let wait_stage_submit_info = ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
             let submit_info = ash::vk::SubmitInfo {
                 s_type: ash::vk::StructureType::SUBMIT_INFO,
                 p_next: std::ptr::null(),
                 wait_semaphore_count: 1,
-                p_wait_semaphores: &v_semaphores_acquired_image[current_frame],
+                p_wait_semaphores: &semaphore_acquired_image,
                 p_wait_dst_stage_mask: &wait_stage_submit_info
                     as *const ash::vk::PipelineStageFlags,
                 command_buffer_count: 1,
-                p_command_buffers: &v_command_buffers[index_of_acquired_image],
+                p_command_buffers: &swapchain_framebuffers.command_buffers()
+                    [index_of_acquired_image],
                 signal_semaphore_count: 1,
-                p_signal_semaphores: &v_semaphores_pipeline_done[current_frame],
+                p_signal_semaphores: &semaphore_pipeline_done,
             };
             logical_device
-                .queue_submit(queue, &[submit_info], v_fences_ref_wait_gpu[current_frame])
+                .queue_submit(
+                    queue,
+                    &[submit_info],
+                    v_fences_wait_gpu[current_frame].handle(),
+                )
                 .expect("Cannot submit queue");
 
-            let present_info = ash::vk::PresentInfoKHR {
-                s_type: ash::vk::StructureType::PRESENT_INFO_KHR,
-                p_next: std::ptr::null(),
-                wait_semaphore_count: 1,
-                p_wait_semaphores: &v_semaphores_pipeline_done[current_frame],
-                swapchain_count: 1,
-                p_swapchains: &swapchain,
-                p_image_indices: &infos_of_acquired_image.0,
-                p_results: std::ptr::null_mut(),
-            };
-            swapchain_loader
-                .queue_present(queue, &present_info)
-                .expect("Cannot present image");
+            match swapchain_framebuffers.swapchain().queue_present(
+                queue,
+                semaphore_pipeline_done,
+                infos_of_acquired_image.0,
+            ) {
+                Ok(suboptimal) => swapchain_outdated |= suboptimal,
+                Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => swapchain_outdated = true,
+                Err(e) => panic!("Cannot present image: {}", e),
+            }
 
-            current_frame = (current_frame + 1) % FRAME_COUNT;
+            current_frame = (current_frame + 1) % present_settings.frames_in_flight;
+        }
+
+        logical_device
+            .device_wait_idle()
+            .expect("Cannot wait for device idle");
+        if let Some(pipeline_statistics) = &pipeline_statistics {
+            pipeline_statistics
+                .flush()
+                .expect("Cannot write pipeline statistics");
         }
     }
 }
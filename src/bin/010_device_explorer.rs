@@ -0,0 +1,302 @@
+extern crate ash;
+extern crate termion;
+extern crate vkcore;
+
+use std::io::Write;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::IntoAlternateScreen;
+use vkcore::json::Json;
+
+const HELP: &str =
+    "up/down: move  enter/right: open  left/backspace: back  /: search  esc: clear search  q: quit";
+
+/// Browses the tree built by `vkcore::report::instance_report`: instance layers
+/// and extensions, then every device with its queue families, memory types and
+/// format features.
+struct Explorer {
+    root: Json,
+    // Index of the opened child at each level, and the selection it had.
+    path: Vec<(usize, usize)>,
+    selected: usize,
+    scroll: usize,
+    filter: String,
+    typing_filter: bool,
+}
+
+impl Explorer {
+    fn new(root: Json) -> Self {
+        Explorer {
+            root,
+            path: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            filter: String::new(),
+            typing_filter: false,
+        }
+    }
+
+    fn current(&self) -> &Json {
+        let mut node = &self.root;
+        for (index, _) in &self.path {
+            node = child(node, *index).1;
+        }
+        node
+    }
+
+    /// `(child index, label)` of the children matching the search filter.
+    fn visible_rows(&self) -> Vec<(usize, String)> {
+        let filter = self.filter.to_lowercase();
+        let node = self.current();
+        (0..child_count(node))
+            .map(|index| (index, row_label(child(node, index))))
+            .filter(|(_, label)| label.to_lowercase().contains(&filter))
+            .collect()
+    }
+
+    fn breadcrumb(&self) -> String {
+        let mut node = &self.root;
+        let mut names = vec!["vulkan".to_owned()];
+        for (index, _) in &self.path {
+            let (name, next) = child(node, *index);
+            names.push(name);
+            node = next;
+        }
+        names.join(" > ")
+    }
+
+    fn open(&mut self) {
+        let rows = self.visible_rows();
+        if let Some((index, _)) = rows.get(self.selected) {
+            if child_count(child(self.current(), *index).1) > 0 {
+                self.path.push((*index, self.selected));
+                self.selected = 0;
+                self.scroll = 0;
+                self.filter.clear();
+            }
+        }
+    }
+
+    fn back(&mut self) {
+        if let Some((_, selected)) = self.path.pop() {
+            self.selected = selected;
+            self.scroll = 0;
+            self.filter.clear();
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let count = self.visible_rows().len() as isize;
+        if count == 0 {
+            self.selected = 0;
+            return;
+        }
+        self.selected = (self.selected as isize + delta).max(0).min(count - 1) as usize;
+    }
+
+    /// Returns false once the user asked to quit.
+    fn handle_key(&mut self, key: Key, page_size: usize) -> bool {
+        if self.typing_filter {
+            match key {
+                Key::Char('\n') | Key::Esc => self.typing_filter = false,
+                Key::Backspace => {
+                    self.filter.pop();
+                }
+                Key::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            self.selected = 0;
+            self.scroll = 0;
+            return true;
+        }
+        match key {
+            Key::Char('q') | Key::Ctrl('c') => return false,
+            Key::Up | Key::Char('k') => self.move_selection(-1),
+            Key::Down | Key::Char('j') => self.move_selection(1),
+            Key::PageUp => self.move_selection(-(page_size as isize)),
+            Key::PageDown => self.move_selection(page_size as isize),
+            Key::Home => self.selected = 0,
+            Key::End => self.move_selection(isize::MAX / 2),
+            Key::Char('\n') | Key::Right | Key::Char('l') => self.open(),
+            Key::Left | Key::Backspace | Key::Char('h') => self.back(),
+            Key::Char('/') => {
+                self.typing_filter = true;
+                self.filter.clear();
+                self.selected = 0;
+            }
+            Key::Esc => {
+                self.filter.clear();
+                self.selected = 0;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw<W: Write>(&mut self, screen: &mut W, width: u16, height: u16) -> std::io::Result<()> {
+        let width = width as usize;
+        let page_size = (height as usize).saturating_sub(4).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + page_size {
+            self.scroll = self.selected + 1 - page_size;
+        }
+
+        write!(
+            screen,
+            "{}{}",
+            termion::clear::All,
+            termion::cursor::Goto(1, 1)
+        )?;
+        write!(
+            screen,
+            "{}{}{}",
+            termion::style::Bold,
+            truncate(&self.breadcrumb(), width),
+            termion::style::Reset
+        )?;
+        let search_line = if self.typing_filter {
+            format!("search: {}_", self.filter)
+        } else if !self.filter.is_empty() {
+            format!("search: {} (esc to clear)", self.filter)
+        } else {
+            String::new()
+        };
+        write!(
+            screen,
+            "{}{}",
+            termion::cursor::Goto(1, 2),
+            truncate(&search_line, width)
+        )?;
+
+        let rows = self.visible_rows();
+        for (line, (_, label)) in rows.iter().enumerate().skip(self.scroll).take(page_size) {
+            write!(
+                screen,
+                "{}",
+                termion::cursor::Goto(1, (line - self.scroll + 3) as u16)
+            )?;
+            if line == self.selected {
+                write!(
+                    screen,
+                    "{}{}{}",
+                    termion::style::Invert,
+                    truncate(label, width),
+                    termion::style::Reset
+                )?;
+            } else {
+                write!(screen, "{}", truncate(label, width))?;
+            }
+        }
+        write!(
+            screen,
+            "{}{}/{}  {}",
+            termion::cursor::Goto(1, height),
+            if rows.is_empty() {
+                0
+            } else {
+                self.selected + 1
+            },
+            rows.len(),
+            truncate(HELP, width.saturating_sub(12))
+        )?;
+        screen.flush()
+    }
+}
+
+fn child_count(node: &Json) -> usize {
+    match node {
+        Json::Object(members) => members.len(),
+        Json::Array(values) => values.len(),
+        _ => 0,
+    }
+}
+
+/// Name and value of the `index`-th child of an object or array.
+fn child(node: &Json, index: usize) -> (String, &Json) {
+    match node {
+        Json::Object(members) => (members[index].0.clone(), &members[index].1),
+        Json::Array(values) => {
+            let value = &values[index];
+            // Devices, extensions and formats are easier to find by name.
+            let name = ["name", "format"]
+                .iter()
+                .filter_map(|key| value.get(key))
+                .map(scalar_text)
+                .next()
+                .unwrap_or_else(|| format!("[{}]", index));
+            (name, value)
+        }
+        _ => unreachable!("scalars have no children"),
+    }
+}
+
+fn row_label((name, value): (String, &Json)) -> String {
+    match value {
+        Json::Object(members) => format!("{} {{{}}}", name, members.len()),
+        Json::Array(values) if values.iter().all(|v| child_count(v) == 0) => format!(
+            "{}: {}",
+            name,
+            values
+                .iter()
+                .map(scalar_text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Json::Array(values) => format!("{} [{}]", name, values.len()),
+        scalar => format!("{}: {}", name, scalar_text(scalar)),
+    }
+}
+
+fn scalar_text(value: &Json) -> String {
+    match value {
+        Json::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+fn main() {
+    let report = unsafe {
+        let entry = ash::Entry::new().expect("Cannot create entry");
+        // The validation layer and the messenger would print over the interface.
+        let instance = vkcore::instance::InstanceBuilder::new("010_device_explorer")
+            .without_layer(vkcore::instance::VALIDATION_LAYER_NAME)
+            .debug_messenger(None)
+            .build(&entry)
+            .expect("Cannot create instance");
+        vkcore::report::instance_report(&instance, None)
+    };
+
+    let mut explorer = Explorer::new(report);
+    let stdin = std::io::stdin();
+    let mut screen = std::io::stdout()
+        .into_raw_mode()
+        .expect("Cannot switch terminal to raw mode")
+        .into_alternate_screen()
+        .expect("Cannot switch to alternate screen");
+    write!(screen, "{}", termion::cursor::Hide).unwrap();
+
+    let (mut width, mut height) = termion::terminal_size().unwrap_or((80, 24));
+    explorer
+        .draw(&mut screen, width, height)
+        .expect("Cannot draw");
+    for key in stdin.keys() {
+        let key = key.expect("Cannot read key");
+        let page_size = (height as usize).saturating_sub(4).max(1);
+        if !explorer.handle_key(key, page_size) {
+            break;
+        }
+        let size = termion::terminal_size().unwrap_or((width, height));
+        width = size.0;
+        height = size.1;
+        explorer
+            .draw(&mut screen, width, height)
+            .expect("Cannot draw");
+    }
+    write!(screen, "{}", termion::cursor::Show).unwrap();
+}
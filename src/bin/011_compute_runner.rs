@@ -0,0 +1,224 @@
+extern crate ash;
+extern crate vkcore;
+
+use vkcore::compute::ComputeJob;
+
+const USAGE: &str =
+    "usage: 011_compute_runner <shader.spv|shader.comp> --buffer [<set>.]<binding>=<source> ...
+    [--push u32:<value>|i32:<value>|f32:<value> ...]
+    [--spec <constant_id>=u32:<value>|i32:<value>|f32:<value> ...] [--groups <x>[,<y>[,<z>]]]
+    [--entry <name>] [--output [<set>.]<binding>=<file> ...]
+    [--print [<set>.]<binding>=u32|i32|f32 ...] [--gpu <value>]
+    [--pipeline-cache-dir <dir>] [--no-pipeline-cache]
+
+buffer sources (<count> is a number of 32-bit elements):
+    file:<path>              contents of a file
+    zeros:<count>            zeroed buffer
+    iota-u32:<count>         0, 1, 2, ... as u32
+    iota-f32:<count>         0.0, 1.0, 2.0, ... as f32
+    fill-u32:<count>:<value> <value> repeated as u32
+    fill-f32:<count>:<value> <value> repeated as f32
+    random-f32:<count>       values in [0, 1), the same on every run";
+
+/// Parses `<binding>` (set 0) or `<set>.<binding>`.
+fn parse_binding(text: &str) -> (u32, u32) {
+    let parse = |value: &str| {
+        value
+            .parse::<u32>()
+            .unwrap_or_else(|_| panic!("Invalid binding {}\n{}", text, USAGE))
+    };
+    match text.split_once('.') {
+        Some((set, binding)) => (parse(set), parse(binding)),
+        None => (0, parse(text)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> T {
+    text.parse()
+        .unwrap_or_else(|_| panic!("Invalid number {}\n{}", text, USAGE))
+}
+
+/// Splits `<key>=<value>`.
+fn split_assignment(text: &str) -> (&str, &str) {
+    text.split_once('=')
+        .unwrap_or_else(|| panic!("Expected <binding>=<value>, got {}\n{}", text, USAGE))
+}
+
+fn words_to_bytes<T: vkcore::buffer::Pod>(words: Vec<T>) -> Vec<u8> {
+    vkcore::buffer::as_bytes(&words).to_vec()
+}
+
+fn generate_buffer(source: &str) -> Vec<u8> {
+    let (kind, arguments) = source
+        .split_once(':')
+        .unwrap_or_else(|| panic!("Invalid buffer source {}\n{}", source, USAGE));
+    if kind == "file" {
+        return std::fs::read(arguments)
+            .unwrap_or_else(|e| panic!("Cannot read {}: {}", arguments, e));
+    }
+    let (count, value) = match arguments.split_once(':') {
+        Some((count, value)) => (parse_number::<usize>(count), Some(value)),
+        None => (parse_number::<usize>(arguments), None),
+    };
+    match (kind, value) {
+        ("zeros", None) => vec![0; count * 4],
+        ("iota-u32", None) => words_to_bytes((0..count as u32).collect()),
+        ("iota-f32", None) => words_to_bytes((0..count).map(|i| i as f32).collect()),
+        ("fill-u32", Some(value)) => words_to_bytes(vec![parse_number::<u32>(value); count]),
+        ("fill-f32", Some(value)) => words_to_bytes(vec![parse_number::<f32>(value); count]),
+        ("random-f32", None) => {
+            // xorshift32, so runs can be compared with each other.
+            let mut state = 0x2545_f491u32;
+            words_to_bytes(
+                (0..count)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 17;
+                        state ^= state << 5;
+                        (state >> 8) as f32 / (1u32 << 24) as f32
+                    })
+                    .collect(),
+            )
+        }
+        _ => panic!("Invalid buffer source {}\n{}", source, USAGE),
+    }
+}
+
+fn print_buffer(set: u32, binding: u32, contents: &[u8], format: &str) {
+    println!("binding {}.{} ({} bytes):", set, binding, contents.len());
+    for (index, word) in contents.chunks_exact(4).enumerate() {
+        let word = [word[0], word[1], word[2], word[3]];
+        match format {
+            "u32" => println!("[{}] {}", index, u32::from_ne_bytes(word)),
+            "i32" => println!("[{}] {}", index, i32::from_ne_bytes(word)),
+            "f32" => println!("[{}] {}", index, f32::from_ne_bytes(word)),
+            _ => panic!("Invalid print format {}\n{}", format, USAGE),
+        }
+    }
+}
+
+fn main() {
+    let v_args: Vec<String> = std::env::args().skip(1).collect();
+    let shader_path = match v_args.first() {
+        Some(path) if !path.starts_with("--") => path.clone(),
+        _ => panic!("{}", USAGE),
+    };
+    let mut job = ComputeJob::new(&shader_path);
+    let mut v_bindings = Vec::new();
+    let mut v_outputs = Vec::new();
+    let mut v_prints = Vec::new();
+    let mut args = v_args[1..].iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().expect(USAGE).as_str();
+        match arg.as_str() {
+            "--buffer" => {
+                let (binding, source) = split_assignment(value());
+                let (set, binding) = parse_binding(binding);
+                job = job.buffer(set, binding, generate_buffer(source));
+                v_bindings.push((set, binding));
+            }
+            "--push" => {
+                let push = value();
+                job = match push.split_once(':') {
+                    Some(("u32", v)) => job.push_constant(parse_number::<u32>(v)),
+                    Some(("i32", v)) => job.push_constant(parse_number::<i32>(v)),
+                    Some(("f32", v)) => job.push_constant(parse_number::<f32>(v)),
+                    _ => panic!("Invalid push constant {}\n{}", push, USAGE),
+                };
+            }
+            "--spec" => {
+                let (constant_id, spec) = split_assignment(value());
+                let constant_id = parse_number::<u32>(constant_id);
+                job = match spec.split_once(':') {
+                    Some(("u32", v)) => {
+                        job.specialization_constant(constant_id, parse_number::<u32>(v))
+                    }
+                    Some(("i32", v)) => {
+                        job.specialization_constant(constant_id, parse_number::<i32>(v))
+                    }
+                    Some(("f32", v)) => {
+                        job.specialization_constant(constant_id, parse_number::<f32>(v))
+                    }
+                    _ => panic!("Invalid specialization constant {}\n{}", spec, USAGE),
+                };
+            }
+            "--groups" => {
+                let v_counts: Vec<u32> = value().split(',').map(parse_number).collect();
+                if v_counts.is_empty() || v_counts.len() > 3 {
+                    panic!("Invalid group count\n{}", USAGE);
+                }
+                let count = |axis: usize| v_counts.get(axis).copied().unwrap_or(1);
+                job = job.group_count(count(0), count(1), count(2));
+            }
+            "--entry" => job = job.entry_point(value()),
+            "--output" => {
+                let (binding, path) = split_assignment(value());
+                v_outputs.push((parse_binding(binding), path.to_owned()));
+            }
+            "--print" => {
+                let (binding, format) = split_assignment(value());
+                v_prints.push((parse_binding(binding), format.to_owned()));
+            }
+            // Read by the device selector.
+            "--gpu" => {
+                value();
+            }
+            _ if arg.starts_with("--gpu=") => {}
+            // Read by PipelineCacheOptions.
+            "--pipeline-cache-dir" => {
+                value();
+            }
+            "--no-pipeline-cache" => {}
+            _ => panic!("Unknown argument {}\n{}", arg, USAGE),
+        }
+    }
+    for (binding, _) in v_outputs.iter().chain(v_prints.iter()) {
+        if !v_bindings.contains(binding) {
+            panic!("No buffer at binding {}.{}", binding.0, binding.1);
+        }
+    }
+
+    unsafe {
+        let entry = ash::Entry::new().expect("Cannot create entry");
+        let instance = vkcore::instance::InstanceBuilder::new("011_compute_runner")
+            .build(&entry)
+            .expect("Instance creation error");
+        let selected_device = vkcore::selector::DeviceSelector::new(ash::vk::QueueFlags::COMPUTE)
+            .select(&instance)
+            .expect("Cannot select physical device");
+        let logical_device = vkcore::device::DeviceBuilder::new(
+            selected_device.gpu,
+            selected_device.index_of_queue_family,
+        )
+        .async_compute_queue(true)
+        .build(&instance)
+        .expect("Cannot create device");
+        let allocator = vkcore::allocator::DeviceMemoryAllocator::new(&logical_device);
+        let pipeline_cache = vkcore::pipeline_cache::DiskPipelineCache::load(
+            &logical_device,
+            "011_compute_runner",
+            &vkcore::pipeline_cache::PipelineCacheOptions::from_args(),
+        )
+        .expect("Cannot load pipeline cache");
+        let v_contents = job
+            .run(&logical_device, &allocator, &pipeline_cache)
+            .unwrap_or_else(|e| panic!("Cannot run {}: {:#}", shader_path, e));
+        println!("{}", pipeline_cache);
+        if let Err(e) = pipeline_cache.save() {
+            eprintln!("Cannot save pipeline cache: {:#}", e);
+        }
+
+        let contents_of = |binding: &(u32, u32)| {
+            let index = v_bindings.iter().position(|b| b == binding).unwrap();
+            &v_contents[index]
+        };
+        for (binding, path) in &v_outputs {
+            std::fs::write(path, contents_of(binding))
+                .unwrap_or_else(|e| panic!("Cannot write {}: {}", path, e));
+            println!("binding {}.{} written to {}", binding.0, binding.1, path);
+        }
+        for (binding, format) in &v_prints {
+            print_buffer(binding.0, binding.1, contents_of(binding), format);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+extern crate ash;
+extern crate vkcore;
+
+use vkcore::primitives::cpu;
+use vkcore::primitives::Primitives;
+use vkcore::primitives::ReduceOp;
+use vkcore::primitives::ScanKind;
+use vkcore::verify::Comparison;
+use vkcore::verify::VerifyOptions;
+
+const USAGE: &str = "usage: 012_parallel_primitives [--count <values>] [--bins <count>]
+    [--verify --max-mismatches <n>] [--gpu <value>]
+    [--pipeline-cache-dir <dir>] [--no-pipeline-cache]";
+
+/// Values in `[0, 2^24)`, the same on every run (xorshift32).
+fn generate_values(count: usize) -> Vec<u32> {
+    let mut state = 0x2545_f491u32;
+    (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state >> 8
+        })
+        .collect()
+}
+
+fn parse_argument(name: &str, default: usize) -> usize {
+    let v_args: Vec<String> = std::env::args().collect();
+    match v_args.iter().position(|arg| arg == name) {
+        Some(index) => v_args
+            .get(index + 1)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(|| panic!("Invalid value after {}\n{}", name, USAGE)),
+        None => default,
+    }
+}
+
+fn report<T: std::fmt::Display>(comparison: Comparison<T>, elapsed: std::time::Duration) -> bool {
+    print!("{}", comparison);
+    println!("  {:.2?} on the GPU", elapsed);
+    comparison.is_ok()
+}
+
+fn main() {
+    let count = parse_argument("--count", 1_000_000);
+    let bin_count = parse_argument("--bins", 64) as u32;
+    // The results are always checked, `--verify` is only needed to pass options.
+    let verify_options = VerifyOptions::from_args().unwrap_or(VerifyOptions {
+        tolerance: Default::default(),
+        max_reported: 10,
+    });
+    let v_values = generate_values(count);
+    let bin_width = ((1u32 << 24) / bin_count.max(1)).max(1);
+
+    let success = unsafe {
+        let entry = ash::Entry::new().expect("Cannot create entry");
+        let instance = vkcore::instance::InstanceBuilder::new("012_parallel_primitives")
+            .build(&entry)
+            .expect("Instance creation error");
+        let selected_device = vkcore::selector::DeviceSelector::new(ash::vk::QueueFlags::COMPUTE)
+            .select(&instance)
+            .expect("Cannot select physical device");
+        let logical_device = vkcore::device::DeviceBuilder::new(
+            selected_device.gpu,
+            selected_device.index_of_queue_family,
+        )
+        .async_compute_queue(true)
+        .build(&instance)
+        .expect("Cannot create device");
+        let allocator = vkcore::allocator::DeviceMemoryAllocator::new(&logical_device);
+        let pipeline_cache = vkcore::pipeline_cache::DiskPipelineCache::load(
+            &logical_device,
+            "012_parallel_primitives",
+            &vkcore::pipeline_cache::PipelineCacheOptions::from_args(),
+        )
+        .expect("Cannot load pipeline cache");
+        let primitives = Primitives::new(&logical_device, &allocator, &pipeline_cache)
+            .expect("Cannot create primitives");
+        println!("{}", pipeline_cache);
+        if let Err(e) = pipeline_cache.save() {
+            eprintln!("Cannot save pipeline cache: {:#}", e);
+        }
+        println!("{} values", count);
+
+        let mut success = true;
+        for &op in &[ReduceOp::Add, ReduceOp::Min, ReduceOp::Max] {
+            let start = std::time::Instant::now();
+            let result = primitives.reduce(&v_values, op).expect("Cannot run reduce");
+            let elapsed = start.elapsed();
+            let comparison = vkcore::verify::compare(
+                &format!("reduce {:?}", op),
+                &[cpu::reduce(&v_values, op)],
+                &[result],
+                &verify_options,
+            );
+            success &= report(comparison, elapsed);
+        }
+        for &kind in &[ScanKind::Inclusive, ScanKind::Exclusive] {
+            let start = std::time::Instant::now();
+            let v_result = primitives.scan(&v_values, kind).expect("Cannot run scan");
+            let elapsed = start.elapsed();
+            let comparison = vkcore::verify::compare(
+                &format!("scan {:?}", kind),
+                &cpu::scan(&v_values, kind),
+                &v_result,
+                &verify_options,
+            );
+            success &= report(comparison, elapsed);
+        }
+        let start = std::time::Instant::now();
+        let v_result = primitives.sort(&v_values).expect("Cannot run sort");
+        let elapsed = start.elapsed();
+        let comparison =
+            vkcore::verify::compare("sort", &cpu::sort(&v_values), &v_result, &verify_options);
+        success &= report(comparison, elapsed);
+        let start = std::time::Instant::now();
+        let v_result = primitives
+            .histogram(&v_values, bin_count, bin_width)
+            .expect("Cannot run histogram");
+        let elapsed = start.elapsed();
+        let comparison = vkcore::verify::compare(
+            "histogram",
+            &cpu::histogram(&v_values, bin_count, bin_width).expect("Cannot compute histogram"),
+            &v_result,
+            &verify_options,
+        );
+        success &= report(comparison, elapsed);
+        success
+    };
+    if !success {
+        std::process::exit(1);
+    }
+}
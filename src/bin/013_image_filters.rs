@@ -0,0 +1,181 @@
+extern crate ash;
+extern crate jpeg_decoder as jpeg;
+extern crate png;
+extern crate vkcore;
+
+use vkcore::image_filter::Filter;
+use vkcore::image_filter::ImageFilters;
+use vkcore::image_filter::Lut;
+use vkcore::image_filter::RgbaImage;
+
+const USAGE: &str =
+    "usage: 013_image_filters <input.jpg|input.png> <output.png> [<filter> ...] [--gpu <value>]
+    [--pipeline-cache-dir <dir>] [--no-pipeline-cache]
+
+filters, applied in order:
+    grayscale
+    blur:<radius>[:<sigma>]    gaussian blur, sigma defaults to radius / 2
+    sobel                      edge detection
+    lut:<file.cube>            color grading with a 3D LUT (e.g. textures/sepia.cube)
+    resize:<width>x<height>    bilinear resize";
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> T {
+    text.parse()
+        .unwrap_or_else(|_| panic!("Invalid number {}\n{}", text, USAGE))
+}
+
+fn parse_filter(text: &str) -> Filter {
+    // The path may contain ':' itself.
+    if let Some(path) = text.strip_prefix("lut:") {
+        return Filter::ColorGrade(Lut::load(path).unwrap_or_else(|e| panic!("{:#}", e)));
+    }
+    let mut v_words = text.split(':');
+    match (v_words.next().unwrap(), v_words.next(), v_words.next()) {
+        ("grayscale", None, None) => Filter::Grayscale,
+        ("sobel", None, None) => Filter::Sobel,
+        ("blur", Some(radius), sigma) => {
+            let radius = parse_number::<u32>(radius);
+            let sigma = match sigma {
+                Some(sigma) => parse_number::<f32>(sigma),
+                None => (radius as f32 / 2.0).max(0.5),
+            };
+            Filter::GaussianBlur { radius, sigma }
+        }
+        ("resize", Some(size), None) => match size.split_once('x') {
+            Some((width, height)) => Filter::Resize {
+                width: parse_number(width),
+                height: parse_number(height),
+            },
+            None => panic!("Invalid size {}\n{}", size, USAGE),
+        },
+        _ => panic!("Invalid filter {}\n{}", text, USAGE),
+    }
+}
+
+fn load_image(path: &str) -> RgbaImage {
+    let file = std::fs::File::open(path).unwrap_or_else(|e| panic!("Cannot open {}: {}", path, e));
+    let lowercase_path = path.to_lowercase();
+    let (width, height, samples, data) = if lowercase_path.ends_with(".png") {
+        let (info, mut reader) = png::Decoder::new(file)
+            .read_info()
+            .unwrap_or_else(|e| panic!("Cannot decode {}: {}", path, e));
+        let mut data = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut data)
+            .unwrap_or_else(|e| panic!("Cannot decode {}: {}", path, e));
+        (info.width, info.height, info.color_type.samples(), data)
+    } else {
+        let mut decoder = jpeg::Decoder::new(std::io::BufReader::new(file));
+        let data = decoder
+            .decode()
+            .unwrap_or_else(|e| panic!("Cannot decode {}: {}", path, e));
+        let info = decoder.info().unwrap();
+        let samples = match info.pixel_format {
+            jpeg::PixelFormat::L8 => 1,
+            jpeg::PixelFormat::RGB24 => 3,
+            jpeg::PixelFormat::CMYK32 => panic!("CMYK images are not supported"),
+        };
+        (info.width as u32, info.height as u32, samples, data)
+    };
+    // Gray, gray + alpha, RGB or RGBA samples to RGBA.
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for pixel in data.chunks_exact(samples) {
+        match samples {
+            1 => pixels.extend_from_slice(&[pixel[0], pixel[0], pixel[0], 255]),
+            2 => pixels.extend_from_slice(&[pixel[0], pixel[0], pixel[0], pixel[1]]),
+            3 => pixels.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]),
+            _ => pixels.extend_from_slice(pixel),
+        }
+    }
+    RgbaImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+fn save_png(path: &str, image: &RgbaImage) {
+    let file =
+        std::fs::File::create(path).unwrap_or_else(|e| panic!("Cannot create {}: {}", path, e));
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&image.pixels))
+        .unwrap_or_else(|e| panic!("Cannot write {}: {}", path, e));
+}
+
+fn main() {
+    let mut v_positionals = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Read by the device selector.
+            "--gpu" => {
+                args.next();
+            }
+            _ if arg.starts_with("--gpu=") => {}
+            // Read by PipelineCacheOptions.
+            "--pipeline-cache-dir" => {
+                args.next();
+            }
+            "--no-pipeline-cache" => {}
+            _ if arg.starts_with("--") => panic!("Unknown argument {}\n{}", arg, USAGE),
+            _ => v_positionals.push(arg),
+        }
+    }
+    if v_positionals.len() < 2 {
+        panic!("{}", USAGE);
+    }
+    let v_filters: Vec<Filter> = v_positionals[2..]
+        .iter()
+        .map(|text| parse_filter(text))
+        .collect();
+    let input_image = load_image(&v_positionals[0]);
+
+    unsafe {
+        let entry = ash::Entry::new().expect("Cannot create entry");
+        let instance = vkcore::instance::InstanceBuilder::new("013_image_filters")
+            .build(&entry)
+            .expect("Instance creation error");
+        let selected_device = vkcore::selector::DeviceSelector::new(ash::vk::QueueFlags::COMPUTE)
+            .select(&instance)
+            .expect("Cannot select physical device");
+        let logical_device = vkcore::device::DeviceBuilder::new(
+            selected_device.gpu,
+            selected_device.index_of_queue_family,
+        )
+        .dedicated_transfer_queue(true)
+        .async_compute_queue(true)
+        .build(&instance)
+        .expect("Cannot create device");
+        let allocator = vkcore::allocator::DeviceMemoryAllocator::new(&logical_device);
+        let pipeline_cache = vkcore::pipeline_cache::DiskPipelineCache::load(
+            &logical_device,
+            "013_image_filters",
+            &vkcore::pipeline_cache::PipelineCacheOptions::from_args(),
+        )
+        .expect("Cannot load pipeline cache");
+        let image_filters = ImageFilters::new(&logical_device, &allocator, &pipeline_cache)
+            .expect("Cannot create image filters");
+        println!("{}", pipeline_cache);
+        if let Err(e) = pipeline_cache.save() {
+            eprintln!("Cannot save pipeline cache: {:#}", e);
+        }
+        let start = std::time::Instant::now();
+        let output_image = image_filters
+            .apply(&input_image, &v_filters)
+            .unwrap_or_else(|e| panic!("Cannot apply filters: {:#}", e));
+        println!(
+            "{} filter(s) applied in {:.2?}, {}x{} -> {}x{}",
+            v_filters.len(),
+            start.elapsed(),
+            input_image.width,
+            input_image.height,
+            output_image.width,
+            output_image.height
+        );
+        save_png(&v_positionals[1], &output_image);
+    }
+}
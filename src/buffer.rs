@@ -0,0 +1,323 @@
+//! Buffers of `T` with typed, scoped access to their memory.
+
+use crate::allocator::Allocation;
+use crate::allocator::DeviceMemoryAllocator;
+use crate::device::Device;
+use crate::objects::Buffer;
+use crate::objects::Fence;
+use anyhow::bail;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::rc::Rc;
+
+/// Types whose every bit pattern is valid and which have no padding or
+/// pointers, so they can be copied byte for byte to and from the GPU.
+///
+/// Implement it for `#[repr(C)]` structs made of `Pod` fields, without padding.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid value of the type, and the type must have
+/// no padding bytes, references or pointers: buffers are read back into it
+/// byte for byte.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+pod!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Views `data` as raw bytes, e.g. to fill push constants.
+pub fn as_bytes<T: Pod>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+
+/// A `vk::Buffer` holding `len` elements of `T`, bound to its own allocation.
+pub struct TypedBuffer<T: Pod> {
+    buffer: Buffer,
+    allocation: Allocation,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Pod> TypedBuffer<T> {
+    /// # Safety
+    ///
+    /// `allocator` must have been created for `device`.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        allocator: &DeviceMemoryAllocator,
+        len: usize,
+        usage: vk::BufferUsageFlags,
+        properties: vk::MemoryPropertyFlags,
+    ) -> anyhow::Result<Self> {
+        if len == 0 || std::mem::size_of::<T>() == 0 {
+            bail!("Cannot create an empty buffer");
+        }
+        let buffer_create_info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::BufferCreateFlags::empty(),
+            size: (len * std::mem::size_of::<T>()) as u64,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: std::ptr::null(),
+        };
+        let buffer = Buffer::new(device, &buffer_create_info)?;
+        let allocation = allocator.allocate_for_buffer(buffer.handle(), properties)?;
+        Ok(TypedBuffer {
+            buffer,
+            allocation,
+            len,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Creates a host visible buffer filled with `data`, which must not be empty.
+    ///
+    /// # Safety
+    ///
+    /// `allocator` must have been created for `device`.
+    pub unsafe fn from_slice(
+        device: &Rc<Device>,
+        allocator: &DeviceMemoryAllocator,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> anyhow::Result<Self> {
+        let mut buffer = TypedBuffer::new(
+            device,
+            allocator,
+            data.len(),
+            usage,
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+        buffer.map_unchecked()?.copy_from_slice(data);
+        Ok(buffer)
+    }
+
+    pub fn handle(&self) -> vk::Buffer {
+        self.buffer.handle()
+    }
+
+    pub fn allocation(&self) -> &Allocation {
+        &self.allocation
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn size_in_bytes(&self) -> vk::DeviceSize {
+        (self.len * std::mem::size_of::<T>()) as vk::DeviceSize
+    }
+
+    /// Maps the buffer to read what the device wrote. `done` is the fence of
+    /// the last submission using the buffer; it must be signaled.
+    pub fn map_read(&self, done: &Fence) -> anyhow::Result<MappedRead<'_, T>> {
+        check_signaled(done)?;
+        unsafe {
+            let p_data = self.allocation.map()?;
+            if let Err(e) = self.allocation.invalidate() {
+                self.allocation.unmap();
+                return Err(e);
+            }
+            Ok(MappedRead {
+                allocation: &self.allocation,
+                data: std::slice::from_raw_parts(p_data as *const T, self.len),
+            })
+        }
+    }
+
+    /// Maps the buffer to write it; the writes are flushed when the guard is
+    /// dropped. `done` is the fence of the last submission using the buffer;
+    /// it must be signaled.
+    pub fn map_write(&mut self, done: &Fence) -> anyhow::Result<MappedWrite<'_, T>> {
+        check_signaled(done)?;
+        unsafe { self.map_unchecked() }
+    }
+
+    /// # Safety
+    ///
+    /// The device must not be accessing the buffer while the guard is alive.
+    unsafe fn map_unchecked(&mut self) -> anyhow::Result<MappedWrite<'_, T>> {
+        let p_data = self.allocation.map()?;
+        Ok(MappedWrite {
+            allocation: &self.allocation,
+            data: std::slice::from_raw_parts_mut(p_data as *mut T, self.len),
+        })
+    }
+}
+
+/// Fails unless `fence` is signaled, i.e. the device is done with the
+/// submission it guards.
+fn check_signaled(fence: &Fence) -> anyhow::Result<()> {
+    if !fence.is_signaled()? {
+        bail!("Cannot map a buffer the device may still be using: the fence is not signaled");
+    }
+    Ok(())
+}
+
+/// Read-only view of a mapped `TypedBuffer`, unmapped on drop.
+pub struct MappedRead<'a, T: Pod> {
+    allocation: &'a Allocation,
+    data: &'a [T],
+}
+
+impl<'a, T: Pod> Deref for MappedRead<'a, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.data
+    }
+}
+
+impl<'a, T: Pod> Drop for MappedRead<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.allocation.unmap();
+        }
+    }
+}
+
+/// Writable view of a mapped `TypedBuffer`, flushed and unmapped on drop.
+pub struct MappedWrite<'a, T: Pod> {
+    allocation: &'a Allocation,
+    data: &'a mut [T],
+}
+
+impl<'a, T: Pod> Deref for MappedWrite<'a, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.data
+    }
+}
+
+impl<'a, T: Pod> DerefMut for MappedWrite<'a, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.data
+    }
+}
+
+impl<'a, T: Pod> Drop for MappedWrite<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = self.allocation.flush() {
+                eprintln!("Cannot flush mapped memory: {}", e);
+            }
+            self.allocation.unmap();
+        }
+    }
+}
+
+/// Ring of `slot_count` values of `T` in one persistently mapped uniform buffer,
+/// bound once as `UNIFORM_BUFFER_DYNAMIC` and selected with a dynamic offset.
+///
+/// Each frame in flight writes its own slot, so a slot must not be rewritten
+/// before the GPU is done with the frame that read it.
+pub struct DynamicUniformBuffer<T: Pod> {
+    buffer: Buffer,
+    allocation: Allocation,
+    p_data: *mut u8,
+    stride: vk::DeviceSize,
+    slot_count: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Pod> DynamicUniformBuffer<T> {
+    /// # Safety
+    ///
+    /// `allocator` must have been created for `device`.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        allocator: &DeviceMemoryAllocator,
+        slot_count: usize,
+    ) -> anyhow::Result<Self> {
+        let limits = device
+            .instance()
+            .get_physical_device_properties(device.gpu())
+            .limits;
+        let alignment = limits.min_uniform_buffer_offset_alignment.max(1);
+        let stride = (std::mem::size_of::<T>() as vk::DeviceSize).div_ceil(alignment) * alignment;
+        let buffer_create_info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::BufferCreateFlags::empty(),
+            size: stride * slot_count as vk::DeviceSize,
+            usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: std::ptr::null(),
+        };
+        let buffer = Buffer::new(device, &buffer_create_info)?;
+        let allocation = allocator
+            .allocate_for_buffer(buffer.handle(), vk::MemoryPropertyFlags::HOST_VISIBLE)?;
+        let p_data = allocation.map()?;
+        Ok(DynamicUniformBuffer {
+            buffer,
+            allocation,
+            p_data,
+            stride,
+            slot_count,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn handle(&self) -> vk::Buffer {
+        self.buffer.handle()
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slot_count
+    }
+
+    /// Offset to pass to `cmd_bind_descriptor_sets` to read `slot`.
+    pub fn dynamic_offset(&self, slot: usize) -> u32 {
+        (self.stride * slot as vk::DeviceSize) as u32
+    }
+
+    /// Describes one slot, for a `UNIFORM_BUFFER_DYNAMIC` descriptor.
+    pub fn descriptor_buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: self.buffer.handle(),
+            offset: 0,
+            range: std::mem::size_of::<T>() as vk::DeviceSize,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The device must not be reading `slot`, e.g. the command buffer using it has been waited for.
+    pub unsafe fn write(&self, slot: usize, value: &T) -> anyhow::Result<()> {
+        assert!(slot < self.slot_count, "Uniform buffer slot out of range");
+        let offset = self.stride * slot as vk::DeviceSize;
+        std::ptr::copy_nonoverlapping(
+            value as *const T,
+            self.p_data.add(offset as usize) as *mut T,
+            1,
+        );
+        self.allocation
+            .flush_range(offset, std::mem::size_of::<T>() as vk::DeviceSize)
+    }
+}
+
+impl<T: Pod> Drop for DynamicUniformBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.allocation.unmap();
+        }
+    }
+}
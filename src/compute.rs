@@ -0,0 +1,338 @@
+//! Runs a compute shader once over a set of storage buffers.
+//!
+//! A `ComputeJob` describes everything a kernel needs (SPIR-V file, buffers and
+//! their bindings, push and specialization constants, group counts); `run`
+//! builds the pipeline, uploads the buffers, dispatches on the compute queue
+//! and returns the contents of every buffer once the device is done. The
+//! buffers are only used by that queue, so they need no ownership transfer.
+
+use crate::allocator::DeviceMemoryAllocator;
+use crate::buffer::Pod;
+use crate::buffer::TypedBuffer;
+use crate::device::Device;
+use crate::objects::DescriptorPool;
+use crate::objects::DescriptorSetLayout;
+use crate::pipeline::create_compute_pipeline;
+use crate::pipeline::create_pipeline_layout;
+use crate::pipeline::SpecializationConstants;
+use crate::pipeline_cache::DiskPipelineCache;
+use crate::transfer::submit_one_time;
+use anyhow::bail;
+use anyhow::Context;
+use ash::version::DeviceV1_0;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::ffi::CString;
+use std::rc::Rc;
+
+/// Storage buffer bound at `set`/`binding`, initialized with `contents`.
+#[derive(Clone, Debug)]
+pub struct StorageBuffer {
+    pub set: u32,
+    pub binding: u32,
+    pub contents: Vec<u8>,
+}
+
+pub struct ComputeJob {
+    shader_path: String,
+    entry_point: String,
+    buffers: Vec<StorageBuffer>,
+    push_constants: Vec<u8>,
+    specialization_constants: SpecializationConstants,
+    group_count: [u32; 3],
+}
+
+impl ComputeJob {
+    pub fn new(shader_path: &str) -> Self {
+        ComputeJob {
+            shader_path: shader_path.to_owned(),
+            entry_point: "main".to_owned(),
+            buffers: Vec::new(),
+            push_constants: Vec::new(),
+            specialization_constants: SpecializationConstants::new(),
+            group_count: [1, 1, 1],
+        }
+    }
+
+    pub fn entry_point(mut self, name: &str) -> Self {
+        self.entry_point = name.to_owned();
+        self
+    }
+
+    pub fn buffer(mut self, set: u32, binding: u32, contents: Vec<u8>) -> Self {
+        self.buffers.push(StorageBuffer {
+            set,
+            binding,
+            contents,
+        });
+        self
+    }
+
+    /// Appends `value` to the push constant block, right after the previous ones.
+    pub fn push_constant<T: Pod>(mut self, value: T) -> Self {
+        self.push_constants
+            .extend_from_slice(crate::buffer::as_bytes(std::slice::from_ref(&value)));
+        self
+    }
+
+    /// Sets the specialization constant `constant_id`, e.g. the workgroup size.
+    pub fn specialization_constant<T: Pod>(mut self, constant_id: u32, value: T) -> Self {
+        self.specialization_constants = self.specialization_constants.set(constant_id, value);
+        self
+    }
+
+    pub fn group_count(mut self, x: u32, y: u32, z: u32) -> Self {
+        self.group_count = [x, y, z];
+        self
+    }
+
+    /// Checks the job against the device limits before creating anything.
+    unsafe fn validate(&self, device: &Device) -> anyhow::Result<()> {
+        let limits = device
+            .instance()
+            .get_physical_device_properties(device.gpu())
+            .limits;
+        if self.buffers.is_empty() {
+            bail!("A compute job needs at least one storage buffer");
+        }
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            if buffer.contents.is_empty() {
+                bail!("Storage buffer {}.{} is empty", buffer.set, buffer.binding);
+            }
+            if buffer.contents.len() as u64 > limits.max_storage_buffer_range as u64 {
+                bail!(
+                    "Storage buffer {}.{} is {} bytes, the device supports {}",
+                    buffer.set,
+                    buffer.binding,
+                    buffer.contents.len(),
+                    limits.max_storage_buffer_range
+                );
+            }
+            if self.buffers[..index]
+                .iter()
+                .any(|other| (other.set, other.binding) == (buffer.set, buffer.binding))
+            {
+                bail!("Binding {}.{} is used twice", buffer.set, buffer.binding);
+            }
+        }
+        let max_set = self.buffers.iter().map(|b| b.set).max().unwrap_or(0);
+        if max_set >= limits.max_bound_descriptor_sets {
+            bail!(
+                "Set {} is out of range, the device binds up to {} sets",
+                max_set,
+                limits.max_bound_descriptor_sets
+            );
+        }
+        if !self.push_constants.len().is_multiple_of(4)
+            || self.push_constants.len() > limits.max_push_constants_size as usize
+        {
+            bail!(
+                "Push constants are {} bytes, they must be a multiple of 4 and at most {}",
+                self.push_constants.len(),
+                limits.max_push_constants_size
+            );
+        }
+        for (axis, (count, max)) in self
+            .group_count
+            .iter()
+            .zip(limits.max_compute_work_group_count.iter())
+            .enumerate()
+        {
+            if *count == 0 || count > max {
+                bail!(
+                    "Group count {} on axis {} must be between 1 and {}",
+                    count,
+                    axis,
+                    max
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Dispatches the job and returns the buffers' contents, in the order they
+    /// were added. The pipeline is created through `pipeline_cache`.
+    ///
+    /// # Safety
+    ///
+    /// `allocator` must have been created for `device`, and `pipeline_cache` too.
+    pub unsafe fn run(
+        &self,
+        device: &Rc<Device>,
+        allocator: &DeviceMemoryAllocator,
+        pipeline_cache: &DiskPipelineCache,
+    ) -> anyhow::Result<Vec<Vec<u8>>> {
+        self.validate(device)?;
+
+        // Sets must be contiguous in the pipeline layout, unused ones stay empty.
+        let set_count = self.buffers.iter().map(|b| b.set).max().unwrap_or(0) + 1;
+        let mut descriptor_set_layouts = Vec::with_capacity(set_count as usize);
+        for set in 0..set_count {
+            let v_bindings: Vec<vk::DescriptorSetLayoutBinding> = self
+                .buffers
+                .iter()
+                .filter(|buffer| buffer.set == set)
+                .map(|buffer| vk::DescriptorSetLayoutBinding {
+                    binding: buffer.binding,
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    p_immutable_samplers: std::ptr::null(),
+                })
+                .collect();
+            let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+                s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                binding_count: v_bindings.len() as u32,
+                p_bindings: v_bindings.as_ptr(),
+            };
+            descriptor_set_layouts.push(DescriptorSetLayout::new(
+                device,
+                &descriptor_set_layout_create_info,
+            )?);
+        }
+        let v_descriptor_set_layout_handles: Vec<vk::DescriptorSetLayout> = descriptor_set_layouts
+            .iter()
+            .map(DescriptorSetLayout::handle)
+            .collect();
+
+        let v_push_constant_ranges: &[vk::PushConstantRange] = if self.push_constants.is_empty() {
+            &[]
+        } else {
+            &[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: self.push_constants.len() as u32,
+            }]
+        };
+        let pipeline_layout = create_pipeline_layout(
+            device,
+            &v_descriptor_set_layout_handles,
+            v_push_constant_ranges,
+        )?;
+
+        let shader_module = crate::shader::create_shader_module(device, &self.shader_path)?;
+        let entry_point = CString::new(self.entry_point.as_str())
+            .with_context(|| format!("Invalid entry point {:?}", self.entry_point))?;
+        let pipeline = pipeline_cache
+            .timed(|| {
+                create_compute_pipeline(
+                    device,
+                    pipeline_cache.handle(),
+                    pipeline_layout.handle(),
+                    &shader_module,
+                    &entry_point,
+                    &self.specialization_constants,
+                )
+            })
+            .with_context(|| format!("Cannot create compute pipeline from {}", self.shader_path))?;
+
+        let descriptor_pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: self.buffers.len() as u32,
+        };
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            max_sets: set_count,
+            pool_size_count: 1,
+            p_pool_sizes: &descriptor_pool_size,
+        };
+        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_create_info)?;
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+            p_next: std::ptr::null(),
+            descriptor_pool: descriptor_pool.handle(),
+            descriptor_set_count: set_count,
+            p_set_layouts: v_descriptor_set_layout_handles.as_ptr(),
+        };
+        let v_descriptor_sets = device.allocate_descriptor_sets(&descriptor_set_allocate_info)?;
+
+        let mut storage_buffers = Vec::with_capacity(self.buffers.len());
+        for buffer in &self.buffers {
+            storage_buffers.push(TypedBuffer::<u8>::from_slice(
+                device,
+                allocator,
+                &buffer.contents,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+            )?);
+        }
+        let v_descriptor_buffer_infos: Vec<vk::DescriptorBufferInfo> = storage_buffers
+            .iter()
+            .map(|buffer| vk::DescriptorBufferInfo {
+                buffer: buffer.handle(),
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            })
+            .collect();
+        let v_descriptor_writes: Vec<vk::WriteDescriptorSet> = self
+            .buffers
+            .iter()
+            .zip(v_descriptor_buffer_infos.iter())
+            .map(|(buffer, descriptor_buffer_info)| vk::WriteDescriptorSet {
+                s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                p_next: std::ptr::null(),
+                dst_set: v_descriptor_sets[buffer.set as usize],
+                dst_binding: buffer.binding,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                p_image_info: std::ptr::null(),
+                p_buffer_info: descriptor_buffer_info,
+                p_texel_buffer_view: std::ptr::null(),
+            })
+            .collect();
+        device.update_descriptor_sets(&v_descriptor_writes, &[]);
+
+        let fence = submit_one_time(device, device.compute_queue(), |command_buffer| {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.handle(),
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline_layout.handle(),
+                0,
+                &v_descriptor_sets,
+                &[],
+            );
+            if !self.push_constants.is_empty() {
+                device.cmd_push_constants(
+                    command_buffer,
+                    pipeline_layout.handle(),
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    &self.push_constants,
+                );
+            }
+            let [x, y, z] = self.group_count;
+            device.cmd_dispatch(command_buffer, x, y, z);
+            // Make the shader writes visible to the mapped reads below.
+            let memory_barrier = vk::MemoryBarrier {
+                s_type: vk::StructureType::MEMORY_BARRIER,
+                p_next: std::ptr::null(),
+                src_access_mask: vk::AccessFlags::SHADER_WRITE,
+                dst_access_mask: vk::AccessFlags::HOST_READ,
+            };
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST,
+                Default::default(),
+                &[memory_barrier],
+                &[],
+                &[],
+            );
+        })?;
+
+        let mut outputs = Vec::with_capacity(storage_buffers.len());
+        for buffer in &storage_buffers {
+            outputs.push(buffer.map_read(&fence)?.to_vec());
+        }
+        Ok(outputs)
+    }
+}
@@ -0,0 +1,437 @@
+//! `VK_EXT_debug_utils` messenger routing validation messages to a sink.
+//!
+//! The messenger is owned by the `Instance` (see `InstanceBuilder::debug_messenger`)
+//! and its create info is also chained into `vkCreateInstance`, so messages
+//! emitted while the instance itself is created or destroyed are captured too.
+
+use anyhow::Context;
+use ash::extensions::ext::DebugUtils;
+use ash::vk;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::fmt;
+use std::io::Write;
+use std::os::raw::c_char;
+use std::os::raw::c_void;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Messages kept in memory by `MessageSink::Collector`.
+pub type MessageCollector = Arc<Mutex<Vec<DebugMessage>>>;
+
+#[derive(Clone)]
+pub enum MessageSink {
+    Stderr,
+    /// Log file, truncated when the instance is created.
+    File(PathBuf),
+    Collector(MessageCollector),
+}
+
+#[derive(Clone, Debug)]
+pub struct DebugMessage {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub id_number: i32,
+    pub id_name: String,
+    pub text: String,
+}
+
+impl DebugMessage {
+    pub fn is_error(&self) -> bool {
+        self.severity
+            .contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR)
+    }
+}
+
+impl fmt::Display for DebugMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{}][{}] {} ({:#x}): {}",
+            severity_name(self.severity),
+            message_type_name(self.message_type),
+            self.id_name,
+            self.id_number,
+            self.text
+        )
+    }
+}
+
+/// Settings of the debug messenger, see `InstanceBuilder::debug_messenger`.
+#[derive(Clone)]
+pub struct DebugMessengerBuilder {
+    severities: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    sink: MessageSink,
+    deduplicate: bool,
+    panic_on_error: bool,
+}
+
+impl DebugMessengerBuilder {
+    /// Warnings and errors of every type, printed once per message ID on stderr.
+    pub fn new() -> Self {
+        DebugMessengerBuilder {
+            severities: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            message_types: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            sink: MessageSink::Stderr,
+            deduplicate: true,
+            panic_on_error: false,
+        }
+    }
+
+    /// Same as `new`, adjusted by the environment so CI can tune runs without
+    /// touching the samples:
+    /// - `VKCORE_DEBUG_LOG=<path>` writes the messages to a file,
+    /// - `VKCORE_DEBUG_VERBOSE=1` also reports info and verbose messages,
+    /// - `VKCORE_DEBUG_PANIC=1` aborts on the first validation error.
+    pub fn from_env() -> Self {
+        let mut builder = DebugMessengerBuilder::new();
+        if let Some(path) = std::env::var_os("VKCORE_DEBUG_LOG") {
+            builder = builder.sink(MessageSink::File(PathBuf::from(path)));
+        }
+        if env_flag("VKCORE_DEBUG_VERBOSE") {
+            builder = builder.severities(vk::DebugUtilsMessageSeverityFlagsEXT::all());
+        }
+        if env_flag("VKCORE_DEBUG_PANIC") {
+            builder = builder.panic_on_error(true);
+        }
+        builder
+    }
+
+    pub fn severities(mut self, severities: vk::DebugUtilsMessageSeverityFlagsEXT) -> Self {
+        self.severities = severities;
+        self
+    }
+
+    pub fn message_types(mut self, message_types: vk::DebugUtilsMessageTypeFlagsEXT) -> Self {
+        self.message_types = message_types;
+        self
+    }
+
+    pub fn sink(mut self, sink: MessageSink) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    /// Reports each message ID only once (messages without an ID are always reported).
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Aborts the process once an error has been written to the sink. The
+    /// callback is called by the driver, so it cannot unwind.
+    pub fn panic_on_error(mut self, panic_on_error: bool) -> Self {
+        self.panic_on_error = panic_on_error;
+        self
+    }
+
+    pub(crate) fn build_state(&self) -> anyhow::Result<Box<MessengerState>> {
+        let output = match &self.sink {
+            MessageSink::Stderr => Output::Stderr,
+            MessageSink::File(path) => Output::File(
+                std::fs::File::create(path)
+                    .with_context(|| format!("Cannot create debug log {}", path.display()))?,
+            ),
+            MessageSink::Collector(collector) => Output::Collector(collector.clone()),
+        };
+        Ok(Box::new(MessengerState {
+            severities: self.severities,
+            message_types: self.message_types,
+            output: Mutex::new(output),
+            seen_ids: Mutex::new(HashSet::new()),
+            deduplicate: self.deduplicate,
+            panic_on_error: self.panic_on_error,
+        }))
+    }
+}
+
+impl Default for DebugMessengerBuilder {
+    fn default() -> Self {
+        DebugMessengerBuilder::new()
+    }
+}
+
+enum Output {
+    Stderr,
+    File(std::fs::File),
+    Collector(MessageCollector),
+}
+
+/// Shared with the driver through `p_user_data`, hence boxed.
+pub(crate) struct MessengerState {
+    severities: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    output: Mutex<Output>,
+    seen_ids: Mutex<HashSet<(i32, String)>>,
+    deduplicate: bool,
+    panic_on_error: bool,
+}
+
+impl MessengerState {
+    /// Chained into `vk::InstanceCreateInfo` and used for the messenger itself.
+    pub(crate) fn create_info(&self) -> vk::DebugUtilsMessengerCreateInfoEXT {
+        vk::DebugUtilsMessengerCreateInfoEXT {
+            s_type: vk::StructureType::DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            message_severity: self.severities,
+            message_type: self.message_types,
+            pfn_user_callback: Some(debug_utils_callback),
+            p_user_data: self as *const MessengerState as *mut c_void,
+        }
+    }
+
+    /// Writes `message` to the sink unless filtered out or already seen.
+    /// Returns whether the process must abort, i.e. `message` is a reported
+    /// error and `panic_on_error` is set.
+    fn report(&self, message: DebugMessage) -> bool {
+        if !self.severities.intersects(message.severity)
+            || !self.message_types.intersects(message.message_type)
+        {
+            return false;
+        }
+        if self.deduplicate && (message.id_number != 0 || !message.id_name.is_empty()) {
+            let mut seen_ids = self.seen_ids.lock().unwrap();
+            if !seen_ids.insert((message.id_number, message.id_name.clone())) {
+                return false;
+            }
+        }
+        let is_error = message.is_error();
+        match &mut *self.output.lock().unwrap() {
+            Output::Stderr => eprintln!("{}", message),
+            Output::File(file) => {
+                let _ = writeln!(file, "{}", message);
+                let _ = file.flush();
+            }
+            Output::Collector(collector) => collector.lock().unwrap().push(message),
+        }
+        is_error && self.panic_on_error
+    }
+}
+
+/// Registered messenger, destroyed by `Instance` right before the instance.
+pub(crate) struct DebugMessenger {
+    loader: DebugUtils,
+    handle: vk::DebugUtilsMessengerEXT,
+    _state: Box<MessengerState>,
+}
+
+impl DebugMessenger {
+    /// On failure `state` is given back with the error: the create info
+    /// chained into the instance still points at it, so it must outlive
+    /// `vkDestroyInstance`.
+    pub(crate) unsafe fn new(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        state: Box<MessengerState>,
+    ) -> Result<Self, (vk::Result, Box<MessengerState>)> {
+        let loader = DebugUtils::new(entry, instance);
+        let handle = match loader.create_debug_utils_messenger(&state.create_info(), None) {
+            Ok(handle) => handle,
+            Err(e) => return Err((e, state)),
+        };
+        Ok(DebugMessenger {
+            loader,
+            handle,
+            _state: state,
+        })
+    }
+
+    pub(crate) unsafe fn destroy(&self) {
+        self.loader.destroy_debug_utils_messenger(self.handle, None);
+    }
+}
+
+unsafe extern "system" fn debug_utils_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    p_user_data: *mut c_void,
+) -> vk::Bool32 {
+    if p_callback_data.is_null() || p_user_data.is_null() {
+        return vk::FALSE;
+    }
+    let callback_data = &*p_callback_data;
+    let state = &*(p_user_data as *const MessengerState);
+    let abort = state.report(DebugMessage {
+        severity: message_severity,
+        message_type: message_types,
+        id_number: callback_data.message_id_number,
+        id_name: c_str_lossy(callback_data.p_message_id_name),
+        text: c_str_lossy(callback_data.p_message),
+    });
+    if abort {
+        eprintln!("Validation error reported, aborting");
+        std::process::abort();
+    }
+    vk::FALSE
+}
+
+unsafe fn c_str_lossy(p_str: *const c_char) -> String {
+    if p_str.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(p_str).to_string_lossy().into_owned()
+    }
+}
+
+fn severity_name(severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> &'static str {
+    if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        "ERROR"
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        "WARNING"
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        "INFO"
+    } else {
+        "VERBOSE"
+    }
+}
+
+fn message_type_name(message_type: vk::DebugUtilsMessageTypeFlagsEXT) -> &'static str {
+    if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) {
+        "VALIDATION"
+    } else if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE) {
+        "PERFORMANCE"
+    } else {
+        "GENERAL"
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    match std::env::var(name) {
+        Ok(value) => value != "0" && !value.is_empty(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(
+        severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+        id_number: i32,
+        id_name: &str,
+    ) -> DebugMessage {
+        DebugMessage {
+            severity,
+            message_type: vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+            id_number,
+            id_name: id_name.to_string(),
+            text: String::from("text"),
+        }
+    }
+
+    fn warning(id_number: i32, id_name: &str) -> DebugMessage {
+        message(
+            vk::DebugUtilsMessageSeverityFlagsEXT::WARNING,
+            id_number,
+            id_name,
+        )
+    }
+
+    fn error(id_number: i32, id_name: &str) -> DebugMessage {
+        message(
+            vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            id_number,
+            id_name,
+        )
+    }
+
+    /// State collecting into a new collector, returned alongside.
+    fn collecting(builder: DebugMessengerBuilder) -> (Box<MessengerState>, MessageCollector) {
+        let collector = MessageCollector::default();
+        let state = builder
+            .sink(MessageSink::Collector(collector.clone()))
+            .build_state()
+            .unwrap();
+        (state, collector)
+    }
+
+    fn ids(collector: &MessageCollector) -> Vec<(i32, String)> {
+        collector
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|message| (message.id_number, message.id_name.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn filters_by_severity_and_type() {
+        let (state, collector) = collecting(
+            DebugMessengerBuilder::new()
+                .message_types(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION),
+        );
+        state.report(message(
+            vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+            1,
+            "info",
+        ));
+        state.report(DebugMessage {
+            message_type: vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            ..warning(2, "performance")
+        });
+        state.report(warning(3, "validation"));
+        assert_eq!(ids(&collector), vec![(3, String::from("validation"))]);
+    }
+
+    #[test]
+    fn reports_each_id_once() {
+        let (state, collector) = collecting(DebugMessengerBuilder::new());
+        state.report(warning(1, "a"));
+        state.report(error(1, "a"));
+        state.report(warning(1, "b"));
+        state.report(warning(2, "a"));
+        // Messages without an ID are never deduplicated.
+        state.report(warning(0, ""));
+        state.report(warning(0, ""));
+        assert_eq!(
+            ids(&collector),
+            vec![
+                (1, String::from("a")),
+                (1, String::from("b")),
+                (2, String::from("a")),
+                (0, String::new()),
+                (0, String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_duplicates_without_deduplication() {
+        let (state, collector) = collecting(DebugMessengerBuilder::new().deduplicate(false));
+        state.report(warning(1, "a"));
+        state.report(warning(1, "a"));
+        assert_eq!(ids(&collector).len(), 2);
+    }
+
+    #[test]
+    fn aborts_on_reported_errors_only() {
+        let (state, collector) = collecting(DebugMessengerBuilder::new().panic_on_error(true));
+        assert!(!state.report(warning(1, "a")));
+        assert!(state.report(error(2, "b")));
+        // The error reaches the sink before the abort.
+        assert_eq!(ids(&collector).len(), 2);
+        // Already seen, so not reported again.
+        assert!(!state.report(error(2, "b")));
+        let (state, _) = collecting(
+            DebugMessengerBuilder::new()
+                .panic_on_error(true)
+                .severities(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING),
+        );
+        assert!(!state.report(error(3, "c")));
+    }
+
+    #[test]
+    fn does_not_abort_without_panic_on_error() {
+        let (state, collector) = collecting(DebugMessengerBuilder::new());
+        assert!(!state.report(error(1, "a")));
+        assert_eq!(ids(&collector).len(), 1);
+    }
+}
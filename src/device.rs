@@ -0,0 +1,306 @@
+use crate::features::DeviceRequirements;
+use crate::features::FeatureSet;
+use crate::instance::Instance;
+use ash::version::DeviceV1_0;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::ffi::CStr;
+use std::ops::Deref;
+use std::os::raw::c_char;
+use std::rc::Rc;
+
+/// Returns the index of the first queue family supporting all of `flags`.
+///
+/// # Safety
+///
+/// `gpu` must have been enumerated from `instance`.
+pub unsafe fn lookup_queue_family_index(
+    instance: &ash::Instance,
+    gpu: vk::PhysicalDevice,
+    flags: vk::QueueFlags,
+) -> Result<usize, &'static str> {
+    let queue_family_properties = instance.get_physical_device_queue_family_properties(gpu);
+    for (index, properties) in queue_family_properties.iter().enumerate() {
+        if properties.queue_flags.contains(flags) {
+            return Ok(index);
+        }
+    }
+    Err("Queue family not found")
+}
+
+/// Returns the index of a queue family supporting `flags` and none of
+/// `excluded`, other than `main_family`.
+unsafe fn lookup_dedicated_queue_family_index(
+    instance: &ash::Instance,
+    gpu: vk::PhysicalDevice,
+    flags: vk::QueueFlags,
+    excluded: vk::QueueFlags,
+    main_family: usize,
+) -> Option<usize> {
+    instance
+        .get_physical_device_queue_family_properties(gpu)
+        .iter()
+        .enumerate()
+        .find(|(index, properties)| {
+            *index != main_family
+                && properties.queue_count > 0
+                && properties.queue_flags.contains(flags)
+                && !properties.queue_flags.intersects(excluded)
+        })
+        .map(|(index, _)| index)
+}
+
+/// Collects everything needed to create an `ash::Device`: one queue from the
+/// chosen family, plus dedicated transfer and compute queues when asked for and
+/// the hardware has them.
+pub struct DeviceBuilder {
+    gpu: vk::PhysicalDevice,
+    index_of_queue_family: usize,
+    requirements: DeviceRequirements,
+    dedicated_transfer_queue: bool,
+    async_compute_queue: bool,
+}
+
+impl DeviceBuilder {
+    pub fn new(gpu: vk::PhysicalDevice, index_of_queue_family: usize) -> Self {
+        DeviceBuilder {
+            gpu,
+            index_of_queue_family,
+            requirements: Default::default(),
+            dedicated_transfer_queue: false,
+            async_compute_queue: false,
+        }
+    }
+
+    /// Requires a device extension.
+    pub fn extension(mut self, name: &'static CStr) -> Self {
+        self.requirements.required_extensions.push(name);
+        self
+    }
+
+    /// Enables a device extension when the device has it.
+    pub fn optional_extension(mut self, name: &'static CStr) -> Self {
+        self.requirements.optional_extensions.push(name);
+        self
+    }
+
+    /// Requires Vulkan 1.0 features.
+    pub fn features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.requirements.required_features.core = features;
+        self
+    }
+
+    pub fn required_features(mut self, features: FeatureSet) -> Self {
+        self.requirements.required_features = features;
+        self
+    }
+
+    /// Features enabled only when the device supports them, see `Device::enabled_features`.
+    pub fn optional_features(mut self, features: FeatureSet) -> Self {
+        self.requirements.optional_features = features;
+        self
+    }
+
+    /// Replaces every feature and extension request.
+    pub fn requirements(mut self, requirements: DeviceRequirements) -> Self {
+        self.requirements = requirements;
+        self
+    }
+
+    /// Uses a transfer-only queue family for uploads when there is one.
+    pub fn dedicated_transfer_queue(mut self, enabled: bool) -> Self {
+        self.dedicated_transfer_queue = enabled;
+        self
+    }
+
+    /// Uses a compute queue family without graphics for compute work when there is one.
+    pub fn async_compute_queue(mut self, enabled: bool) -> Self {
+        self.async_compute_queue = enabled;
+        self
+    }
+
+    /// Fails with `UnmetDeviceRequirements` when a required feature or
+    /// extension is not supported.
+    ///
+    /// # Safety
+    ///
+    /// `self.gpu` must have been enumerated from `instance`.
+    pub unsafe fn build(&self, instance: &Rc<Instance>) -> anyhow::Result<Rc<Device>> {
+        let resolved = self.requirements.resolve(instance, self.gpu)?;
+        let main_family = self.index_of_queue_family;
+        let transfer_family = if self.dedicated_transfer_queue {
+            lookup_dedicated_queue_family_index(
+                instance,
+                self.gpu,
+                vk::QueueFlags::TRANSFER,
+                vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+                main_family,
+            )
+        } else {
+            None
+        };
+        let compute_family = if self.async_compute_queue {
+            lookup_dedicated_queue_family_index(
+                instance,
+                self.gpu,
+                vk::QueueFlags::COMPUTE,
+                vk::QueueFlags::GRAPHICS,
+                main_family,
+            )
+        } else {
+            None
+        };
+
+        let priorities = [1.0_f32];
+        let mut v_families = vec![main_family];
+        v_families.extend(transfer_family);
+        v_families.extend(compute_family);
+        let v_queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = v_families
+            .iter()
+            .map(|family| vk::DeviceQueueCreateInfo {
+                s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                queue_family_index: *family as u32,
+                queue_count: 1,
+                p_queue_priorities: priorities.as_ptr(),
+            })
+            .collect();
+        let v_extensions_c: Vec<*const c_char> =
+            resolved.extensions.iter().map(|e| e.as_ptr()).collect();
+        // Features needing Vulkan 1.2 or later are chained behind `vk::PhysicalDeviceFeatures2`;
+        // without them the features go through `p_enabled_features` as before.
+        let mut chained_features = resolved.features;
+        let features2 = chained_features.chain();
+        let use_features2 = resolved.features.min_api_version() >= ash::vk_make_version!(1, 2, 0);
+        let device_create_info = vk::DeviceCreateInfo {
+            s_type: vk::StructureType::DEVICE_CREATE_INFO,
+            p_next: if use_features2 {
+                &features2 as *const _ as *const std::ffi::c_void
+            } else {
+                std::ptr::null()
+            },
+            flags: Default::default(),
+            queue_create_info_count: v_queue_create_infos.len() as u32,
+            p_queue_create_infos: v_queue_create_infos.as_ptr(),
+            enabled_layer_count: 0,
+            pp_enabled_layer_names: std::ptr::null(),
+            enabled_extension_count: v_extensions_c.len() as u32,
+            pp_enabled_extension_names: v_extensions_c.as_ptr(),
+            p_enabled_features: if use_features2 {
+                std::ptr::null()
+            } else {
+                &resolved.features.core
+            },
+        };
+        let handle = instance.create_device(self.gpu, &device_create_info, None)?;
+        let get_queue = |family: usize| Queue {
+            family_index: family as u32,
+            handle: handle.get_device_queue(family as u32, 0),
+        };
+        let main_queue = get_queue(main_family);
+        let transfer_queue = transfer_family.map_or(main_queue, get_queue);
+        let compute_queue = compute_family.map_or(main_queue, get_queue);
+        Ok(Rc::new(Device {
+            instance: instance.clone(),
+            gpu: self.gpu,
+            handle,
+            api_version: resolved.api_version,
+            enabled_features: resolved.features,
+            enabled_extensions: resolved.extensions,
+            main_queue,
+            transfer_queue,
+            compute_queue,
+        }))
+    }
+}
+
+/// A queue and the family it belongs to, needed for ownership transfers.
+#[derive(Clone, Copy, Debug)]
+pub struct Queue {
+    pub family_index: u32,
+    pub handle: vk::Queue,
+}
+
+/// Owned `ash::Device`, destroyed once every object created from it is gone.
+///
+/// The wrappers in `vkcore::objects` keep an `Rc<Device>`, so dropping them in
+/// any order is safe. The device itself keeps its instance alive.
+pub struct Device {
+    instance: Rc<Instance>,
+    gpu: vk::PhysicalDevice,
+    handle: ash::Device,
+    api_version: u32,
+    enabled_features: FeatureSet,
+    enabled_extensions: Vec<&'static CStr>,
+    main_queue: Queue,
+    transfer_queue: Queue,
+    compute_queue: Queue,
+}
+
+impl Device {
+    pub fn instance(&self) -> &Rc<Instance> {
+        &self.instance
+    }
+
+    pub fn gpu(&self) -> vk::PhysicalDevice {
+        self.gpu
+    }
+
+    pub fn raw(&self) -> &ash::Device {
+        &self.handle
+    }
+
+    /// Lower of the instance and device API versions.
+    pub fn api_version(&self) -> u32 {
+        self.api_version
+    }
+
+    /// Required features plus the optional ones the device supports.
+    pub fn enabled_features(&self) -> &FeatureSet {
+        &self.enabled_features
+    }
+
+    pub fn enabled_extensions(&self) -> &[&'static CStr] {
+        &self.enabled_extensions
+    }
+
+    /// Queue of the family given to `DeviceBuilder::new`.
+    pub fn main_queue(&self) -> Queue {
+        self.main_queue
+    }
+
+    /// Dedicated transfer queue, or the main queue when there is none.
+    pub fn transfer_queue(&self) -> Queue {
+        self.transfer_queue
+    }
+
+    /// Async compute queue, or the main queue when there is none.
+    pub fn compute_queue(&self) -> Queue {
+        self.compute_queue
+    }
+
+    pub fn has_dedicated_transfer_queue(&self) -> bool {
+        self.transfer_queue.family_index != self.main_queue.family_index
+    }
+
+    pub fn has_async_compute_queue(&self) -> bool {
+        self.compute_queue.family_index != self.main_queue.family_index
+    }
+}
+
+impl Deref for Device {
+    type Target = ash::Device;
+
+    fn deref(&self) -> &ash::Device {
+        &self.handle
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        unsafe {
+            self.handle.destroy_device(None);
+        }
+    }
+}
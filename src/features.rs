@@ -0,0 +1,517 @@
+//! Device features and extensions requested by the samples.
+//!
+//! `DeviceRequirements` lists what a device must (or may) provide: Vulkan 1.0
+//! features, the Vulkan 1.1/1.2/1.3 feature structures chained through
+//! `p_next`, and device extensions. `resolve` checks them against a physical
+//! device and returns what to enable, or every unmet requirement.
+//!
+//! Feature names are the field names, prefixed with `vulkan11.`, `vulkan12.`
+//! or `vulkan13.` outside of `vk::PhysicalDeviceFeatures`.
+
+use crate::instance::Instance;
+use ash::version::InstanceV1_0;
+use ash::version::InstanceV1_1;
+use ash::vk;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_void;
+
+macro_rules! feature_list {
+    ($($field:ident),* $(,)?) => {
+        /// `(name, enabled)` for every field of `vk::PhysicalDeviceFeatures`.
+        pub fn feature_flags(features: &vk::PhysicalDeviceFeatures) -> Vec<(&'static str, bool)> {
+            vec![$((stringify!($field), features.$field == vk::TRUE)),*]
+        }
+
+        fn combine_features(
+            a: &vk::PhysicalDeviceFeatures,
+            b: &vk::PhysicalDeviceFeatures,
+            op: fn(bool, bool) -> bool,
+        ) -> vk::PhysicalDeviceFeatures {
+            vk::PhysicalDeviceFeatures {
+                $($field: to_bool32(op(a.$field == vk::TRUE, b.$field == vk::TRUE)),)*
+            }
+        }
+    };
+}
+
+/// Declares a Vulkan 1.x feature structure, which ash 0.29 predates.
+macro_rules! versioned_features {
+    ($name:ident, $s_type:expr, $prefix:expr, [$($field:ident),* $(,)?]) => {
+        #[repr(C)]
+        #[derive(Clone, Copy, Debug)]
+        pub struct $name {
+            pub s_type: vk::StructureType,
+            pub p_next: *mut c_void,
+            $(pub $field: vk::Bool32,)*
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name {
+                    s_type: vk::StructureType::from_raw($s_type),
+                    p_next: std::ptr::null_mut(),
+                    $($field: vk::FALSE,)*
+                }
+            }
+        }
+
+        impl $name {
+            /// `(name, enabled)` for every feature of the structure.
+            pub fn flags(&self) -> Vec<(&'static str, bool)> {
+                vec![$((concat!($prefix, ".", stringify!($field)), self.$field == vk::TRUE)),*]
+            }
+
+            fn is_empty(&self) -> bool {
+                true $(&& self.$field == vk::FALSE)*
+            }
+
+            fn combine(&self, other: &Self, op: fn(bool, bool) -> bool) -> Self {
+                $name {
+                    $($field: to_bool32(op(self.$field == vk::TRUE, other.$field == vk::TRUE)),)*
+                    ..Default::default()
+                }
+            }
+        }
+    };
+}
+
+feature_list!(
+    robust_buffer_access,
+    full_draw_index_uint32,
+    image_cube_array,
+    independent_blend,
+    geometry_shader,
+    tessellation_shader,
+    sample_rate_shading,
+    dual_src_blend,
+    logic_op,
+    multi_draw_indirect,
+    draw_indirect_first_instance,
+    depth_clamp,
+    depth_bias_clamp,
+    fill_mode_non_solid,
+    depth_bounds,
+    wide_lines,
+    large_points,
+    alpha_to_one,
+    multi_viewport,
+    sampler_anisotropy,
+    texture_compression_etc2,
+    texture_compression_astc_ldr,
+    texture_compression_bc,
+    occlusion_query_precise,
+    pipeline_statistics_query,
+    vertex_pipeline_stores_and_atomics,
+    fragment_stores_and_atomics,
+    shader_tessellation_and_geometry_point_size,
+    shader_image_gather_extended,
+    shader_storage_image_extended_formats,
+    shader_storage_image_multisample,
+    shader_storage_image_read_without_format,
+    shader_storage_image_write_without_format,
+    shader_uniform_buffer_array_dynamic_indexing,
+    shader_sampled_image_array_dynamic_indexing,
+    shader_storage_buffer_array_dynamic_indexing,
+    shader_storage_image_array_dynamic_indexing,
+    shader_clip_distance,
+    shader_cull_distance,
+    shader_float64,
+    shader_int64,
+    shader_int16,
+    shader_resource_residency,
+    shader_resource_min_lod,
+    sparse_binding,
+    sparse_residency_buffer,
+    sparse_residency_image2_d,
+    sparse_residency_image3_d,
+    sparse_residency2_samples,
+    sparse_residency4_samples,
+    sparse_residency8_samples,
+    sparse_residency16_samples,
+    sparse_residency_aliased,
+    variable_multisample_rate,
+    inherited_queries,
+);
+
+/// Names of the features enabled in `features`.
+pub fn enabled_features(features: &vk::PhysicalDeviceFeatures) -> Vec<&'static str> {
+    feature_flags(features)
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Names of the features enabled in `required` but not in `available`.
+pub fn missing_features(
+    required: &vk::PhysicalDeviceFeatures,
+    available: &vk::PhysicalDeviceFeatures,
+) -> Vec<&'static str> {
+    feature_flags(required)
+        .into_iter()
+        .zip(feature_flags(available))
+        .filter(|((_, is_required), (_, is_available))| *is_required && !*is_available)
+        .map(|((name, _), _)| name)
+        .collect()
+}
+
+fn to_bool32(value: bool) -> vk::Bool32 {
+    if value {
+        vk::TRUE
+    } else {
+        vk::FALSE
+    }
+}
+
+versioned_features!(
+    PhysicalDeviceVulkan11Features,
+    49,
+    "vulkan11",
+    [
+        storage_buffer16_bit_access,
+        uniform_and_storage_buffer16_bit_access,
+        storage_push_constant16,
+        storage_input_output16,
+        multiview,
+        multiview_geometry_shader,
+        multiview_tessellation_shader,
+        variable_pointers_storage_buffer,
+        variable_pointers,
+        protected_memory,
+        sampler_ycbcr_conversion,
+        shader_draw_parameters,
+    ]
+);
+
+versioned_features!(
+    PhysicalDeviceVulkan12Features,
+    51,
+    "vulkan12",
+    [
+        sampler_mirror_clamp_to_edge,
+        draw_indirect_count,
+        storage_buffer8_bit_access,
+        uniform_and_storage_buffer8_bit_access,
+        storage_push_constant8,
+        shader_buffer_int64_atomics,
+        shader_shared_int64_atomics,
+        shader_float16,
+        shader_int8,
+        descriptor_indexing,
+        shader_input_attachment_array_dynamic_indexing,
+        shader_uniform_texel_buffer_array_dynamic_indexing,
+        shader_storage_texel_buffer_array_dynamic_indexing,
+        shader_uniform_buffer_array_non_uniform_indexing,
+        shader_sampled_image_array_non_uniform_indexing,
+        shader_storage_buffer_array_non_uniform_indexing,
+        shader_storage_image_array_non_uniform_indexing,
+        shader_input_attachment_array_non_uniform_indexing,
+        shader_uniform_texel_buffer_array_non_uniform_indexing,
+        shader_storage_texel_buffer_array_non_uniform_indexing,
+        descriptor_binding_uniform_buffer_update_after_bind,
+        descriptor_binding_sampled_image_update_after_bind,
+        descriptor_binding_storage_image_update_after_bind,
+        descriptor_binding_storage_buffer_update_after_bind,
+        descriptor_binding_uniform_texel_buffer_update_after_bind,
+        descriptor_binding_storage_texel_buffer_update_after_bind,
+        descriptor_binding_update_unused_while_pending,
+        descriptor_binding_partially_bound,
+        descriptor_binding_variable_descriptor_count,
+        runtime_descriptor_array,
+        sampler_filter_minmax,
+        scalar_block_layout,
+        imageless_framebuffer,
+        uniform_buffer_standard_layout,
+        shader_subgroup_extended_types,
+        separate_depth_stencil_layouts,
+        host_query_reset,
+        timeline_semaphore,
+        buffer_device_address,
+        buffer_device_address_capture_replay,
+        buffer_device_address_multi_device,
+        vulkan_memory_model,
+        vulkan_memory_model_device_scope,
+        vulkan_memory_model_availability_visibility_chains,
+        shader_output_viewport_index,
+        shader_output_layer,
+        subgroup_broadcast_dynamic_id,
+    ]
+);
+
+versioned_features!(
+    PhysicalDeviceVulkan13Features,
+    53,
+    "vulkan13",
+    [
+        robust_image_access,
+        inline_uniform_block,
+        descriptor_binding_inline_uniform_block_update_after_bind,
+        pipeline_creation_cache_control,
+        private_data,
+        shader_demote_to_helper_invocation,
+        shader_terminate_invocation,
+        subgroup_size_control,
+        compute_full_subgroups,
+        synchronization2,
+        texture_compression_astc_hdr,
+        shader_zero_initialize_workgroup_memory,
+        dynamic_rendering,
+        shader_integer_dot_product,
+        maintenance4,
+    ]
+);
+
+/// Every feature structure known to vkcore. `vulkan11` and `vulkan12` need a
+/// Vulkan 1.2 device, `vulkan13` a Vulkan 1.3 one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FeatureSet {
+    pub core: vk::PhysicalDeviceFeatures,
+    pub vulkan11: PhysicalDeviceVulkan11Features,
+    pub vulkan12: PhysicalDeviceVulkan12Features,
+    pub vulkan13: PhysicalDeviceVulkan13Features,
+}
+
+impl FeatureSet {
+    pub fn from_core(core: vk::PhysicalDeviceFeatures) -> Self {
+        FeatureSet {
+            core,
+            ..Default::default()
+        }
+    }
+
+    /// Features supported by `gpu`, limited to what `api_version` exposes.
+    ///
+    /// # Safety
+    ///
+    /// `gpu` must have been enumerated from `instance`.
+    pub unsafe fn query(instance: &Instance, gpu: vk::PhysicalDevice, api_version: u32) -> Self {
+        let mut available = FeatureSet::from_core(instance.get_physical_device_features(gpu));
+        if api_version < ash::vk_make_version!(1, 2, 0) {
+            return available;
+        }
+        let mut vulkan11 = PhysicalDeviceVulkan11Features::default();
+        let mut vulkan12 = PhysicalDeviceVulkan12Features::default();
+        let mut vulkan13 = PhysicalDeviceVulkan13Features::default();
+        vulkan11.p_next = &mut vulkan12 as *mut _ as *mut c_void;
+        if api_version >= ash::vk_make_version!(1, 3, 0) {
+            vulkan12.p_next = &mut vulkan13 as *mut _ as *mut c_void;
+        }
+        let mut features2 = vk::PhysicalDeviceFeatures2 {
+            p_next: &mut vulkan11 as *mut _ as *mut c_void,
+            ..Default::default()
+        };
+        instance
+            .fp_v1_1()
+            .get_physical_device_features2(gpu, &mut features2);
+        // The copies must not point to these locals.
+        vulkan11.p_next = std::ptr::null_mut();
+        vulkan12.p_next = std::ptr::null_mut();
+        available.core = features2.features;
+        available.vulkan11 = vulkan11;
+        available.vulkan12 = vulkan12;
+        available.vulkan13 = vulkan13;
+        available
+    }
+
+    /// `(name, enabled)` for every feature, see the module documentation.
+    pub fn flags(&self) -> Vec<(&'static str, bool)> {
+        let mut flags = feature_flags(&self.core);
+        flags.extend(self.vulkan11.flags());
+        flags.extend(self.vulkan12.flags());
+        flags.extend(self.vulkan13.flags());
+        flags
+    }
+
+    pub fn enabled(&self) -> Vec<&'static str> {
+        self.flags()
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Names of the features enabled in `self` but not in `available`.
+    pub fn missing(&self, available: &FeatureSet) -> Vec<&'static str> {
+        self.flags()
+            .into_iter()
+            .zip(available.flags())
+            .filter(|((_, is_required), (_, is_available))| *is_required && !*is_available)
+            .map(|((name, _), _)| name)
+            .collect()
+    }
+
+    pub fn union(&self, other: &FeatureSet) -> Self {
+        self.combine(other, |a, b| a || b)
+    }
+
+    pub fn intersection(&self, other: &FeatureSet) -> Self {
+        self.combine(other, |a, b| a && b)
+    }
+
+    /// Lowest API version able to enable these features.
+    pub fn min_api_version(&self) -> u32 {
+        if !self.vulkan13.is_empty() {
+            ash::vk_make_version!(1, 3, 0)
+        } else if !self.vulkan11.is_empty() || !self.vulkan12.is_empty() {
+            ash::vk_make_version!(1, 2, 0)
+        } else {
+            ash::vk_make_version!(1, 0, 0)
+        }
+    }
+
+    /// Chains the non-empty structures behind a `vk::PhysicalDeviceFeatures2`,
+    /// to put in `vk::DeviceCreateInfo::p_next`. The chain points into `self`,
+    /// which must not move while it is used.
+    pub fn chain(&mut self) -> vk::PhysicalDeviceFeatures2 {
+        let mut p_next: *mut c_void = std::ptr::null_mut();
+        if !self.vulkan13.is_empty() {
+            self.vulkan13.p_next = p_next;
+            p_next = &mut self.vulkan13 as *mut _ as *mut c_void;
+        }
+        if !self.vulkan12.is_empty() {
+            self.vulkan12.p_next = p_next;
+            p_next = &mut self.vulkan12 as *mut _ as *mut c_void;
+        }
+        if !self.vulkan11.is_empty() {
+            self.vulkan11.p_next = p_next;
+            p_next = &mut self.vulkan11 as *mut _ as *mut c_void;
+        }
+        vk::PhysicalDeviceFeatures2 {
+            p_next,
+            features: self.core,
+            ..Default::default()
+        }
+    }
+
+    fn combine(&self, other: &FeatureSet, op: fn(bool, bool) -> bool) -> Self {
+        FeatureSet {
+            core: combine_features(&self.core, &other.core, op),
+            vulkan11: self.vulkan11.combine(&other.vulkan11, op),
+            vulkan12: self.vulkan12.combine(&other.vulkan12, op),
+            vulkan13: self.vulkan13.combine(&other.vulkan13, op),
+        }
+    }
+}
+
+/// What a device must provide, and what it should enable when available.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceRequirements {
+    pub required_features: FeatureSet,
+    pub optional_features: FeatureSet,
+    pub required_extensions: Vec<&'static CStr>,
+    pub optional_extensions: Vec<&'static CStr>,
+}
+
+/// What to enable on a device meeting its `DeviceRequirements`.
+pub struct ResolvedRequirements {
+    /// Lower of the instance and device API versions.
+    pub api_version: u32,
+    pub features: FeatureSet,
+    pub extensions: Vec<&'static CStr>,
+}
+
+impl DeviceRequirements {
+    /// # Safety
+    ///
+    /// `gpu` must have been enumerated from `instance`.
+    pub unsafe fn resolve(
+        &self,
+        instance: &Instance,
+        gpu: vk::PhysicalDevice,
+    ) -> Result<ResolvedRequirements, UnmetDeviceRequirements> {
+        let properties = instance.get_physical_device_properties(gpu);
+        let api_version = properties.api_version.min(instance.api_version());
+        let mut unmet = UnmetDeviceRequirements {
+            device: CStr::from_ptr(properties.device_name.as_ptr())
+                .to_string_lossy()
+                .into_owned(),
+            api_version,
+            features: Vec::new(),
+            extensions: Vec::new(),
+        };
+
+        let available_features = FeatureSet::query(instance, gpu, api_version);
+        unmet.features = self.required_features.missing(&available_features);
+        let features = self
+            .required_features
+            .union(&self.optional_features.intersection(&available_features));
+
+        let available_extensions: Vec<&CStr> =
+            match instance.enumerate_device_extension_properties(gpu) {
+                Ok(properties) => properties
+                    .iter()
+                    .map(|p| CStr::from_ptr(p.extension_name.as_ptr()))
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+        let mut extensions = Vec::new();
+        for (extension, required) in self
+            .required_extensions
+            .iter()
+            .map(|e| (*e, true))
+            .chain(self.optional_extensions.iter().map(|e| (*e, false)))
+        {
+            if available_extensions.contains(&extension) {
+                if !extensions.contains(&extension) {
+                    extensions.push(extension);
+                }
+            } else if required {
+                unmet
+                    .extensions
+                    .push(extension.to_string_lossy().into_owned());
+            }
+        }
+
+        if unmet.features.is_empty() && unmet.extensions.is_empty() {
+            Ok(ResolvedRequirements {
+                api_version,
+                features,
+                extensions,
+            })
+        } else {
+            Err(unmet)
+        }
+    }
+}
+
+/// Required features and extensions a device does not provide.
+#[derive(Debug)]
+pub struct UnmetDeviceRequirements {
+    pub device: String,
+    pub api_version: u32,
+    pub features: Vec<&'static str>,
+    pub extensions: Vec<String>,
+}
+
+impl UnmetDeviceRequirements {
+    /// The missing features and extensions, without the device name.
+    pub fn reasons(&self) -> String {
+        let mut reasons = Vec::new();
+        if !self.features.is_empty() {
+            reasons.push(format!("missing features [{}]", self.features.join(", ")));
+        }
+        if !self.extensions.is_empty() {
+            reasons.push(format!(
+                "missing extensions [{}]",
+                self.extensions.join(", ")
+            ));
+        }
+        reasons.join(", ")
+    }
+}
+
+impl fmt::Display for UnmetDeviceRequirements {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Cannot use {} (Vulkan {}.{}): {}",
+            self.device,
+            ash::vk_version_major!(self.api_version),
+            ash::vk_version_minor!(self.api_version),
+            self.reasons()
+        )
+    }
+}
+
+impl std::error::Error for UnmetDeviceRequirements {}
@@ -0,0 +1,129 @@
+//! Shader hot-reload for the windowed samples.
+//!
+//! A `ShaderWatcher` notices when the shaders of a pipeline, or the files they
+//! include, are modified. `rebuild_graphics_pipeline` then compiles them again
+//! into a new pipeline; when that fails, the sample keeps drawing with the
+//! previous one.
+
+use crate::device::Device;
+use crate::objects::Pipeline;
+use crate::shader::create_shader_module;
+use crate::shader_compiler::ShaderCompiler;
+use ash::vk;
+use std::ffi::CStr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+/// Time between two checks of the modification times.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches the files of the shaders of one pipeline.
+pub struct ShaderWatcher {
+    v_shader_paths: Vec<PathBuf>,
+    /// Shaders and included files, with their last modification time.
+    v_files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new<P: AsRef<Path>>(v_shader_paths: &[P]) -> Self {
+        let v_shader_paths: Vec<PathBuf> = v_shader_paths
+            .iter()
+            .map(|path| path.as_ref().to_owned())
+            .collect();
+        ShaderWatcher {
+            v_files: watched_files(&v_shader_paths),
+            v_shader_paths,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Returns `true` once after any of the files changed. Cheap enough to be
+    /// called every frame: the files are only checked every 250 ms.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        let changed = self
+            .v_files
+            .iter()
+            .any(|(path, modified)| modification_time(path) != *modified);
+        if changed {
+            // Includes may have been added or removed.
+            self.v_files = watched_files(&self.v_shader_paths);
+        }
+        changed
+    }
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn watched_files(v_shader_paths: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let compiler = ShaderCompiler::new();
+    v_shader_paths
+        .iter()
+        .flat_map(|path| match path.extension().and_then(|e| e.to_str()) {
+            Some("spv") => vec![path.clone()],
+            _ => compiler.dependencies(path),
+        })
+        .map(|path| {
+            let modified = modification_time(&path);
+            (path, modified)
+        })
+        .collect()
+}
+
+/// Loads `v_stages` again and creates a pipeline with them and the other
+/// states of `create_info`, through `pipeline_cache` (may be null). The device
+/// does not need to be idle, only the destruction of the previous pipeline
+/// does.
+///
+/// # Safety
+///
+/// Same as `Pipeline::new_graphics`; the shader stages of `create_info` are ignored.
+pub unsafe fn rebuild_graphics_pipeline(
+    device: &Rc<Device>,
+    pipeline_cache: vk::PipelineCache,
+    create_info: &vk::GraphicsPipelineCreateInfo,
+    v_stages: &[(vk::ShaderStageFlags, &str)],
+    entry_name: &CStr,
+) -> anyhow::Result<Pipeline> {
+    let mut v_shader_modules = Vec::with_capacity(v_stages.len());
+    for (_, path) in v_stages {
+        v_shader_modules.push(create_shader_module(device, path)?);
+    }
+    let v_stage_create_infos: Vec<vk::PipelineShaderStageCreateInfo> = v_stages
+        .iter()
+        .zip(&v_shader_modules)
+        .map(
+            |((stage, _), shader_module)| vk::PipelineShaderStageCreateInfo {
+                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                stage: *stage,
+                module: shader_module.handle(),
+                p_name: entry_name.as_ptr(),
+                p_specialization_info: std::ptr::null(),
+            },
+        )
+        .collect();
+    let create_info = vk::GraphicsPipelineCreateInfo {
+        stage_count: v_stage_create_infos.len() as u32,
+        p_stages: v_stage_create_infos.as_ptr(),
+        ..*create_info
+    };
+    Ok(Pipeline::new_graphics(
+        device,
+        pipeline_cache,
+        &create_info,
+    )?)
+}
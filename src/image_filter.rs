@@ -0,0 +1,714 @@
+//! Image filters running in compute shaders on RGBA8 storage images.
+//!
+//! `ImageFilters::apply` uploads an image, runs a chain of `Filter`s (each one
+//! reading the previous output and writing a new storage image) in a single
+//! command buffer on the compute queue, and reads the last image back. The
+//! upload hands the input image over to the compute queue family. The kernels
+//! are in `shaders/filters/`.
+
+use crate::allocator::Allocation;
+use crate::allocator::DeviceMemoryAllocator;
+use crate::buffer::TypedBuffer;
+use crate::device::Device;
+use crate::objects::DescriptorPool;
+use crate::objects::DescriptorSetLayout;
+use crate::objects::Fence;
+use crate::objects::Image;
+use crate::objects::ImageView;
+use crate::objects::Pipeline;
+use crate::objects::PipelineLayout;
+use crate::pipeline::create_compute_pipeline;
+use crate::pipeline::create_pipeline_layout;
+use crate::pipeline::SpecializationConstants;
+use crate::pipeline_cache::DiskPipelineCache;
+use crate::transfer::submit_one_time;
+use crate::transfer::ImageState;
+use crate::transfer::Uploader;
+use anyhow::bail;
+use anyhow::Context;
+use ash::version::DeviceV1_0;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::rc::Rc;
+
+/// `local_size_x` and `local_size_y` of every kernel.
+const TILE_SIZE: u32 = 16;
+
+const COLOR_SUBRESOURCE_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask: vk::ImageAspectFlags::COLOR,
+    base_mip_level: 0,
+    level_count: 1,
+    base_array_layer: 0,
+    layer_count: 1,
+};
+
+/// 8-bit RGBA pixels, row by row without padding.
+#[derive(Clone, Debug)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// 3D color lookup table, as found in `.cube` files.
+#[derive(Clone, Debug)]
+pub struct Lut {
+    size: u32,
+    /// rgb plus padding, red varying fastest then green then blue.
+    v_entries: Vec<[f32; 4]>,
+}
+
+impl Lut {
+    /// Parses the `LUT_3D_SIZE` and table lines of a `.cube` file; the domain
+    /// must be the default `[0, 1]`.
+    pub fn from_cube(text: &str) -> anyhow::Result<Self> {
+        let mut size = None;
+        let mut v_entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None => continue,
+                Some(word) if word.starts_with('#') => continue,
+                Some("LUT_3D_SIZE") => {
+                    let value = words.next().unwrap_or("");
+                    size = Some(
+                        value
+                            .parse::<u32>()
+                            .with_context(|| format!("Invalid LUT size {:?}", value))?,
+                    );
+                }
+                Some(keyword @ "DOMAIN_MIN") | Some(keyword @ "DOMAIN_MAX") => {
+                    let expected = if keyword == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    if words.any(|bound| bound.parse::<f32>().ok() != Some(expected)) {
+                        bail!("Line {}: only the [0, 1] domain is supported", index + 1);
+                    }
+                }
+                Some(word) if word.chars().next().is_some_and(char::is_alphabetic) => {
+                    // TITLE, LUT_1D_SIZE... are ignored.
+                    if word == "LUT_1D_SIZE" {
+                        bail!("1D LUTs are not supported");
+                    }
+                }
+                Some(_) => {
+                    let v_components: Vec<f32> = line
+                        .split_whitespace()
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()
+                        .with_context(|| format!("Line {}: invalid LUT entry", index + 1))?;
+                    if v_components.len() != 3 {
+                        bail!("Line {}: a LUT entry has 3 components", index + 1);
+                    }
+                    v_entries.push([v_components[0], v_components[1], v_components[2], 0.0]);
+                }
+            }
+        }
+        let size = size.context("Missing LUT_3D_SIZE")?;
+        if size < 2 {
+            bail!("A LUT needs at least 2 entries per axis, got {}", size);
+        }
+        if v_entries.len() != (size * size * size) as usize {
+            bail!(
+                "A LUT of size {} has {} entries, got {}",
+                size,
+                size * size * size,
+                v_entries.len()
+            );
+        }
+        Ok(Lut { size, v_entries })
+    }
+
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Cannot read {}", path))?;
+        Lut::from_cube(&text).with_context(|| format!("Invalid LUT {}", path))
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Filter {
+    /// Luminance with Rec. 709 weights.
+    Grayscale,
+    /// Separable blur over `2 * radius + 1` pixels in each direction.
+    GaussianBlur { radius: u32, sigma: f32 },
+    /// Gradient magnitude of the luminance.
+    Sobel,
+    /// Color grading with a 3D LUT.
+    ColorGrade(Lut),
+    /// Bilinear resize.
+    Resize { width: u32, height: u32 },
+}
+
+/// Storage image with its memory and view, in the `GENERAL` layout once
+/// created.
+struct StorageImage {
+    image: Image,
+    _allocation: Allocation,
+    view: ImageView,
+    width: u32,
+    height: u32,
+}
+
+/// One dispatch of a filter chain.
+struct Pass {
+    pipeline: vk::Pipeline,
+    push_constants: [u32; 4],
+    /// Index of the LUT buffer bound at binding 2.
+    lut: Option<usize>,
+    width: u32,
+    height: u32,
+}
+
+pub struct ImageFilters<'a> {
+    device: Rc<Device>,
+    allocator: &'a DeviceMemoryAllocator,
+    uploader: Uploader,
+    max_image_dimension: u32,
+    descriptor_set_layout: DescriptorSetLayout,
+    pipeline_layout: PipelineLayout,
+    grayscale: Pipeline,
+    gaussian_blur: Pipeline,
+    sobel: Pipeline,
+    lut: Pipeline,
+    resize: Pipeline,
+}
+
+impl<'a> ImageFilters<'a> {
+    /// Builds the pipelines of every kernel in `shaders/filters/`, through
+    /// `pipeline_cache`.
+    ///
+    /// # Safety
+    ///
+    /// `allocator` and `pipeline_cache` must have been created for `device`.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        allocator: &'a DeviceMemoryAllocator,
+        pipeline_cache: &DiskPipelineCache,
+    ) -> anyhow::Result<Self> {
+        let max_image_dimension = device
+            .instance()
+            .get_physical_device_properties(device.gpu())
+            .limits
+            .max_image_dimension2_d;
+
+        let v_bindings = [
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorType::STORAGE_BUFFER,
+        ]
+        .iter()
+        .enumerate()
+        .map(
+            |(binding, &descriptor_type)| vk::DescriptorSetLayoutBinding {
+                binding: binding as u32,
+                descriptor_type,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                p_immutable_samplers: std::ptr::null(),
+            },
+        )
+        .collect::<Vec<_>>();
+        let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            binding_count: v_bindings.len() as u32,
+            p_bindings: v_bindings.as_ptr(),
+        };
+        let descriptor_set_layout =
+            DescriptorSetLayout::new(device, &descriptor_set_layout_create_info)?;
+        let push_constant_range = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<[u32; 4]>() as u32,
+        };
+        let pipeline_layout = create_pipeline_layout(
+            device,
+            &[descriptor_set_layout.handle()],
+            &[push_constant_range],
+        )?;
+
+        let entry_point = std::ffi::CString::new("main").unwrap();
+        let create_pipeline = |name: &str| -> anyhow::Result<Pipeline> {
+            let shader_path = format!("shaders/filters/{}.comp", name);
+            let shader_module = crate::shader::create_shader_module(device, &shader_path)?;
+            pipeline_cache
+                .timed(|| {
+                    create_compute_pipeline(
+                        device,
+                        pipeline_cache.handle(),
+                        pipeline_layout.handle(),
+                        &shader_module,
+                        &entry_point,
+                        &SpecializationConstants::new(),
+                    )
+                })
+                .with_context(|| format!("Cannot create compute pipeline from {}", shader_path))
+        };
+        Ok(ImageFilters {
+            device: device.clone(),
+            allocator,
+            uploader: Uploader::for_queue(device, device.compute_queue())?,
+            max_image_dimension,
+            grayscale: create_pipeline("grayscale")?,
+            gaussian_blur: create_pipeline("gaussian_blur")?,
+            sobel: create_pipeline("sobel")?,
+            lut: create_pipeline("lut")?,
+            resize: create_pipeline("resize")?,
+            descriptor_set_layout,
+            pipeline_layout,
+        })
+    }
+
+    /// Runs `filters` in order on `image` and returns the result.
+    ///
+    /// # Safety
+    ///
+    /// The device must still be alive; the call waits for the work it submits.
+    pub unsafe fn apply(&self, image: &RgbaImage, filters: &[Filter]) -> anyhow::Result<RgbaImage> {
+        if image.pixels.len() != (image.width * image.height * 4) as usize {
+            bail!(
+                "A {}x{} RGBA image has {} bytes, got {}",
+                image.width,
+                image.height,
+                image.width * image.height * 4,
+                image.pixels.len()
+            );
+        }
+        self.check_size(image.width, image.height)?;
+
+        let mut v_passes = Vec::new();
+        let mut v_luts = Vec::new();
+        let (mut width, mut height) = (image.width, image.height);
+        for filter in filters {
+            let mut pass = |pipeline: &Pipeline, push_constants: [u32; 4], lut| {
+                v_passes.push(Pass {
+                    pipeline: pipeline.handle(),
+                    push_constants,
+                    lut,
+                    width,
+                    height,
+                })
+            };
+            match filter {
+                Filter::Grayscale => pass(&self.grayscale, [0; 4], None),
+                Filter::GaussianBlur { radius, sigma } => {
+                    if *sigma <= 0.0 {
+                        bail!("The blur sigma must be positive, got {}", sigma);
+                    }
+                    let sigma = sigma.to_bits();
+                    pass(&self.gaussian_blur, [*radius, sigma, 1, 0], None);
+                    pass(&self.gaussian_blur, [*radius, sigma, 0, 1], None);
+                }
+                Filter::Sobel => pass(&self.sobel, [0; 4], None),
+                Filter::ColorGrade(lut) => {
+                    v_luts.push(TypedBuffer::from_slice(
+                        &self.device,
+                        self.allocator,
+                        &lut.v_entries,
+                        vk::BufferUsageFlags::STORAGE_BUFFER,
+                    )?);
+                    pass(&self.lut, [lut.size, 0, 0, 0], Some(v_luts.len() - 1));
+                }
+                Filter::Resize {
+                    width: new_width,
+                    height: new_height,
+                } => {
+                    self.check_size(*new_width, *new_height)?;
+                    width = *new_width;
+                    height = *new_height;
+                    v_passes.push(Pass {
+                        pipeline: self.resize.handle(),
+                        push_constants: [0; 4],
+                        lut: None,
+                        width,
+                        height,
+                    });
+                }
+            }
+        }
+
+        // The input, then the output of each pass.
+        let mut v_images = vec![self.create_storage_image(image.width, image.height)?];
+        for pass in &v_passes {
+            v_images.push(self.create_storage_image(pass.width, pass.height)?);
+        }
+        let staging_buffer = TypedBuffer::from_slice(
+            &self.device,
+            self.allocator,
+            &image.pixels,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+        )?;
+        self.uploader.copy_buffer_to_image(
+            staging_buffer.handle(),
+            v_images[0].image.handle(),
+            image.width,
+            image.height,
+            ImageState {
+                layout: vk::ImageLayout::GENERAL,
+                stage: vk::PipelineStageFlags::COMPUTE_SHADER,
+                access: vk::AccessFlags::SHADER_READ,
+            },
+        )?;
+        drop(staging_buffer);
+        for storage_image in &v_images[1..] {
+            self.uploader.change_image_layout(
+                storage_image.image.handle(),
+                ImageState {
+                    layout: vk::ImageLayout::UNDEFINED,
+                    stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+                    access: vk::AccessFlags::empty(),
+                },
+                ImageState {
+                    layout: vk::ImageLayout::GENERAL,
+                    stage: vk::PipelineStageFlags::COMPUTE_SHADER,
+                    access: vk::AccessFlags::SHADER_WRITE,
+                },
+            )?;
+        }
+        let last_image = v_images.last().unwrap();
+        let readback_buffer = TypedBuffer::<u8>::new(
+            &self.device,
+            self.allocator,
+            (last_image.width * last_image.height * 4) as usize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+        let fence = self.execute(&v_passes, &v_images, &v_luts, &readback_buffer)?;
+        let pixels = readback_buffer.map_read(&fence)?.to_vec();
+        Ok(RgbaImage {
+            width: last_image.width,
+            height: last_image.height,
+            pixels,
+        })
+    }
+
+    fn check_size(&self, width: u32, height: u32) -> anyhow::Result<()> {
+        if width == 0
+            || height == 0
+            || width > self.max_image_dimension
+            || height > self.max_image_dimension
+        {
+            bail!(
+                "Image size {}x{} must be between 1 and {} on each axis",
+                width,
+                height,
+                self.max_image_dimension
+            );
+        }
+        Ok(())
+    }
+
+    unsafe fn create_storage_image(&self, width: u32, height: u32) -> anyhow::Result<StorageImage> {
+        let image_create_info = vk::ImageCreateInfo {
+            s_type: vk::StructureType::IMAGE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            image_type: vk::ImageType::TYPE_2D,
+            format: vk::Format::R8G8B8A8_UNORM,
+            extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: std::ptr::null(),
+            initial_layout: vk::ImageLayout::UNDEFINED,
+        };
+        let image = Image::new(&self.device, &image_create_info)?;
+        let allocation = self.allocator.allocate_for_image(
+            image.handle(),
+            vk::ImageTiling::OPTIMAL,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )?;
+        let image_view_create_info = vk::ImageViewCreateInfo {
+            s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            image: image.handle(),
+            view_type: vk::ImageViewType::TYPE_2D,
+            format: vk::Format::R8G8B8A8_UNORM,
+            components: vk::ComponentMapping {
+                r: vk::ComponentSwizzle::IDENTITY,
+                g: vk::ComponentSwizzle::IDENTITY,
+                b: vk::ComponentSwizzle::IDENTITY,
+                a: vk::ComponentSwizzle::IDENTITY,
+            },
+            subresource_range: COLOR_SUBRESOURCE_RANGE,
+        };
+        let view = ImageView::new(&self.device, &image_view_create_info)?;
+        Ok(StorageImage {
+            image,
+            _allocation: allocation,
+            view,
+            width,
+            height,
+        })
+    }
+
+    /// Records pass `i` from `v_images[i]` to `v_images[i + 1]`, then the copy
+    /// of the last image to `readback_buffer`, waits for the device and
+    /// returns the fence.
+    unsafe fn execute(
+        &self,
+        v_passes: &[Pass],
+        v_images: &[StorageImage],
+        v_luts: &[TypedBuffer<[f32; 4]>],
+        readback_buffer: &TypedBuffer<u8>,
+    ) -> anyhow::Result<Fence> {
+        let device = &self.device;
+        // Sized for at least one set, a pool cannot be empty.
+        let v_descriptor_pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: 2 * v_passes.len().max(1) as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: v_luts.len().max(1) as u32,
+            },
+        ];
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            max_sets: v_passes.len().max(1) as u32,
+            pool_size_count: v_descriptor_pool_sizes.len() as u32,
+            p_pool_sizes: v_descriptor_pool_sizes.as_ptr(),
+        };
+        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_create_info)?;
+        let v_descriptor_sets = if v_passes.is_empty() {
+            Vec::new()
+        } else {
+            let v_set_layouts = vec![self.descriptor_set_layout.handle(); v_passes.len()];
+            let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
+                s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+                p_next: std::ptr::null(),
+                descriptor_pool: descriptor_pool.handle(),
+                descriptor_set_count: v_set_layouts.len() as u32,
+                p_set_layouts: v_set_layouts.as_ptr(),
+            };
+            device.allocate_descriptor_sets(&descriptor_set_allocate_info)?
+        };
+
+        let fence = submit_one_time(device, device.compute_queue(), |command_buffer| {
+            for (index, (pass, &descriptor_set)) in
+                v_passes.iter().zip(v_descriptor_sets.iter()).enumerate()
+            {
+                let v_descriptor_image_infos: Vec<vk::DescriptorImageInfo> = v_images
+                    [index..index + 2]
+                    .iter()
+                    .map(|storage_image| vk::DescriptorImageInfo {
+                        sampler: vk::Sampler::null(),
+                        image_view: storage_image.view.handle(),
+                        image_layout: vk::ImageLayout::GENERAL,
+                    })
+                    .collect();
+                let mut v_descriptor_writes = vec![vk::WriteDescriptorSet {
+                    s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                    p_next: std::ptr::null(),
+                    dst_set: descriptor_set,
+                    dst_binding: 0,
+                    dst_array_element: 0,
+                    descriptor_count: 2,
+                    descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                    p_image_info: v_descriptor_image_infos.as_ptr(),
+                    p_buffer_info: std::ptr::null(),
+                    p_texel_buffer_view: std::ptr::null(),
+                }];
+                let descriptor_buffer_info = pass.lut.map(|lut| vk::DescriptorBufferInfo {
+                    buffer: v_luts[lut].handle(),
+                    offset: 0,
+                    range: vk::WHOLE_SIZE,
+                });
+                if let Some(descriptor_buffer_info) = &descriptor_buffer_info {
+                    v_descriptor_writes.push(vk::WriteDescriptorSet {
+                        s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                        p_next: std::ptr::null(),
+                        dst_set: descriptor_set,
+                        dst_binding: 2,
+                        dst_array_element: 0,
+                        descriptor_count: 1,
+                        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                        p_image_info: std::ptr::null(),
+                        p_buffer_info: descriptor_buffer_info,
+                        p_texel_buffer_view: std::ptr::null(),
+                    });
+                }
+                device.update_descriptor_sets(&v_descriptor_writes, &[]);
+
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    pass.pipeline,
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipeline_layout.handle(),
+                    0,
+                    &[descriptor_set],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout.handle(),
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    crate::buffer::as_bytes(&pass.push_constants),
+                );
+                device.cmd_dispatch(
+                    command_buffer,
+                    pass.width.div_ceil(TILE_SIZE),
+                    pass.height.div_ceil(TILE_SIZE),
+                    1,
+                );
+                // The next pass reads what this one wrote.
+                let image_memory_barrier = vk::ImageMemoryBarrier {
+                    s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+                    p_next: std::ptr::null(),
+                    src_access_mask: vk::AccessFlags::SHADER_WRITE,
+                    dst_access_mask: vk::AccessFlags::SHADER_READ,
+                    old_layout: vk::ImageLayout::GENERAL,
+                    new_layout: vk::ImageLayout::GENERAL,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    image: v_images[index + 1].image.handle(),
+                    subresource_range: COLOR_SUBRESOURCE_RANGE,
+                };
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    Default::default(),
+                    &[],
+                    &[],
+                    &[image_memory_barrier],
+                );
+            }
+
+            let last_image = v_images.last().unwrap();
+            let image_memory_barrier = vk::ImageMemoryBarrier {
+                s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+                p_next: std::ptr::null(),
+                src_access_mask: vk::AccessFlags::SHADER_WRITE,
+                dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+                old_layout: vk::ImageLayout::GENERAL,
+                new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: last_image.image.handle(),
+                subresource_range: COLOR_SUBRESOURCE_RANGE,
+            };
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                Default::default(),
+                &[],
+                &[],
+                &[image_memory_barrier],
+            );
+            let buffer_image_copy_region = vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width: last_image.width,
+                    height: last_image.height,
+                    depth: 1,
+                },
+            };
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                last_image.image.handle(),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buffer.handle(),
+                &[buffer_image_copy_region],
+            );
+            // Make the copy visible to the mapped read of the result.
+            let memory_barrier = vk::MemoryBarrier {
+                s_type: vk::StructureType::MEMORY_BARRIER,
+                p_next: std::ptr::null(),
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::HOST_READ,
+            };
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                Default::default(),
+                &[memory_barrier],
+                &[],
+                &[],
+            );
+        })?;
+        Ok(fence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY_CUBE: &str = "\
+# Identity
+TITLE \"identity\"
+LUT_3D_SIZE 2
+DOMAIN_MIN 0 0 0
+DOMAIN_MAX 1 1 1
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    #[test]
+    fn parses_a_cube_file() {
+        let lut = Lut::from_cube(IDENTITY_CUBE).unwrap();
+        assert_eq!(lut.size(), 2);
+        assert_eq!(lut.v_entries.len(), 8);
+        assert_eq!(lut.v_entries[1], [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(lut.v_entries[6], [0.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn rejects_a_wrong_entry_count() {
+        let text = IDENTITY_CUBE.replace("1 1 1\n", "");
+        assert!(Lut::from_cube(&text).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_cubes() {
+        assert!(Lut::from_cube("0 0 0\n").is_err());
+        assert!(Lut::from_cube(&IDENTITY_CUBE.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 1")).is_err());
+        assert!(
+            Lut::from_cube(&IDENTITY_CUBE.replace("DOMAIN_MAX 1 1 1", "DOMAIN_MAX 2 2 2")).is_err()
+        );
+        assert!(Lut::from_cube(&IDENTITY_CUBE.replace("LUT_3D_SIZE", "LUT_1D_SIZE")).is_err());
+        assert!(Lut::from_cube(&IDENTITY_CUBE.replace("1 0 1", "1 0")).is_err());
+    }
+}
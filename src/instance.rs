@@ -0,0 +1,424 @@
+use crate::debug::DebugMessenger;
+use crate::debug::DebugMessengerBuilder;
+use ash::extensions::ext::DebugUtils;
+use ash::version::EntryV1_0;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::fmt;
+use std::ops::Deref;
+use std::os::raw::c_char;
+use std::rc::Rc;
+
+pub const VALIDATION_LAYER_NAME: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Collects everything needed to create an `ash::Instance`.
+///
+/// The validation layer is requested by default, like it always was in the
+/// samples, together with a debug messenger configured by
+/// `DebugMessengerBuilder::from_env`. Both are optional: they are skipped with a
+/// note on stderr when the Vulkan SDK is not installed.
+pub struct InstanceBuilder {
+    application_name: CString,
+    engine_name: CString,
+    application_version: u32,
+    api_version: u32,
+    layers: Vec<Requested>,
+    extensions: Vec<Requested>,
+    debug_messenger: Option<DebugMessengerBuilder>,
+}
+
+struct Requested {
+    name: CString,
+    required: bool,
+}
+
+impl InstanceBuilder {
+    pub fn new(application_name: &str) -> Self {
+        InstanceBuilder {
+            application_name: to_c_string(application_name),
+            engine_name: to_c_string("Not Unreal Engine 4"),
+            application_version: ash::vk_make_version!(0, 0, 1),
+            api_version: ash::vk_make_version!(1, 0, 0),
+            layers: vec![Requested {
+                name: to_c_string(VALIDATION_LAYER_NAME),
+                required: false,
+            }],
+            extensions: Vec::new(),
+            debug_messenger: Some(DebugMessengerBuilder::from_env()),
+        }
+    }
+
+    pub fn engine_name(mut self, engine_name: &str) -> Self {
+        self.engine_name = to_c_string(engine_name);
+        self
+    }
+
+    pub fn application_version(mut self, version: u32) -> Self {
+        self.application_version = version;
+        self
+    }
+
+    pub fn api_version(mut self, version: u32) -> Self {
+        self.api_version = version;
+        self
+    }
+
+    /// Requests a layer, `build` fails if it is not available.
+    pub fn layer(self, name: &str) -> Self {
+        self.request_layer(name, true)
+    }
+
+    /// Requests a layer that is enabled only if available.
+    pub fn optional_layer(self, name: &str) -> Self {
+        self.request_layer(name, false)
+    }
+
+    /// Stops requesting a layer, e.g. the default validation layer.
+    pub fn without_layer(mut self, name: &str) -> Self {
+        let name = to_c_string(name);
+        self.layers.retain(|l| l.name != name);
+        self
+    }
+
+    /// Requests an extension, `build` fails if it is not available.
+    pub fn extension(self, name: &str) -> Self {
+        self.request_extension(name, true)
+    }
+
+    /// Requests an extension that is enabled only if available.
+    pub fn optional_extension(self, name: &str) -> Self {
+        self.request_extension(name, false)
+    }
+
+    /// Adds several required extensions at once, typically the list returned by
+    /// `sdl2::video::Window::vulkan_instance_extensions`.
+    pub fn extensions(mut self, names: &[&str]) -> Self {
+        for name in names {
+            self = self.request_extension(name, true);
+        }
+        self
+    }
+
+    /// Replaces the debug messenger settings, `None` disables the messenger.
+    pub fn debug_messenger(mut self, debug_messenger: Option<DebugMessengerBuilder>) -> Self {
+        self.debug_messenger = debug_messenger;
+        self
+    }
+
+    /// # Safety
+    ///
+    /// `entry` must stay loaded as long as the instance.
+    pub unsafe fn build(&self, entry: &ash::Entry) -> anyhow::Result<Rc<Instance>> {
+        let available_layers = available_instance_layers(entry)?;
+        let mut missing = MissingInstanceSupport::default();
+        let mut enabled_layers = Vec::new();
+        for layer in &self.layers {
+            if available_layers
+                .iter()
+                .any(|l| l.as_c_str() == layer.name.as_c_str())
+            {
+                push_unique(&mut enabled_layers, &layer.name);
+            } else if layer.required {
+                missing
+                    .layers
+                    .push(layer.name.to_string_lossy().into_owned());
+            } else {
+                eprintln!(
+                    "Optional instance layer {} is not available, skipping it",
+                    layer.name.to_string_lossy()
+                );
+            }
+        }
+
+        // Layers may provide extensions of their own, e.g. VK_EXT_debug_utils.
+        let available_extensions = available_instance_extensions(entry, &enabled_layers)?;
+        let mut v_requested_extensions: Vec<&Requested> = self.extensions.iter().collect();
+        let debug_utils = Requested {
+            name: DebugUtils::name().to_owned(),
+            required: false,
+        };
+        if self.debug_messenger.is_some() {
+            v_requested_extensions.push(&debug_utils);
+        }
+        let mut enabled_extensions = Vec::new();
+        for extension in v_requested_extensions {
+            if available_extensions
+                .iter()
+                .any(|e| e.as_c_str() == extension.name.as_c_str())
+            {
+                push_unique(&mut enabled_extensions, &extension.name);
+            } else if extension.required {
+                missing
+                    .extensions
+                    .push(extension.name.to_string_lossy().into_owned());
+            } else {
+                eprintln!(
+                    "Optional instance extension {} is not available, skipping it",
+                    extension.name.to_string_lossy()
+                );
+            }
+        }
+        if !missing.layers.is_empty() || !missing.extensions.is_empty() {
+            return Err(missing.into());
+        }
+
+        let application_info = vk::ApplicationInfo {
+            s_type: vk::StructureType::APPLICATION_INFO,
+            p_next: std::ptr::null(),
+            p_application_name: self.application_name.as_ptr(),
+            application_version: self.application_version,
+            p_engine_name: self.engine_name.as_ptr(),
+            engine_version: ash::vk_make_version!(0, 0, 1),
+            api_version: self.api_version,
+        };
+        let v_layers_c: Vec<*const c_char> = enabled_layers.iter().map(|l| l.as_ptr()).collect();
+        let v_extensions_c: Vec<*const c_char> =
+            enabled_extensions.iter().map(|e| e.as_ptr()).collect();
+        let messenger_state = match &self.debug_messenger {
+            Some(debug_messenger) if enabled_extensions.contains(&debug_utils.name) => {
+                Some(debug_messenger.build_state()?)
+            }
+            _ => None,
+        };
+        let messenger_create_info = messenger_state.as_ref().map(|state| state.create_info());
+        let instance_create_info = vk::InstanceCreateInfo {
+            s_type: vk::StructureType::INSTANCE_CREATE_INFO,
+            p_next: match &messenger_create_info {
+                Some(create_info) => create_info as *const _ as *const std::ffi::c_void,
+                None => std::ptr::null(),
+            },
+            flags: Default::default(),
+            p_application_info: &application_info,
+            enabled_layer_count: v_layers_c.len() as u32,
+            pp_enabled_layer_names: v_layers_c.as_ptr(),
+            enabled_extension_count: v_extensions_c.len() as u32,
+            pp_enabled_extension_names: v_extensions_c.as_ptr(),
+        };
+        let handle = entry.create_instance(&instance_create_info, None)?;
+        let debug_messenger = match messenger_state {
+            Some(state) => match DebugMessenger::new(entry, &handle, state) {
+                Ok(debug_messenger) => Some(debug_messenger),
+                Err((e, state)) => {
+                    // The layers can still call back into `state` while the
+                    // instance is destroyed.
+                    handle.destroy_instance(None);
+                    drop(state);
+                    return Err(e.into());
+                }
+            },
+            None => None,
+        };
+        Ok(Rc::new(Instance {
+            entry: entry.clone(),
+            handle,
+            api_version: self.api_version,
+            enabled_layers,
+            enabled_extensions,
+            debug_messenger,
+        }))
+    }
+
+    fn request_layer(mut self, name: &str, required: bool) -> Self {
+        let name = to_c_string(name);
+        match self.layers.iter_mut().find(|l| l.name == name) {
+            Some(layer) => layer.required |= required,
+            None => self.layers.push(Requested { name, required }),
+        }
+        self
+    }
+
+    fn request_extension(mut self, name: &str, required: bool) -> Self {
+        let name = to_c_string(name);
+        match self.extensions.iter_mut().find(|e| e.name == name) {
+            Some(extension) => extension.required |= required,
+            None => self.extensions.push(Requested { name, required }),
+        }
+        self
+    }
+}
+
+/// Required layers and extensions the Vulkan loader does not provide.
+#[derive(Debug, Default)]
+pub struct MissingInstanceSupport {
+    pub layers: Vec<String>,
+    pub extensions: Vec<String>,
+}
+
+impl fmt::Display for MissingInstanceSupport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cannot create instance:")?;
+        if !self.layers.is_empty() {
+            write!(f, " missing layers [{}]", self.layers.join(", "))?;
+        }
+        if !self.extensions.is_empty() {
+            write!(f, " missing extensions [{}]", self.extensions.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MissingInstanceSupport {}
+
+/// Owned `ash::Instance`, destroyed once every object created from it is gone.
+///
+/// Devices and surfaces keep an `Rc<Instance>`, so the instance cannot be
+/// destroyed before them.
+pub struct Instance {
+    entry: ash::Entry,
+    handle: ash::Instance,
+    api_version: u32,
+    enabled_layers: Vec<CString>,
+    enabled_extensions: Vec<CString>,
+    debug_messenger: Option<DebugMessenger>,
+}
+
+impl Instance {
+    pub fn entry(&self) -> &ash::Entry {
+        &self.entry
+    }
+
+    pub fn raw(&self) -> &ash::Instance {
+        &self.handle
+    }
+
+    /// Version given to `InstanceBuilder::api_version`, the highest the samples may use.
+    pub fn api_version(&self) -> u32 {
+        self.api_version
+    }
+
+    pub fn enabled_layers(&self) -> &[CString] {
+        &self.enabled_layers
+    }
+
+    pub fn enabled_extensions(&self) -> &[CString] {
+        &self.enabled_extensions
+    }
+
+    pub fn has_debug_messenger(&self) -> bool {
+        self.debug_messenger.is_some()
+    }
+}
+
+impl Deref for Instance {
+    type Target = ash::Instance;
+
+    fn deref(&self) -> &ash::Instance {
+        &self.handle
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(debug_messenger) = &self.debug_messenger {
+                debug_messenger.destroy();
+            }
+            self.handle.destroy_instance(None);
+        }
+    }
+}
+
+/// Names of the instance layers installed on this machine.
+///
+/// # Safety
+///
+/// `entry` must be a loaded Vulkan entry.
+pub unsafe fn available_instance_layers(entry: &ash::Entry) -> anyhow::Result<Vec<CString>> {
+    let instance_layers_properties = entry.enumerate_instance_layer_properties()?;
+    Ok(instance_layers_properties
+        .iter()
+        .map(|p| CStr::from_ptr(p.layer_name.as_ptr()).to_owned())
+        .collect())
+}
+
+/// Names of the instance extensions provided by the loader and by `layers`.
+///
+/// # Safety
+///
+/// `entry` must be a loaded Vulkan entry.
+pub unsafe fn available_instance_extensions(
+    entry: &ash::Entry,
+    layers: &[CString],
+) -> anyhow::Result<Vec<CString>> {
+    let mut v_extensions = Vec::new();
+    for extension_properties in entry.enumerate_instance_extension_properties()? {
+        push_unique(
+            &mut v_extensions,
+            CStr::from_ptr(extension_properties.extension_name.as_ptr()),
+        );
+    }
+    for layer in layers {
+        for extension_properties in enumerate_layer_extension_properties(entry, layer)? {
+            push_unique(
+                &mut v_extensions,
+                CStr::from_ptr(extension_properties.extension_name.as_ptr()),
+            );
+        }
+    }
+    Ok(v_extensions)
+}
+
+/// # Safety
+///
+/// `entry` must be a loaded Vulkan entry.
+pub unsafe fn print_instance_layers(entry: &ash::Entry) {
+    let instance_layers = available_instance_layers(entry).expect("Cannot find instance layers");
+    for layer_name in &instance_layers {
+        println!("instance layer name = {}", layer_name.to_string_lossy());
+    }
+}
+
+/// # Safety
+///
+/// `entry` must be a loaded Vulkan entry.
+pub unsafe fn print_instance_extensions(entry: &ash::Entry) {
+    let instance_extensions =
+        available_instance_extensions(entry, &[]).expect("Cannot retrieve instance extensions");
+    println!("instance extensions: {}", instance_extensions.len());
+    for extension_name in &instance_extensions {
+        println!(
+            "instance extensions name = {}",
+            extension_name.to_string_lossy()
+        );
+    }
+}
+
+// ash 0.29 only wraps the query for the extensions of the implementation.
+unsafe fn enumerate_layer_extension_properties(
+    entry: &ash::Entry,
+    layer: &CStr,
+) -> ash::prelude::VkResult<Vec<vk::ExtensionProperties>> {
+    let mut count = 0;
+    let result = entry.fp_v1_0().enumerate_instance_extension_properties(
+        layer.as_ptr(),
+        &mut count,
+        std::ptr::null_mut(),
+    );
+    if result != vk::Result::SUCCESS {
+        return Err(result);
+    }
+    let mut v_extension_properties = Vec::with_capacity(count as usize);
+    let result = entry.fp_v1_0().enumerate_instance_extension_properties(
+        layer.as_ptr(),
+        &mut count,
+        v_extension_properties.as_mut_ptr(),
+    );
+    if result != vk::Result::SUCCESS && result != vk::Result::INCOMPLETE {
+        return Err(result);
+    }
+    v_extension_properties.set_len(count as usize);
+    Ok(v_extension_properties)
+}
+
+fn push_unique(names: &mut Vec<CString>, name: &CStr) {
+    if !names.iter().any(|n| n.as_c_str() == name) {
+        names.push(name.to_owned());
+    }
+}
+
+// SDL hands out extension names that may still carry their nul terminator.
+fn to_c_string(name: &str) -> CString {
+    CString::new(name.trim_end_matches('\0')).expect("Name contains an interior nul byte")
+}
@@ -0,0 +1,221 @@
+//! Minimal JSON tree, enough for the reports written by our tools.
+
+use std::fmt;
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// Already formatted number, so integers keep their full precision.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object() -> Self {
+        Json::Object(Vec::new())
+    }
+
+    /// Appends a member to an object, does nothing on other values.
+    pub fn with(mut self, key: &str, value: impl Into<Json>) -> Self {
+        if let Json::Object(members) = &mut self {
+            members.push((key.to_owned(), value.into()));
+        }
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn to_pretty_string(&self) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, 0).unwrap();
+        output
+    }
+
+    fn write_pretty(&self, output: &mut String, indent: usize) -> fmt::Result {
+        match self {
+            Json::Array(values) if !values.is_empty() => {
+                output.push_str("[\n");
+                for (i, value) in values.iter().enumerate() {
+                    write!(output, "{:1$}", "", indent + 2)?;
+                    value.write_pretty(output, indent + 2)?;
+                    output.push_str(if i + 1 < values.len() { ",\n" } else { "\n" });
+                }
+                write!(output, "{:1$}]", "", indent)
+            }
+            Json::Object(members) if !members.is_empty() => {
+                output.push_str("{\n");
+                for (i, (key, value)) in members.iter().enumerate() {
+                    write!(output, "{:1$}", "", indent + 2)?;
+                    write_escaped(output, key)?;
+                    output.push_str(": ");
+                    value.write_pretty(output, indent + 2)?;
+                    output.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+                }
+                write!(output, "{:1$}}}", "", indent)
+            }
+            _ => write!(output, "{}", self),
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    /// Compact JSON.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => {
+                let mut escaped = String::new();
+                write_escaped(&mut escaped, s)?;
+                write!(f, "{}", escaped)
+            }
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    let mut escaped = String::new();
+                    write_escaped(&mut escaped, key)?;
+                    write!(f, "{}:{}", escaped, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_escaped(output: &mut String, s: &str) -> fmt::Result {
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(output, "\\u{:04x}", c as u32)?,
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    Ok(())
+}
+
+macro_rules! json_number {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Json {
+            fn from(value: $t) -> Self {
+                Json::Number(value.to_string())
+            }
+        })*
+    };
+}
+
+json_number!(u8, u16, u32, u64, usize, i8, i16, i32, i64);
+
+impl From<f32> for Json {
+    fn from(value: f32) -> Self {
+        if value.is_finite() {
+            Json::Number(value.to_string())
+        } else {
+            Json::Null
+        }
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        if value.is_finite() {
+            Json::Number(value.to_string())
+        } else {
+            Json::Null
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Self {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Json> + Copy, const N: usize> From<[T; N]> for Json {
+    fn from(values: [T; N]) -> Self {
+        Json::Array(values.iter().map(|v| (*v).into()).collect())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => Json::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        let value = Json::from("quote \" backslash \\ newline \n tab \t bell \u{7} é");
+        assert_eq!(
+            value.to_string(),
+            r#""quote \" backslash \\ newline \n tab \t bell \u0007 é""#
+        );
+    }
+
+    #[test]
+    fn escapes_keys() {
+        let value = Json::object().with("a\"b", 1u32);
+        assert_eq!(value.to_string(), r#"{"a\"b":1}"#);
+        assert_eq!(value.to_pretty_string(), "{\n  \"a\\\"b\": 1\n}");
+    }
+
+    #[test]
+    fn non_finite_floats_are_null() {
+        assert_eq!(Json::from(f32::NAN), Json::Null);
+        assert_eq!(Json::from(f64::INFINITY), Json::Null);
+    }
+}
@@ -0,0 +1,36 @@
+//! Vulkan bootstrap code shared by the samples in `src/bin/`.
+//!
+//! Every sample used to carry its own copy of the instance, device, memory
+//! and shader helpers. They now live here so the samples (and our own tools)
+//! only keep the code that is specific to what they demonstrate.
+
+extern crate anyhow;
+extern crate ash;
+extern crate nalgebra_glm as glm;
+extern crate shaderc;
+
+pub mod allocator;
+pub mod buffer;
+pub mod compute;
+pub mod debug;
+pub mod device;
+pub mod features;
+pub mod hot_reload;
+pub mod image_filter;
+pub mod instance;
+pub mod json;
+pub mod memory;
+pub mod objects;
+pub mod pipeline;
+pub mod pipeline_cache;
+pub mod pipeline_statistics;
+pub mod primitives;
+pub mod profiler;
+pub mod reflection;
+pub mod report;
+pub mod selector;
+pub mod shader;
+pub mod shader_compiler;
+pub mod swapchain;
+pub mod transfer;
+pub mod verify;
@@ -0,0 +1,27 @@
+use ash::version::InstanceV1_0;
+use ash::vk;
+
+/// Returns the index of the first memory type allowed by `requirements` that has
+/// all the properties in `type_to_find`.
+///
+/// # Safety
+///
+/// `gpu` must have been enumerated from `instance`.
+pub unsafe fn search_physical_device_memory_type(
+    instance: &ash::Instance,
+    gpu: vk::PhysicalDevice,
+    requirements: &vk::MemoryRequirements,
+    type_to_find: vk::MemoryPropertyFlags,
+) -> Result<usize, &'static str> {
+    let memory_properties = instance.get_physical_device_memory_properties(gpu);
+    let memory_types =
+        &memory_properties.memory_types[..memory_properties.memory_type_count as usize];
+    for (index, memory_type) in memory_types.iter().enumerate() {
+        if requirements.memory_type_bits & (1 << index) > 0
+            && memory_type.property_flags.contains(type_to_find)
+        {
+            return Ok(index);
+        }
+    }
+    Err("Cannot find device memory type")
+}
@@ -0,0 +1,245 @@
+//! Owned wrappers for the Vulkan objects created from a logical device.
+//!
+//! Each wrapper keeps an `Rc<Device>` and destroys its handle on `Drop`, so the
+//! device always outlives the objects created from it.
+
+use crate::device::Device;
+use ash::version::DeviceV1_0;
+use ash::vk;
+use std::rc::Rc;
+
+macro_rules! device_object {
+    ($(#[$meta:meta])* $name:ident, $handle:ty, $create_info:ty, $create:ident, $destroy:ident) => {
+        $(#[$meta])*
+        pub struct $name {
+            device: Rc<Device>,
+            handle: $handle,
+        }
+
+        impl $name {
+            /// # Safety
+            ///
+            /// `create_info` and everything it points to must be valid for
+            /// `device`.
+            pub unsafe fn new(device: &Rc<Device>, create_info: &$create_info) -> ash::prelude::VkResult<Self> {
+                let handle = device.$create(create_info, None)?;
+                Ok(Self::from_raw(device, handle))
+            }
+
+            /// Takes ownership of a handle created directly through `ash`.
+            ///
+            /// # Safety
+            ///
+            /// `handle` must come from `device` and must not be destroyed
+            /// elsewhere: the wrapper destroys it when dropped.
+            pub unsafe fn from_raw(device: &Rc<Device>, handle: $handle) -> Self {
+                $name {
+                    device: device.clone(),
+                    handle,
+                }
+            }
+
+            pub fn handle(&self) -> $handle {
+                self.handle
+            }
+
+            pub fn device(&self) -> &Rc<Device> {
+                &self.device
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                unsafe {
+                    self.device.$destroy(self.handle, None);
+                }
+            }
+        }
+    };
+}
+
+device_object!(
+    Buffer,
+    vk::Buffer,
+    vk::BufferCreateInfo,
+    create_buffer,
+    destroy_buffer
+);
+device_object!(
+    DeviceMemory,
+    vk::DeviceMemory,
+    vk::MemoryAllocateInfo,
+    allocate_memory,
+    free_memory
+);
+device_object!(
+    Image,
+    vk::Image,
+    vk::ImageCreateInfo,
+    create_image,
+    destroy_image
+);
+device_object!(
+    ImageView,
+    vk::ImageView,
+    vk::ImageViewCreateInfo,
+    create_image_view,
+    destroy_image_view
+);
+device_object!(
+    Sampler,
+    vk::Sampler,
+    vk::SamplerCreateInfo,
+    create_sampler,
+    destroy_sampler
+);
+device_object!(
+    ShaderModule,
+    vk::ShaderModule,
+    vk::ShaderModuleCreateInfo,
+    create_shader_module,
+    destroy_shader_module
+);
+device_object!(
+    DescriptorSetLayout,
+    vk::DescriptorSetLayout,
+    vk::DescriptorSetLayoutCreateInfo,
+    create_descriptor_set_layout,
+    destroy_descriptor_set_layout
+);
+device_object!(
+    /// Destroying the pool also frees the descriptor sets allocated from it.
+    DescriptorPool,
+    vk::DescriptorPool,
+    vk::DescriptorPoolCreateInfo,
+    create_descriptor_pool,
+    destroy_descriptor_pool
+);
+device_object!(
+    PipelineLayout,
+    vk::PipelineLayout,
+    vk::PipelineLayoutCreateInfo,
+    create_pipeline_layout,
+    destroy_pipeline_layout
+);
+device_object!(
+    RenderPass,
+    vk::RenderPass,
+    vk::RenderPassCreateInfo,
+    create_render_pass,
+    destroy_render_pass
+);
+device_object!(
+    Framebuffer,
+    vk::Framebuffer,
+    vk::FramebufferCreateInfo,
+    create_framebuffer,
+    destroy_framebuffer
+);
+device_object!(
+    /// Destroying the pool also frees the command buffers allocated from it.
+    CommandPool,
+    vk::CommandPool,
+    vk::CommandPoolCreateInfo,
+    create_command_pool,
+    destroy_command_pool
+);
+device_object!(
+    Semaphore,
+    vk::Semaphore,
+    vk::SemaphoreCreateInfo,
+    create_semaphore,
+    destroy_semaphore
+);
+device_object!(
+    Fence,
+    vk::Fence,
+    vk::FenceCreateInfo,
+    create_fence,
+    destroy_fence
+);
+
+impl Fence {
+    /// Whether the fence is signaled, without waiting.
+    pub fn is_signaled(&self) -> ash::prelude::VkResult<bool> {
+        match unsafe { self.device.get_fence_status(self.handle) } {
+            Ok(()) => Ok(true),
+            Err(vk::Result::NOT_READY) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+device_object!(
+    PipelineCache,
+    vk::PipelineCache,
+    vk::PipelineCacheCreateInfo,
+    create_pipeline_cache,
+    destroy_pipeline_cache
+);
+device_object!(
+    QueryPool,
+    vk::QueryPool,
+    vk::QueryPoolCreateInfo,
+    create_query_pool,
+    destroy_query_pool
+);
+
+/// Graphics or compute pipeline.
+pub struct Pipeline {
+    device: Rc<Device>,
+    handle: vk::Pipeline,
+}
+
+impl Pipeline {
+    /// # Safety
+    ///
+    /// `create_info` and the states, shader modules and layout it points to
+    /// must be valid for `device`; `pipeline_cache` may be null.
+    pub unsafe fn new_graphics(
+        device: &Rc<Device>,
+        pipeline_cache: vk::PipelineCache,
+        create_info: &vk::GraphicsPipelineCreateInfo,
+    ) -> ash::prelude::VkResult<Self> {
+        let v_pipelines = device
+            .create_graphics_pipelines(pipeline_cache, &[*create_info], None)
+            .map_err(|(_, e)| e)?;
+        Ok(Self::from_raw(device, v_pipelines[0]))
+    }
+
+    /// # Safety
+    ///
+    /// Same as `new_graphics`.
+    pub unsafe fn new_compute(
+        device: &Rc<Device>,
+        pipeline_cache: vk::PipelineCache,
+        create_info: &vk::ComputePipelineCreateInfo,
+    ) -> ash::prelude::VkResult<Self> {
+        let v_pipelines = device
+            .create_compute_pipelines(pipeline_cache, &[*create_info], None)
+            .map_err(|(_, e)| e)?;
+        Ok(Self::from_raw(device, v_pipelines[0]))
+    }
+
+    /// # Safety
+    ///
+    /// `handle` must come from `device` and must not be destroyed elsewhere.
+    pub unsafe fn from_raw(device: &Rc<Device>, handle: vk::Pipeline) -> Self {
+        Pipeline {
+            device: device.clone(),
+            handle,
+        }
+    }
+
+    pub fn handle(&self) -> vk::Pipeline {
+        self.handle
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.handle, None);
+        }
+    }
+}
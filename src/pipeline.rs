@@ -0,0 +1,122 @@
+//! Pipeline layouts with push constant ranges, and compute pipelines whose
+//! specialization constants are set at creation, so one SPIR-V module can be
+//! reused with different workgroup sizes or problem sizes.
+
+use crate::buffer::Pod;
+use crate::device::Device;
+use crate::objects::Pipeline;
+use crate::objects::PipelineLayout;
+use crate::objects::ShaderModule;
+use ash::prelude::VkResult;
+use ash::vk;
+use std::ffi::CStr;
+use std::rc::Rc;
+
+/// Values of a shader's `layout(constant_id = ...)` constants.
+#[derive(Clone, Debug, Default)]
+pub struct SpecializationConstants {
+    v_map_entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl SpecializationConstants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets constant `constant_id`; `T` must match the constant's type in the
+    /// shader (`u32` for `uint` and workgroup sizes, `i32`, `f32`...).
+    pub fn set<T: Pod>(mut self, constant_id: u32, value: T) -> Self {
+        let bytes = crate::buffer::as_bytes(std::slice::from_ref(&value));
+        match self
+            .v_map_entries
+            .iter()
+            .find(|entry| entry.constant_id == constant_id)
+        {
+            Some(entry) if entry.size == bytes.len() => {
+                let offset = entry.offset as usize;
+                self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            }
+            Some(_) => panic!("Specialization constant {} changed size", constant_id),
+            None => {
+                self.v_map_entries.push(vk::SpecializationMapEntry {
+                    constant_id,
+                    offset: self.data.len() as u32,
+                    size: bytes.len(),
+                });
+                self.data.extend_from_slice(bytes);
+            }
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v_map_entries.is_empty()
+    }
+
+    /// Points into `self`, which must outlive the pipeline creation.
+    pub fn info(&self) -> vk::SpecializationInfo {
+        vk::SpecializationInfo {
+            map_entry_count: self.v_map_entries.len() as u32,
+            p_map_entries: self.v_map_entries.as_ptr(),
+            data_size: self.data.len(),
+            p_data: self.data.as_ptr() as *const std::ffi::c_void,
+        }
+    }
+}
+
+/// # Safety
+///
+/// The descriptor set layouts must come from `device`.
+pub unsafe fn create_pipeline_layout(
+    device: &Rc<Device>,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> VkResult<PipelineLayout> {
+    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
+        s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+        p_next: std::ptr::null(),
+        flags: Default::default(),
+        set_layout_count: descriptor_set_layouts.len() as u32,
+        p_set_layouts: descriptor_set_layouts.as_ptr(),
+        push_constant_range_count: push_constant_ranges.len() as u32,
+        p_push_constant_ranges: push_constant_ranges.as_ptr(),
+    };
+    PipelineLayout::new(device, &pipeline_layout_create_info)
+}
+
+/// # Safety
+///
+/// `pipeline_layout` and `shader_module` must come from `device`; `pipeline_cache` may be null.
+pub unsafe fn create_compute_pipeline(
+    device: &Rc<Device>,
+    pipeline_cache: vk::PipelineCache,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: &ShaderModule,
+    entry_point: &CStr,
+    specialization_constants: &SpecializationConstants,
+) -> VkResult<Pipeline> {
+    let specialization_info = specialization_constants.info();
+    let compute_pipeline_create_info = vk::ComputePipelineCreateInfo {
+        s_type: vk::StructureType::COMPUTE_PIPELINE_CREATE_INFO,
+        p_next: std::ptr::null(),
+        flags: Default::default(),
+        stage: vk::PipelineShaderStageCreateInfo {
+            s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            stage: vk::ShaderStageFlags::COMPUTE,
+            module: shader_module.handle(),
+            p_name: entry_point.as_ptr(),
+            p_specialization_info: if specialization_constants.is_empty() {
+                std::ptr::null()
+            } else {
+                &specialization_info
+            },
+        },
+        layout: pipeline_layout,
+        base_pipeline_handle: vk::Pipeline::null(),
+        base_pipeline_index: -1,
+    };
+    Pipeline::new_compute(device, pipeline_cache, &compute_pipeline_create_info)
+}
@@ -0,0 +1,297 @@
+//! `VkPipelineCache` persisted on disk between runs.
+//!
+//! The cache file is named after the device's `pipelineCacheUUID` and driver
+//! version, so another GPU or a driver update starts from an empty cache
+//! instead of handing the driver data it cannot use. The header of the file is
+//! also checked against the device before the data is given to Vulkan.
+
+use crate::device::Device;
+use crate::objects::PipelineCache;
+use anyhow::Context;
+use ash::version::DeviceV1_0;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::cell::Cell;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Size of `VkPipelineCacheHeaderVersionOne`.
+const HEADER_SIZE: usize = 32;
+
+/// Options of the pipeline cache, read from the command line.
+#[derive(Clone, Debug)]
+pub struct PipelineCacheOptions {
+    pub directory: PathBuf,
+    /// Whether the cache is loaded and saved; `false` starts from an empty
+    /// cache every time, to measure pipeline creation without it.
+    pub persistent: bool,
+}
+
+impl PipelineCacheOptions {
+    /// `--pipeline-cache-dir <directory>` chooses where cache files go (by
+    /// default `$XDG_CACHE_HOME/vulkan-samples` or
+    /// `~/.cache/vulkan-samples`), `--no-pipeline-cache` disables them.
+    pub fn from_args() -> Self {
+        let v_args: Vec<String> = std::env::args().collect();
+        let directory = match v_args.iter().position(|arg| arg == "--pipeline-cache-dir") {
+            Some(index) => match v_args.get(index + 1) {
+                Some(directory) => PathBuf::from(directory),
+                None => panic!("Missing value after --pipeline-cache-dir"),
+            },
+            None => default_directory(),
+        };
+        PipelineCacheOptions {
+            directory,
+            persistent: !v_args.iter().any(|arg| arg == "--no-pipeline-cache"),
+        }
+    }
+}
+
+fn default_directory() -> PathBuf {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")));
+    match cache_home {
+        Some(cache_home) => cache_home.join("vulkan-samples"),
+        None => PathBuf::from("pipeline-cache"),
+    }
+}
+
+/// Checks that `data` starts with a header written by this device and driver.
+fn validate_header(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> anyhow::Result<()> {
+    if data.len() < HEADER_SIZE {
+        anyhow::bail!("{} bytes is too short for a header", data.len());
+    }
+    let word = |index: usize| {
+        u32::from_le_bytes([
+            data[4 * index],
+            data[4 * index + 1],
+            data[4 * index + 2],
+            data[4 * index + 3],
+        ])
+    };
+    let header_size = word(0) as usize;
+    if header_size < HEADER_SIZE || header_size > data.len() {
+        anyhow::bail!("invalid header size {}", header_size);
+    }
+    if word(1) != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+        anyhow::bail!("unknown header version {}", word(1));
+    }
+    if (word(2), word(3)) != (properties.vendor_id, properties.device_id) {
+        anyhow::bail!(
+            "written by device {:04x}:{:04x}, not {:04x}:{:04x}",
+            word(2),
+            word(3),
+            properties.vendor_id,
+            properties.device_id
+        );
+    }
+    if data[16..HEADER_SIZE] != properties.pipeline_cache_uuid {
+        anyhow::bail!("pipeline cache UUID differs");
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// What was found in the cache file when loading it.
+#[derive(Clone, Debug)]
+enum LoadStatus {
+    Disabled,
+    Missing,
+    Loaded(usize),
+    Rejected(String),
+}
+
+pub struct DiskPipelineCache {
+    device: Rc<Device>,
+    pipeline_cache: PipelineCache,
+    path: PathBuf,
+    persistent: bool,
+    status: LoadStatus,
+    pipeline_count: Cell<usize>,
+    creation_time: Cell<Duration>,
+}
+
+impl DiskPipelineCache {
+    /// Creates a pipeline cache filled from the file of `name` (e.g. the
+    /// sample's name) for this device, when there is a valid one.
+    ///
+    /// # Safety
+    ///
+    /// `device` must outlive the cache.
+    pub unsafe fn load(
+        device: &Rc<Device>,
+        name: &str,
+        options: &PipelineCacheOptions,
+    ) -> anyhow::Result<Self> {
+        let properties = device
+            .instance()
+            .get_physical_device_properties(device.gpu());
+        let path = options.directory.join(format!(
+            "{}-{}-{:08x}.bin",
+            name,
+            hex(&properties.pipeline_cache_uuid),
+            properties.driver_version
+        ));
+        let (data, status) = if !options.persistent {
+            (Vec::new(), LoadStatus::Disabled)
+        } else {
+            match std::fs::read(&path) {
+                Ok(data) => match validate_header(&data, &properties) {
+                    Ok(()) => {
+                        let size = data.len();
+                        (data, LoadStatus::Loaded(size))
+                    }
+                    Err(e) => (Vec::new(), LoadStatus::Rejected(e.to_string())),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    (Vec::new(), LoadStatus::Missing)
+                }
+                Err(e) => (Vec::new(), LoadStatus::Rejected(e.to_string())),
+            }
+        };
+        let pipeline_cache_create_info = vk::PipelineCacheCreateInfo {
+            s_type: vk::StructureType::PIPELINE_CACHE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            initial_data_size: data.len(),
+            p_initial_data: data.as_ptr() as *const std::ffi::c_void,
+        };
+        Ok(DiskPipelineCache {
+            device: device.clone(),
+            pipeline_cache: PipelineCache::new(device, &pipeline_cache_create_info)
+                .context("Cannot create pipeline cache")?,
+            path,
+            persistent: options.persistent,
+            status,
+            pipeline_count: Cell::new(0),
+            creation_time: Cell::new(Duration::default()),
+        })
+    }
+
+    pub fn handle(&self) -> vk::PipelineCache {
+        self.pipeline_cache.handle()
+    }
+
+    /// Runs `create`, which creates a pipeline with `handle()`, and adds the
+    /// time it took to the report.
+    pub fn timed<T>(&self, create: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = create();
+        self.creation_time
+            .set(self.creation_time.get() + start.elapsed());
+        self.pipeline_count.set(self.pipeline_count.get() + 1);
+        result
+    }
+
+    /// Writes the cache to its file, through a temporary file so a sample
+    /// killed while saving leaves no truncated cache behind.
+    ///
+    /// # Safety
+    ///
+    /// No pipeline may be created through the cache at the same time.
+    pub unsafe fn save(&self) -> anyhow::Result<()> {
+        if !self.persistent {
+            return Ok(());
+        }
+        let data = self
+            .device
+            .get_pipeline_cache_data(self.handle())
+            .context("Cannot get pipeline cache data")?;
+        std::fs::create_dir_all(self.path.parent().unwrap())
+            .with_context(|| format!("Cannot create {}", self.path.parent().unwrap().display()))?;
+        let temporary_path = self.path.with_extension("tmp");
+        std::fs::write(&temporary_path, &data)
+            .with_context(|| format!("Cannot write {}", temporary_path.display()))?;
+        std::fs::rename(&temporary_path, &self.path)
+            .with_context(|| format!("Cannot write {}", self.path.display()))
+    }
+}
+
+impl fmt::Display for DiskPipelineCache {
+    /// Pipeline creation time and where the cache came from.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Created {} pipelines in {:.2} ms, ",
+            self.pipeline_count.get(),
+            self.creation_time.get().as_secs_f64() * 1e3
+        )?;
+        match &self.status {
+            LoadStatus::Disabled => write!(f, "without pipeline cache"),
+            LoadStatus::Missing => write!(f, "new pipeline cache {}", self.path.display()),
+            LoadStatus::Loaded(size) => write!(
+                f,
+                "pipeline cache of {} bytes from {}",
+                size,
+                self.path.display()
+            ),
+            LoadStatus::Rejected(reason) => write!(
+                f,
+                "pipeline cache {} ignored: {}",
+                self.path.display(),
+                reason
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x1234,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    /// Header written by `properties()`, followed by some driver data.
+    fn header() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(
+            &(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes(),
+        );
+        data.extend_from_slice(&0x10deu32.to_le_bytes());
+        data.extend_from_slice(&0x1234u32.to_le_bytes());
+        data.extend_from_slice(&[7; vk::UUID_SIZE]);
+        data.extend_from_slice(&[0xff; 8]);
+        data
+    }
+
+    #[test]
+    fn accepts_its_own_header() {
+        assert!(validate_header(&header(), &properties()).is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert!(validate_header(&header()[..HEADER_SIZE - 1], &properties()).is_err());
+        let mut data = header();
+        data[0] = 200;
+        assert!(validate_header(&data, &properties()).is_err());
+    }
+
+    #[test]
+    fn rejects_another_device_or_driver() {
+        let mut data = header();
+        data[4] = 2;
+        assert!(validate_header(&data, &properties()).is_err());
+        let mut data = header();
+        data[12] = 0;
+        assert!(validate_header(&data, &properties()).is_err());
+        let mut data = header();
+        data[HEADER_SIZE - 1] = 0;
+        assert!(validate_header(&data, &properties()).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Pipeline statistics queries (shader invocations, clipping) around recorded
+//! work, for devices supporting the `pipelineStatisticsQuery` feature.
+//!
+//! Like `GpuProfiler`, a `PipelineStatistics` has one slot per command buffer
+//! that can be in flight. The query of a slot is reset and begun before the
+//! work, ended after it, and read back by `collect` once the fence of the
+//! command buffer has signaled.
+
+use crate::device::Device;
+use crate::features::FeatureSet;
+use crate::objects::QueryPool;
+use anyhow::bail;
+use anyhow::Context;
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Counted statistics, written by the device in the order of their bits.
+const STATISTIC_FLAGS: [vk::QueryPipelineStatisticFlags; 7] = [
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES,
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES,
+    vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES,
+    vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS,
+];
+
+/// Options of the statistics mode, read from the command line.
+#[derive(Clone, Debug)]
+pub struct StatisticsOptions {
+    /// Number of frames the averages are computed over.
+    pub interval: usize,
+    pub csv_path: Option<String>,
+}
+
+impl StatisticsOptions {
+    /// Returns `Some` when `--stats` or `--stats-csv <file>` is on the command
+    /// line, with `--stats-interval <frames>` applied (100 by default).
+    pub fn from_args() -> Option<Self> {
+        let v_args: Vec<String> = std::env::args().collect();
+        let value_of = |name: &str| {
+            v_args
+                .iter()
+                .position(|arg| arg == name)
+                .map(|index| match v_args.get(index + 1) {
+                    Some(value) => value.clone(),
+                    None => panic!("Missing value after {}", name),
+                })
+        };
+        let csv_path = value_of("--stats-csv");
+        if csv_path.is_none() && !v_args.iter().any(|arg| arg == "--stats") {
+            return None;
+        }
+        let interval = match value_of("--stats-interval") {
+            Some(value) => value
+                .parse()
+                .ok()
+                .filter(|&interval| interval > 0)
+                .unwrap_or_else(|| panic!("Invalid stats interval {}", value)),
+            None => 100,
+        };
+        Some(StatisticsOptions { interval, csv_path })
+    }
+}
+
+/// Statistics of one slot, or their sum over several frames.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineCounters {
+    pub input_assembly_vertices: u64,
+    pub input_assembly_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    pub clipping_invocations: u64,
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+    pub compute_shader_invocations: u64,
+}
+
+impl PipelineCounters {
+    fn from_results(v_results: &[u64; 7]) -> Self {
+        PipelineCounters {
+            input_assembly_vertices: v_results[0],
+            input_assembly_primitives: v_results[1],
+            vertex_shader_invocations: v_results[2],
+            clipping_invocations: v_results[3],
+            clipping_primitives: v_results[4],
+            fragment_shader_invocations: v_results[5],
+            compute_shader_invocations: v_results[6],
+        }
+    }
+
+    /// Names (also used as CSV columns) and values.
+    pub fn fields(&self) -> [(&'static str, u64); 7] {
+        [
+            ("input_assembly_vertices", self.input_assembly_vertices),
+            ("input_assembly_primitives", self.input_assembly_primitives),
+            ("vertex_shader_invocations", self.vertex_shader_invocations),
+            ("clipping_invocations", self.clipping_invocations),
+            ("clipping_primitives", self.clipping_primitives),
+            (
+                "fragment_shader_invocations",
+                self.fragment_shader_invocations,
+            ),
+            (
+                "compute_shader_invocations",
+                self.compute_shader_invocations,
+            ),
+        ]
+    }
+}
+
+unsafe fn create_query_pool(device: &Rc<Device>, slot_count: usize) -> VkResult<QueryPool> {
+    let query_pool_create_info = vk::QueryPoolCreateInfo {
+        s_type: vk::StructureType::QUERY_POOL_CREATE_INFO,
+        p_next: std::ptr::null(),
+        flags: Default::default(),
+        query_type: vk::QueryType::PIPELINE_STATISTICS,
+        query_count: slot_count as u32,
+        pipeline_statistics: STATISTIC_FLAGS
+            .iter()
+            .fold(vk::QueryPipelineStatisticFlags::empty(), |flags, &flag| {
+                flags | flag
+            }),
+    };
+    QueryPool::new(device, &query_pool_create_info)
+}
+
+struct State {
+    /// Last counters read, at most `interval` of them.
+    v_window: VecDeque<PipelineCounters>,
+    frame_count: u64,
+    csv: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+pub struct PipelineStatistics {
+    device: Rc<Device>,
+    query_pool: RefCell<QueryPool>,
+    interval: usize,
+    state: RefCell<State>,
+}
+
+impl PipelineStatistics {
+    /// Features to give to `DeviceBuilder::optional_features`.
+    pub fn features() -> FeatureSet {
+        FeatureSet::from_core(
+            vk::PhysicalDeviceFeatures::builder()
+                .pipeline_statistics_query(true)
+                .build(),
+        )
+    }
+
+    /// Creates `slot_count` queries. Fails when `pipelineStatisticsQuery` is
+    /// not enabled on `device`, or when the CSV file cannot be created.
+    ///
+    /// # Safety
+    ///
+    /// `device` must outlive the queries.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        slot_count: usize,
+        options: &StatisticsOptions,
+    ) -> anyhow::Result<Self> {
+        if device.enabled_features().core.pipeline_statistics_query == vk::FALSE {
+            bail!("The device does not support pipelineStatisticsQuery");
+        }
+        let csv = match &options.csv_path {
+            Some(path) => {
+                let mut csv = std::io::BufWriter::new(
+                    std::fs::File::create(path)
+                        .with_context(|| format!("Cannot create {}", path))?,
+                );
+                let v_columns: Vec<&str> = PipelineCounters::default()
+                    .fields()
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect();
+                writeln!(csv, "frame,slot,{}", v_columns.join(","))?;
+                Some(csv)
+            }
+            None => None,
+        };
+        Ok(PipelineStatistics {
+            device: device.clone(),
+            query_pool: RefCell::new(create_query_pool(device, slot_count)?),
+            interval: options.interval,
+            state: RefCell::new(State {
+                v_window: VecDeque::with_capacity(options.interval),
+                frame_count: 0,
+                csv,
+            }),
+        })
+    }
+
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    /// Changes the number of slots, e.g. when the swapchain is recreated with
+    /// another number of images. The counters read so far are kept. None of
+    /// the queries may be in use.
+    ///
+    /// # Safety
+    ///
+    /// No command buffer recording or executing the queries may still be pending.
+    pub unsafe fn resize(&self, slot_count: usize) -> anyhow::Result<()> {
+        *self.query_pool.borrow_mut() = create_query_pool(&self.device, slot_count)?;
+        Ok(())
+    }
+
+    /// Resets and begins the query of `slot`. Record it outside of a render
+    /// pass, before the work to count.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, outside of a render pass, and `slot` must not be in use
+    /// by another pending command buffer.
+    pub unsafe fn begin(&self, command_buffer: vk::CommandBuffer, slot: usize) {
+        let query_pool = self.query_pool.borrow().handle();
+        self.device
+            .cmd_reset_query_pool(command_buffer, query_pool, slot as u32, 1);
+        self.device.cmd_begin_query(
+            command_buffer,
+            query_pool,
+            slot as u32,
+            vk::QueryControlFlags::empty(),
+        );
+    }
+
+    /// # Safety
+    ///
+    /// Must follow `begin` for `slot` in the same command buffer.
+    pub unsafe fn end(&self, command_buffer: vk::CommandBuffer, slot: usize) {
+        self.device.cmd_end_query(
+            command_buffer,
+            self.query_pool.borrow().handle(),
+            slot as u32,
+        );
+    }
+
+    /// Reads the counters of `slot`, whose command buffer must have been
+    /// submitted and waited for, and appends them to the CSV file. Returns
+    /// `None` when they are not available.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer that recorded `slot` must have completed.
+    pub unsafe fn collect(&self, slot: usize) -> anyhow::Result<Option<PipelineCounters>> {
+        // ash only reads results of up to 8 bytes, one query here is 7 of them.
+        let mut v_results = [0u64; 7];
+        let result = self.device.fp_v1_0().get_query_pool_results(
+            self.device.handle(),
+            self.query_pool.borrow().handle(),
+            slot as u32,
+            1,
+            std::mem::size_of_val(&v_results),
+            v_results.as_mut_ptr() as *mut std::ffi::c_void,
+            std::mem::size_of_val(&v_results) as vk::DeviceSize,
+            vk::QueryResultFlags::TYPE_64,
+        );
+        match result {
+            vk::Result::SUCCESS => {}
+            vk::Result::NOT_READY => return Ok(None),
+            e => return Err(e).context("Cannot read pipeline statistics"),
+        }
+
+        let counters = PipelineCounters::from_results(&v_results);
+        let mut state = self.state.borrow_mut();
+        let State {
+            v_window,
+            frame_count,
+            csv,
+        } = &mut *state;
+        if let Some(csv) = csv {
+            let v_values: Vec<String> = counters
+                .fields()
+                .iter()
+                .map(|(_, value)| value.to_string())
+                .collect();
+            writeln!(csv, "{},{},{}", frame_count, slot, v_values.join(","))?;
+        }
+        *frame_count += 1;
+        if v_window.len() == self.interval {
+            v_window.pop_front();
+        }
+        v_window.push_back(counters);
+        Ok(Some(counters))
+    }
+
+    /// Number of frames collected so far.
+    pub fn frame_count(&self) -> u64 {
+        self.state.borrow().frame_count
+    }
+
+    /// Average counters per frame over the last `interval` frames.
+    pub fn average(&self) -> Option<PipelineCounters> {
+        let state = self.state.borrow();
+        let count = state.v_window.len() as u64;
+        if count == 0 {
+            return None;
+        }
+        let sum = |value: fn(&PipelineCounters) -> u64| {
+            state.v_window.iter().map(value).sum::<u64>() / count
+        };
+        Some(PipelineCounters {
+            input_assembly_vertices: sum(|c| c.input_assembly_vertices),
+            input_assembly_primitives: sum(|c| c.input_assembly_primitives),
+            vertex_shader_invocations: sum(|c| c.vertex_shader_invocations),
+            clipping_invocations: sum(|c| c.clipping_invocations),
+            clipping_primitives: sum(|c| c.clipping_primitives),
+            fragment_shader_invocations: sum(|c| c.fragment_shader_invocations),
+            compute_shader_invocations: sum(|c| c.compute_shader_invocations),
+        })
+    }
+
+    /// Flushes the CSV file, also done when dropped.
+    pub fn flush(&self) -> std::io::Result<()> {
+        match &mut self.state.borrow_mut().csv {
+            Some(csv) => csv.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for PipelineStatistics {
+    /// Average counters per frame, one per line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let average = match self.average() {
+            Some(average) => average,
+            None => return Ok(()),
+        };
+        writeln!(
+            f,
+            "Pipeline statistics per frame (average over {} frames):",
+            self.state.borrow().v_window.len()
+        )?;
+        for (name, value) in average.fields().iter() {
+            writeln!(f, "    {:28} {}", name, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,663 @@
+//! Parallel primitives on `u32` values: reduce, prefix scan, radix sort and
+//! histogram, for inputs of any size.
+//!
+//! The kernels in `shaders/primitives/` each handle blocks of `BLOCK_SIZE`
+//! values, compiled in as a define; the functions here chain as many passes as
+//! the input needs (scan of the block sums, reduction of the partial
+//! results...) in one command buffer, submitted to the compute queue. The
+//! `cpu` module has the reference implementations the results are checked
+//! against.
+
+use crate::allocator::DeviceMemoryAllocator;
+use crate::buffer::TypedBuffer;
+use crate::device::Device;
+use crate::objects::DescriptorPool;
+use crate::objects::DescriptorSetLayout;
+use crate::objects::Fence;
+use crate::objects::Pipeline;
+use crate::objects::PipelineLayout;
+use crate::pipeline::create_compute_pipeline;
+use crate::pipeline::create_pipeline_layout;
+use crate::pipeline::SpecializationConstants;
+use crate::pipeline_cache::DiskPipelineCache;
+use crate::shader::create_shader_module_from_code;
+use crate::shader_compiler::ShaderCompiler;
+use crate::transfer::submit_one_time;
+use anyhow::bail;
+use anyhow::Context;
+use ash::version::DeviceV1_0;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::rc::Rc;
+
+/// Number of values handled by one workgroup, `local_size_x` of every kernel
+/// (the `BLOCK_SIZE` define of the shaders).
+pub const BLOCK_SIZE: usize = 256;
+
+/// Bits of the key sorted by each radix sort pass.
+const RADIX_BITS: u32 = 4;
+
+/// The discriminants are the `operation` push constant of reduce.comp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReduceOp {
+    Add = 0,
+    Min = 1,
+    Max = 2,
+}
+
+impl ReduceOp {
+    fn identity(self) -> u32 {
+        match self {
+            ReduceOp::Add | ReduceOp::Max => 0,
+            ReduceOp::Min => u32::MAX,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanKind {
+    /// `output[i]` is the sum of `input[0..=i]`.
+    Inclusive,
+    /// `output[i]` is the sum of `input[0..i]`.
+    Exclusive,
+}
+
+/// CPU reference implementations. Additions wrap like on the device.
+pub mod cpu {
+    use super::ReduceOp;
+    use super::ScanKind;
+
+    pub fn reduce(values: &[u32], op: ReduceOp) -> u32 {
+        values
+            .iter()
+            .fold(op.identity(), |accumulator, &value| match op {
+                ReduceOp::Add => accumulator.wrapping_add(value),
+                ReduceOp::Min => accumulator.min(value),
+                ReduceOp::Max => accumulator.max(value),
+            })
+    }
+
+    pub fn scan(values: &[u32], kind: ScanKind) -> Vec<u32> {
+        let mut sum = 0u32;
+        values
+            .iter()
+            .map(|&value| {
+                let previous = sum;
+                sum = sum.wrapping_add(value);
+                match kind {
+                    ScanKind::Inclusive => sum,
+                    ScanKind::Exclusive => previous,
+                }
+            })
+            .collect()
+    }
+
+    pub fn sort(values: &[u32]) -> Vec<u32> {
+        let mut sorted = values.to_vec();
+        sorted.sort_unstable();
+        sorted
+    }
+
+    /// Bin `i` counts the values in `[i * bin_width, (i + 1) * bin_width)`,
+    /// the last bin also counts everything above.
+    pub fn histogram(values: &[u32], bin_count: u32, bin_width: u32) -> anyhow::Result<Vec<u32>> {
+        super::check_bins(bin_count, bin_width)?;
+        let mut bins = vec![0u32; bin_count as usize];
+        for &value in values {
+            bins[(value / bin_width).min(bin_count - 1) as usize] += 1;
+        }
+        Ok(bins)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn reduce_of_nothing_is_the_identity() {
+            assert_eq!(reduce(&[], ReduceOp::Add), 0);
+            assert_eq!(reduce(&[], ReduceOp::Min), u32::MAX);
+            assert_eq!(reduce(&[], ReduceOp::Max), 0);
+        }
+
+        #[test]
+        fn reduce_add_wraps() {
+            assert_eq!(reduce(&[u32::MAX, 2], ReduceOp::Add), 1);
+            assert_eq!(reduce(&[3, 1, 2], ReduceOp::Min), 1);
+            assert_eq!(reduce(&[3, 1, 2], ReduceOp::Max), 3);
+        }
+
+        #[test]
+        fn scan_inclusive_and_exclusive() {
+            assert!(scan(&[], ScanKind::Inclusive).is_empty());
+            assert_eq!(scan(&[1, 2, 3], ScanKind::Inclusive), [1, 3, 6]);
+            assert_eq!(scan(&[1, 2, 3], ScanKind::Exclusive), [0, 1, 3]);
+        }
+
+        #[test]
+        fn scan_wraps() {
+            assert_eq!(
+                scan(&[u32::MAX, 1, 1], ScanKind::Inclusive),
+                [u32::MAX, 0, 1]
+            );
+            assert_eq!(
+                scan(&[u32::MAX, 1, 1], ScanKind::Exclusive),
+                [0, u32::MAX, 0]
+            );
+        }
+
+        #[test]
+        fn sort_ascending() {
+            assert!(sort(&[]).is_empty());
+            assert_eq!(sort(&[3, u32::MAX, 0, 3]), [0, 3, 3, u32::MAX]);
+        }
+
+        #[test]
+        fn histogram_last_bin_counts_the_overflow() {
+            assert_eq!(histogram(&[], 3, 10).unwrap(), [0, 0, 0]);
+            assert_eq!(
+                histogram(&[0, 9, 10, 25, 30, u32::MAX], 3, 10).unwrap(),
+                [2, 1, 3]
+            );
+        }
+
+        #[test]
+        fn histogram_rejects_empty_bins() {
+            assert!(histogram(&[1], 0, 10).is_err());
+            assert!(histogram(&[1], 3, 0).is_err());
+        }
+    }
+}
+
+fn check_bins(bin_count: u32, bin_width: u32) -> anyhow::Result<()> {
+    if bin_count == 0 || bin_width == 0 {
+        bail!("The bin count and width must not be 0");
+    }
+    Ok(())
+}
+
+/// One kernel launch: up to three storage buffers and the push constants.
+struct Dispatch {
+    pipeline: vk::Pipeline,
+    v_buffers: [vk::Buffer; 3],
+    push_constants: [u32; 4],
+    group_count: u32,
+}
+
+pub struct Primitives<'a> {
+    device: Rc<Device>,
+    allocator: &'a DeviceMemoryAllocator,
+    max_group_count: u32,
+    descriptor_set_layout: DescriptorSetLayout,
+    pipeline_layout: PipelineLayout,
+    reduce: Pipeline,
+    scan: Pipeline,
+    scan_add: Pipeline,
+    radix_count: Pipeline,
+    radix_scatter: Pipeline,
+    histogram: Pipeline,
+    histogram_merge: Pipeline,
+}
+
+impl<'a> Primitives<'a> {
+    /// Builds the pipelines of every kernel in `shaders/primitives/`, through
+    /// `pipeline_cache`.
+    ///
+    /// # Safety
+    ///
+    /// `allocator` and `pipeline_cache` must have been created for `device`.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        allocator: &'a DeviceMemoryAllocator,
+        pipeline_cache: &DiskPipelineCache,
+    ) -> anyhow::Result<Self> {
+        let max_group_count = device
+            .instance()
+            .get_physical_device_properties(device.gpu())
+            .limits
+            .max_compute_work_group_count[0];
+
+        let v_bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..3)
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                p_immutable_samplers: std::ptr::null(),
+            })
+            .collect();
+        let descriptor_set_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            binding_count: v_bindings.len() as u32,
+            p_bindings: v_bindings.as_ptr(),
+        };
+        let descriptor_set_layout =
+            DescriptorSetLayout::new(device, &descriptor_set_layout_create_info)?;
+        let push_constant_range = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<[u32; 4]>() as u32,
+        };
+        let pipeline_layout = create_pipeline_layout(
+            device,
+            &[descriptor_set_layout.handle()],
+            &[push_constant_range],
+        )?;
+
+        let entry_point = std::ffi::CString::new("main").unwrap();
+        let compiler = ShaderCompiler::new().define("BLOCK_SIZE", BLOCK_SIZE);
+        let create_pipeline = |name: &str| -> anyhow::Result<Pipeline> {
+            let shader_path = format!("shaders/primitives/{}.comp", name);
+            let shader_module =
+                create_shader_module_from_code(device, &compiler.compile_file(&shader_path)?)?;
+            pipeline_cache
+                .timed(|| {
+                    create_compute_pipeline(
+                        device,
+                        pipeline_cache.handle(),
+                        pipeline_layout.handle(),
+                        &shader_module,
+                        &entry_point,
+                        &SpecializationConstants::new(),
+                    )
+                })
+                .with_context(|| format!("Cannot create compute pipeline from {}", shader_path))
+        };
+        Ok(Primitives {
+            device: device.clone(),
+            allocator,
+            max_group_count,
+            reduce: create_pipeline("reduce")?,
+            scan: create_pipeline("scan")?,
+            scan_add: create_pipeline("scan_add")?,
+            radix_count: create_pipeline("radix_count")?,
+            radix_scatter: create_pipeline("radix_scatter")?,
+            histogram: create_pipeline("histogram")?,
+            histogram_merge: create_pipeline("histogram_merge")?,
+            descriptor_set_layout,
+            pipeline_layout,
+        })
+    }
+
+    /// Combines every value with `op`; additions wrap.
+    ///
+    /// # Safety
+    ///
+    /// The device must still be alive; the call waits for the work it submits.
+    pub unsafe fn reduce(&self, values: &[u32], op: ReduceOp) -> anyhow::Result<u32> {
+        if values.is_empty() {
+            return Ok(op.identity());
+        }
+        self.group_count(values.len())?;
+        let input = self.host_buffer(values)?;
+        // One buffer of partial results per pass, the last one holds the result.
+        let mut v_partials = Vec::new();
+        let mut count = values.len();
+        while count > 1 {
+            count = block_count(count);
+            v_partials.push(if count == 1 {
+                self.host_buffer(&[0])?
+            } else {
+                self.scratch_buffer(count)?
+            });
+        }
+        let mut v_dispatches = Vec::new();
+        let mut source = input.handle();
+        let mut count = values.len();
+        for partial in &v_partials {
+            v_dispatches.push(Dispatch {
+                pipeline: self.reduce.handle(),
+                v_buffers: [source, partial.handle(), partial.handle()],
+                push_constants: [count as u32, op as u32, 0, 0],
+                group_count: block_count(count) as u32,
+            });
+            source = partial.handle();
+            count = block_count(count);
+        }
+        let fence = self.execute(&v_dispatches)?;
+        let result = match v_partials.last() {
+            Some(partial) => partial.map_read(&fence)?[0],
+            None => values[0],
+        };
+        Ok(result)
+    }
+
+    /// Prefix sum of `values`; additions wrap.
+    ///
+    /// # Safety
+    ///
+    /// Same as `reduce`.
+    pub unsafe fn scan(&self, values: &[u32], kind: ScanKind) -> anyhow::Result<Vec<u32>> {
+        if values.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.group_count(values.len())?;
+        let input = self.host_buffer(values)?;
+        let output = self.host_buffer(&vec![0; values.len()])?;
+        let v_sums = self.scan_sums(values.len())?;
+        let mut v_dispatches = Vec::new();
+        self.record_scan(
+            &mut v_dispatches,
+            input.handle(),
+            output.handle(),
+            values.len(),
+            kind,
+            &v_sums,
+        );
+        let fence = self.execute(&v_dispatches)?;
+        let result = output.map_read(&fence)?.to_vec();
+        Ok(result)
+    }
+
+    /// Sorts `values` in ascending order, 4 bits per pass.
+    ///
+    /// # Safety
+    ///
+    /// Same as `reduce`.
+    pub unsafe fn sort(&self, values: &[u32]) -> anyhow::Result<Vec<u32>> {
+        if values.is_empty() {
+            return Ok(Vec::new());
+        }
+        let group_count = self.group_count(values.len())?;
+        let digit_count = 1usize << RADIX_BITS;
+        self.group_count(digit_count * group_count as usize)?;
+        // Each pass moves the keys from one buffer to the other; the pass count
+        // is even so the sorted keys end up back in `keys`.
+        let keys = self.host_buffer(values)?;
+        let other_keys = self.scratch_buffer(values.len())?;
+        let counts = self.scratch_buffer(digit_count * group_count as usize)?;
+        let v_sums = self.scan_sums(counts.len())?;
+        let mut v_dispatches = Vec::new();
+        let mut source = keys.handle();
+        let mut destination = other_keys.handle();
+        for shift in (0..32).step_by(RADIX_BITS as usize) {
+            v_dispatches.push(Dispatch {
+                pipeline: self.radix_count.handle(),
+                v_buffers: [source, counts.handle(), counts.handle()],
+                push_constants: [values.len() as u32, shift, 0, 0],
+                group_count,
+            });
+            self.record_scan(
+                &mut v_dispatches,
+                counts.handle(),
+                counts.handle(),
+                counts.len(),
+                ScanKind::Exclusive,
+                &v_sums,
+            );
+            v_dispatches.push(Dispatch {
+                pipeline: self.radix_scatter.handle(),
+                v_buffers: [source, destination, counts.handle()],
+                push_constants: [values.len() as u32, shift, 0, 0],
+                group_count,
+            });
+            std::mem::swap(&mut source, &mut destination);
+        }
+        let fence = self.execute(&v_dispatches)?;
+        let result = keys.map_read(&fence)?.to_vec();
+        Ok(result)
+    }
+
+    /// Counts `values` per bin, see `cpu::histogram`.
+    ///
+    /// # Safety
+    ///
+    /// Same as `reduce`.
+    pub unsafe fn histogram(
+        &self,
+        values: &[u32],
+        bin_count: u32,
+        bin_width: u32,
+    ) -> anyhow::Result<Vec<u32>> {
+        check_bins(bin_count, bin_width)?;
+        if values.is_empty() {
+            return Ok(vec![0; bin_count as usize]);
+        }
+        let group_count = self.group_count(values.len())?;
+        let merge_group_count = self.group_count(bin_count as usize)?;
+        let block_bin_count = group_count as u64 * bin_count as u64;
+        if block_bin_count > u32::MAX as u64 {
+            bail!(
+                "{} blocks of {} bins do not fit in a storage buffer",
+                group_count,
+                bin_count
+            );
+        }
+        let input = self.host_buffer(values)?;
+        let block_bins = self.scratch_buffer(block_bin_count as usize)?;
+        let bins = self.host_buffer(&vec![0; bin_count as usize])?;
+        let v_dispatches = [
+            Dispatch {
+                pipeline: self.histogram.handle(),
+                v_buffers: [input.handle(), block_bins.handle(), block_bins.handle()],
+                push_constants: [values.len() as u32, bin_count, bin_width, 0],
+                group_count,
+            },
+            Dispatch {
+                pipeline: self.histogram_merge.handle(),
+                v_buffers: [block_bins.handle(), bins.handle(), bins.handle()],
+                push_constants: [group_count, bin_count, 0, 0],
+                group_count: merge_group_count,
+            },
+        ];
+        let fence = self.execute(&v_dispatches)?;
+        let result = bins.map_read(&fence)?.to_vec();
+        Ok(result)
+    }
+
+    /// Number of blocks covering `count` values, checked against the device.
+    fn group_count(&self, count: usize) -> anyhow::Result<u32> {
+        let group_count = block_count(count);
+        if group_count > self.max_group_count as usize {
+            bail!(
+                "{} values need {} workgroups, the device supports {}",
+                count,
+                group_count,
+                self.max_group_count
+            );
+        }
+        Ok(group_count as u32)
+    }
+
+    unsafe fn host_buffer(&self, values: &[u32]) -> anyhow::Result<TypedBuffer<u32>> {
+        TypedBuffer::from_slice(
+            &self.device,
+            self.allocator,
+            values,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )
+    }
+
+    unsafe fn scratch_buffer(&self, len: usize) -> anyhow::Result<TypedBuffer<u32>> {
+        TypedBuffer::new(
+            &self.device,
+            self.allocator,
+            len,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+    }
+
+    /// Block sums of every level of a scan of `count` values, down to a
+    /// single block.
+    unsafe fn scan_sums(&self, count: usize) -> anyhow::Result<Vec<TypedBuffer<u32>>> {
+        let mut v_sums = Vec::new();
+        let mut count = count;
+        loop {
+            count = block_count(count);
+            v_sums.push(self.scratch_buffer(count)?);
+            if count == 1 {
+                return Ok(v_sums);
+            }
+        }
+    }
+
+    /// Scans each level, then adds the scanned sums of each level to the level
+    /// below. `input` and `output` may be the same buffer.
+    fn record_scan(
+        &self,
+        v_dispatches: &mut Vec<Dispatch>,
+        input: vk::Buffer,
+        output: vk::Buffer,
+        count: usize,
+        kind: ScanKind,
+        v_sums: &[TypedBuffer<u32>],
+    ) {
+        let mut v_levels = vec![(output, count)];
+        let mut source = input;
+        let mut kind = kind;
+        for sums in v_sums {
+            let (values, count) = *v_levels.last().unwrap();
+            v_dispatches.push(Dispatch {
+                pipeline: self.scan.handle(),
+                v_buffers: [source, values, sums.handle()],
+                push_constants: [count as u32, (kind == ScanKind::Exclusive) as u32, 0, 0],
+                group_count: block_count(count) as u32,
+            });
+            if count <= BLOCK_SIZE {
+                break;
+            }
+            // Block sums are scanned in place and exclusively, giving the
+            // offset of each block.
+            v_levels.push((sums.handle(), sums.len()));
+            source = sums.handle();
+            kind = ScanKind::Exclusive;
+        }
+        for pair in v_levels.windows(2).rev() {
+            let ((values, count), (offsets, _)) = (pair[0], pair[1]);
+            v_dispatches.push(Dispatch {
+                pipeline: self.scan_add.handle(),
+                v_buffers: [offsets, values, values],
+                push_constants: [count as u32, 0, 0, 0],
+                group_count: block_count(count) as u32,
+            });
+        }
+    }
+
+    /// Records the dispatches in order, each one waiting for the previous
+    /// ones' writes, waits for the device and returns the fence.
+    unsafe fn execute(&self, v_dispatches: &[Dispatch]) -> anyhow::Result<Fence> {
+        let device = &self.device;
+        let descriptor_pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 3 * v_dispatches.len() as u32,
+        };
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+            max_sets: v_dispatches.len() as u32,
+            pool_size_count: 1,
+            p_pool_sizes: &descriptor_pool_size,
+        };
+        let descriptor_pool = DescriptorPool::new(device, &descriptor_pool_create_info)?;
+        let v_set_layouts = vec![self.descriptor_set_layout.handle(); v_dispatches.len()];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+            p_next: std::ptr::null(),
+            descriptor_pool: descriptor_pool.handle(),
+            descriptor_set_count: v_set_layouts.len() as u32,
+            p_set_layouts: v_set_layouts.as_ptr(),
+        };
+        let v_descriptor_sets = device.allocate_descriptor_sets(&descriptor_set_allocate_info)?;
+        let v_descriptor_buffer_infos: Vec<vk::DescriptorBufferInfo> = v_dispatches
+            .iter()
+            .flat_map(|dispatch| dispatch.v_buffers.iter())
+            .map(|&buffer| vk::DescriptorBufferInfo {
+                buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            })
+            .collect();
+        let v_descriptor_writes: Vec<vk::WriteDescriptorSet> = v_descriptor_sets
+            .iter()
+            .zip(v_descriptor_buffer_infos.chunks_exact(3))
+            .map(
+                |(&descriptor_set, descriptor_buffer_infos)| vk::WriteDescriptorSet {
+                    s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                    p_next: std::ptr::null(),
+                    dst_set: descriptor_set,
+                    dst_binding: 0,
+                    dst_array_element: 0,
+                    descriptor_count: 3,
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                    p_image_info: std::ptr::null(),
+                    p_buffer_info: descriptor_buffer_infos.as_ptr(),
+                    p_texel_buffer_view: std::ptr::null(),
+                },
+            )
+            .collect();
+        device.update_descriptor_sets(&v_descriptor_writes, &[]);
+
+        let fence = submit_one_time(device, device.compute_queue(), |command_buffer| {
+            for (index, (dispatch, &descriptor_set)) in v_dispatches
+                .iter()
+                .zip(v_descriptor_sets.iter())
+                .enumerate()
+            {
+                if index > 0 {
+                    let memory_barrier = vk::MemoryBarrier {
+                        s_type: vk::StructureType::MEMORY_BARRIER,
+                        p_next: std::ptr::null(),
+                        src_access_mask: vk::AccessFlags::SHADER_WRITE,
+                        dst_access_mask: vk::AccessFlags::SHADER_READ
+                            | vk::AccessFlags::SHADER_WRITE,
+                    };
+                    device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        Default::default(),
+                        &[memory_barrier],
+                        &[],
+                        &[],
+                    );
+                }
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    dispatch.pipeline,
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipeline_layout.handle(),
+                    0,
+                    &[descriptor_set],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout.handle(),
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    crate::buffer::as_bytes(&dispatch.push_constants),
+                );
+                device.cmd_dispatch(command_buffer, dispatch.group_count, 1, 1);
+            }
+            // Make the shader writes visible to the mapped reads of the results.
+            let memory_barrier = vk::MemoryBarrier {
+                s_type: vk::StructureType::MEMORY_BARRIER,
+                p_next: std::ptr::null(),
+                src_access_mask: vk::AccessFlags::SHADER_WRITE,
+                dst_access_mask: vk::AccessFlags::HOST_READ,
+            };
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST,
+                Default::default(),
+                &[memory_barrier],
+                &[],
+                &[],
+            );
+        })?;
+        Ok(fence)
+    }
+}
+
+fn block_count(count: usize) -> usize {
+    count.div_ceil(BLOCK_SIZE)
+}
@@ -0,0 +1,389 @@
+//! GPU timestamps around recorded work (dispatches, render passes, uploads).
+//!
+//! A `GpuProfiler` owns a timestamp query pool split in slots, one per command
+//! buffer that can be in flight (e.g. one per swapchain image). A slot is reset
+//! at the start of its command buffer, each scope writes a timestamp before and
+//! after the work, and `collect` reads the slot back once its fence has
+//! signaled. Durations are kept over a window of frames for min/avg/max, and
+//! every scope can be exported as a Chrome trace (chrome://tracing, Perfetto).
+
+use crate::device::Device;
+use crate::json::Json;
+use crate::objects::QueryPool;
+use anyhow::bail;
+use anyhow::Context;
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+/// Trace events kept for the Chrome trace, later ones are dropped.
+const MAX_TRACE_EVENTS: usize = 100_000;
+
+/// Options of the profiling mode, read from the command line.
+#[derive(Clone, Debug)]
+pub struct ProfilerOptions {
+    /// Number of frames the statistics are computed over.
+    pub window: usize,
+    pub trace_path: Option<String>,
+}
+
+impl ProfilerOptions {
+    /// Returns `Some` when `--profile` or `--trace <file>` is on the command
+    /// line, with `--profile-window <frames>` applied (100 by default).
+    pub fn from_args() -> Option<Self> {
+        let v_args: Vec<String> = std::env::args().collect();
+        let value_of = |name: &str| {
+            v_args
+                .iter()
+                .position(|arg| arg == name)
+                .map(|index| match v_args.get(index + 1) {
+                    Some(value) => value.clone(),
+                    None => panic!("Missing value after {}", name),
+                })
+        };
+        let trace_path = value_of("--trace");
+        if trace_path.is_none() && !v_args.iter().any(|arg| arg == "--profile") {
+            return None;
+        }
+        let window = match value_of("--profile-window") {
+            Some(value) => value
+                .parse()
+                .ok()
+                .filter(|&window| window > 0)
+                .unwrap_or_else(|| panic!("Invalid profile window {}", value)),
+            None => 100,
+        };
+        Some(ProfilerOptions { window, trace_path })
+    }
+}
+
+unsafe fn create_query_pool(
+    device: &Rc<Device>,
+    slot_count: usize,
+    scopes_per_slot: u32,
+) -> VkResult<QueryPool> {
+    let query_pool_create_info = vk::QueryPoolCreateInfo {
+        s_type: vk::StructureType::QUERY_POOL_CREATE_INFO,
+        p_next: std::ptr::null(),
+        flags: Default::default(),
+        query_type: vk::QueryType::TIMESTAMP,
+        query_count: slot_count as u32 * scopes_per_slot * 2,
+        pipeline_statistics: Default::default(),
+    };
+    QueryPool::new(device, &query_pool_create_info)
+}
+
+/// Timestamps of one scope, returned by `begin_scope` and given back to
+/// `end_scope`.
+#[derive(Clone, Copy, Debug)]
+pub struct ScopeId {
+    query: u32,
+}
+
+/// Durations of a scope over the window, in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct ScopeStats {
+    pub count: usize,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+struct TraceEvent {
+    name: String,
+    slot: usize,
+    begin: u64,
+    duration: u64,
+}
+
+#[derive(Default)]
+struct State {
+    /// Names of the scopes recorded in each slot, in query order.
+    v_slot_scopes: Vec<Vec<String>>,
+    /// Last durations of each scope name, in the order the names were seen.
+    v_windows: Vec<(String, VecDeque<f64>)>,
+    first_timestamp: Option<u64>,
+    v_trace_events: Vec<TraceEvent>,
+}
+
+pub struct GpuProfiler {
+    device: Rc<Device>,
+    query_pool: RefCell<QueryPool>,
+    queue_family_index: u32,
+    scopes_per_slot: u32,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f64,
+    /// Bits of a timestamp that are meaningful on this queue family.
+    timestamp_mask: u64,
+    window: usize,
+    state: RefCell<State>,
+}
+
+impl GpuProfiler {
+    /// Creates `slot_count` slots of `scopes_per_slot` scopes, for command
+    /// buffers submitted to a queue of `queue_family_index`.
+    ///
+    /// # Safety
+    ///
+    /// `queue_family_index` must be a queue family of `device`, which must outlive the profiler.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        queue_family_index: u32,
+        slot_count: usize,
+        scopes_per_slot: u32,
+        window: usize,
+    ) -> anyhow::Result<Self> {
+        let instance = device.instance();
+        let timestamp_valid_bits = instance
+            .get_physical_device_queue_family_properties(device.gpu())
+            .get(queue_family_index as usize)
+            .context("Invalid queue family")?
+            .timestamp_valid_bits;
+        if timestamp_valid_bits == 0 {
+            bail!(
+                "Queue family {} does not support timestamps",
+                queue_family_index
+            );
+        }
+        let timestamp_period = instance
+            .get_physical_device_properties(device.gpu())
+            .limits
+            .timestamp_period as f64;
+        Ok(GpuProfiler {
+            device: device.clone(),
+            query_pool: RefCell::new(create_query_pool(device, slot_count, scopes_per_slot)?),
+            queue_family_index,
+            scopes_per_slot,
+            timestamp_period,
+            timestamp_mask: if timestamp_valid_bits >= 64 {
+                u64::MAX
+            } else {
+                (1u64 << timestamp_valid_bits) - 1
+            },
+            window: window.max(1),
+            state: RefCell::new(State {
+                v_slot_scopes: (0..slot_count).map(|_| Vec::new()).collect(),
+                ..Default::default()
+            }),
+        })
+    }
+
+    pub fn queue_family_index(&self) -> u32 {
+        self.queue_family_index
+    }
+
+    /// Changes the number of slots, e.g. when the swapchain is recreated with
+    /// another number of images. The scopes recorded in the slots are
+    /// forgotten, the statistics and the trace are kept. None of the queries
+    /// may be in use.
+    ///
+    /// # Safety
+    ///
+    /// No command buffer recording or executing the queries may still be pending.
+    pub unsafe fn resize(&self, slot_count: usize) -> VkResult<()> {
+        *self.query_pool.borrow_mut() =
+            create_query_pool(&self.device, slot_count, self.scopes_per_slot)?;
+        self.state.borrow_mut().v_slot_scopes = (0..slot_count).map(|_| Vec::new()).collect();
+        Ok(())
+    }
+
+    /// Resets the queries of `slot` and forgets its scopes. Record it at the
+    /// start of the command buffer, outside of a render pass.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, outside of a render pass, and must be submitted to a
+    /// queue of the profiler's family.
+    pub unsafe fn record_reset(&self, command_buffer: vk::CommandBuffer, slot: usize) {
+        self.state.borrow_mut().v_slot_scopes[slot].clear();
+        self.device.cmd_reset_query_pool(
+            command_buffer,
+            self.query_pool.borrow().handle(),
+            slot as u32 * self.scopes_per_slot * 2,
+            self.scopes_per_slot * 2,
+        );
+    }
+
+    /// Writes the timestamp starting the scope `name`, once every previous
+    /// command has started.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording and must have recorded `record_reset` for `slot`.
+    pub unsafe fn begin_scope(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        name: &str,
+    ) -> ScopeId {
+        let mut state = self.state.borrow_mut();
+        let v_scopes = &mut state.v_slot_scopes[slot];
+        if v_scopes.len() as u32 >= self.scopes_per_slot {
+            panic!(
+                "Slot {} already has its {} scopes",
+                slot, self.scopes_per_slot
+            );
+        }
+        let query = (slot as u32 * self.scopes_per_slot + v_scopes.len() as u32) * 2;
+        v_scopes.push(name.to_owned());
+        self.device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            self.query_pool.borrow().handle(),
+            query,
+        );
+        ScopeId { query }
+    }
+
+    /// Writes the timestamp ending `scope`, once every previous command is done.
+    ///
+    /// # Safety
+    ///
+    /// `scope` must come from `begin_scope` on the same command buffer.
+    pub unsafe fn end_scope(&self, command_buffer: vk::CommandBuffer, scope: ScopeId) {
+        self.device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.query_pool.borrow().handle(),
+            scope.query + 1,
+        );
+    }
+
+    /// Reads the timestamps of `slot`, whose command buffer must have been
+    /// submitted and waited for. Returns `false` when they are not available.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer that recorded `slot` must have completed.
+    pub unsafe fn collect(&self, slot: usize) -> VkResult<bool> {
+        let mut state = self.state.borrow_mut();
+        let scope_count = state.v_slot_scopes[slot].len() as u32;
+        if scope_count == 0 {
+            return Ok(false);
+        }
+        let mut v_timestamps = vec![0u64; (scope_count * 2) as usize];
+        match self.device.get_query_pool_results(
+            self.query_pool.borrow().handle(),
+            slot as u32 * self.scopes_per_slot * 2,
+            scope_count * 2,
+            &mut v_timestamps,
+            vk::QueryResultFlags::TYPE_64,
+        ) {
+            Ok(()) => {}
+            Err(vk::Result::NOT_READY) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        let State {
+            v_slot_scopes,
+            v_windows,
+            first_timestamp,
+            v_trace_events,
+        } = &mut *state;
+        for (name, timestamps) in v_slot_scopes[slot].iter().zip(v_timestamps.chunks(2)) {
+            let begin = timestamps[0] & self.timestamp_mask;
+            let duration = timestamps[1].wrapping_sub(timestamps[0]) & self.timestamp_mask;
+            let milliseconds = duration as f64 * self.timestamp_period / 1e6;
+            let window = match v_windows.iter_mut().find(|(n, _)| n == name) {
+                Some((_, window)) => window,
+                None => {
+                    v_windows.push((name.clone(), VecDeque::with_capacity(self.window)));
+                    &mut v_windows.last_mut().unwrap().1
+                }
+            };
+            if window.len() == self.window {
+                window.pop_front();
+            }
+            window.push_back(milliseconds);
+
+            let first_timestamp = *first_timestamp.get_or_insert(begin);
+            if v_trace_events.len() < MAX_TRACE_EVENTS {
+                v_trace_events.push(TraceEvent {
+                    name: name.clone(),
+                    slot,
+                    begin: begin.wrapping_sub(first_timestamp) & self.timestamp_mask,
+                    duration,
+                });
+            }
+        }
+        Ok(true)
+    }
+
+    /// Statistics of every scope name over the window.
+    pub fn stats(&self) -> Vec<(String, ScopeStats)> {
+        self.state
+            .borrow()
+            .v_windows
+            .iter()
+            .filter(|(_, window)| !window.is_empty())
+            .map(|(name, window)| {
+                let stats = ScopeStats {
+                    count: window.len(),
+                    min: window.iter().copied().fold(f64::INFINITY, f64::min),
+                    avg: window.iter().sum::<f64>() / window.len() as f64,
+                    max: window.iter().copied().fold(0.0, f64::max),
+                };
+                (name.clone(), stats)
+            })
+            .collect()
+    }
+
+    /// Complete events ("ph": "X") in microseconds, one thread per slot.
+    pub fn chrome_trace(&self) -> Json {
+        let microseconds = |ticks: u64| ticks as f64 * self.timestamp_period / 1e3;
+        let v_events: Vec<Json> = self
+            .state
+            .borrow()
+            .v_trace_events
+            .iter()
+            .map(|event| {
+                Json::object()
+                    .with("name", event.name.as_str())
+                    .with("cat", "gpu")
+                    .with("ph", "X")
+                    .with("ts", microseconds(event.begin))
+                    .with("dur", microseconds(event.duration))
+                    .with("pid", self.queue_family_index)
+                    .with("tid", event.slot)
+            })
+            .collect();
+        Json::object()
+            .with("traceEvents", v_events)
+            .with("displayTimeUnit", "ms")
+    }
+
+    pub fn write_chrome_trace(&self, path: &str) -> anyhow::Result<()> {
+        std::fs::write(path, self.chrome_trace().to_string())
+            .with_context(|| format!("Cannot write {}", path))
+    }
+}
+
+impl fmt::Display for GpuProfiler {
+    /// One line of statistics per scope.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let v_stats = self.stats();
+        let name_width = v_stats
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, stats) in &v_stats {
+            writeln!(
+                f,
+                "{:width$}  min {:.3} ms  avg {:.3} ms  max {:.3} ms  ({} samples)",
+                name,
+                stats.min,
+                stats.avg,
+                stats.max,
+                stats.count,
+                width = name_width
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,793 @@
+//! SPIR-V reflection: descriptor set layouts, push constant ranges and vertex
+//! inputs read from the shaders, so their binding numbers and locations are
+//! not repeated by hand on the Rust side.
+//!
+//! `PipelineReflection` merges the interfaces of the stages of a pipeline.
+//! Vertex structs describe their fields with `vertex_layout!`, and
+//! `vertex_input_attributes` checks them against the inputs of the vertex
+//! shader: the fields, in declaration order, feed the inputs in location order.
+
+use crate::shader::read_shader_code;
+use anyhow::bail;
+use anyhow::Context;
+use ash::vk;
+use std::collections::HashMap;
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+
+mod op {
+    pub const NAME: u32 = 5;
+    pub const ENTRY_POINT: u32 = 15;
+    pub const TYPE_INT: u32 = 21;
+    pub const TYPE_FLOAT: u32 = 22;
+    pub const TYPE_VECTOR: u32 = 23;
+    pub const TYPE_MATRIX: u32 = 24;
+    pub const TYPE_IMAGE: u32 = 25;
+    pub const TYPE_SAMPLER: u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const TYPE_ARRAY: u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const TYPE_STRUCT: u32 = 30;
+    pub const TYPE_POINTER: u32 = 32;
+    pub const CONSTANT: u32 = 43;
+    pub const SPEC_CONSTANT: u32 = 50;
+    pub const VARIABLE: u32 = 59;
+    pub const DECORATE: u32 = 71;
+    pub const MEMBER_DECORATE: u32 = 72;
+}
+
+mod decoration {
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod storage_class {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Clone, Debug)]
+enum Type {
+    Float { width: u32 },
+    Int { width: u32, signed: bool },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage { image: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { v_members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    buffer_block: bool,
+    built_in: bool,
+    array_stride: Option<u32>,
+}
+
+#[derive(Default)]
+struct MemberDecorations {
+    offset: u32,
+    matrix_stride: Option<u32>,
+}
+
+/// What reflection needs from a module, indexed by result id.
+#[derive(Default)]
+struct Module {
+    execution_model: Option<u32>,
+    names: HashMap<u32, String>,
+    types: HashMap<u32, Type>,
+    /// 32-bit integer constants, specialization constants with their default.
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    /// Result id, pointer type and storage class of the global variables.
+    v_variables: Vec<(u32, u32, u32)>,
+}
+
+/// Reads the null-terminated string starting at `v_words`.
+fn literal_string(v_words: &[u32]) -> String {
+    let v_bytes: Vec<u8> = v_words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&v_bytes).into_owned()
+}
+
+impl Module {
+    fn parse(v_code: &[u32]) -> anyhow::Result<Self> {
+        if v_code.len() < 5 || v_code[0] != MAGIC_NUMBER {
+            bail!("Not a SPIR-V module");
+        }
+        let mut module = Module::default();
+        let mut index = 5;
+        while index < v_code.len() {
+            let word_count = (v_code[index] >> 16) as usize;
+            let opcode = v_code[index] & 0xffff;
+            if word_count == 0 || index + word_count > v_code.len() {
+                bail!("Truncated SPIR-V instruction at word {}", index);
+            }
+            let v_operands = &v_code[index + 1..index + word_count];
+            index += word_count;
+            let operand = |i: usize| {
+                v_operands.get(i).copied().with_context(|| {
+                    format!("Missing operand {} of SPIR-V instruction {}", i, opcode)
+                })
+            };
+            match opcode {
+                op::NAME => {
+                    module
+                        .names
+                        .insert(operand(0)?, literal_string(&v_operands[1..]));
+                }
+                op::ENTRY_POINT => {
+                    module.execution_model.get_or_insert(operand(0)?);
+                }
+                op::TYPE_INT => {
+                    let ty = Type::Int {
+                        width: operand(1)?,
+                        signed: operand(2)? != 0,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::TYPE_FLOAT => {
+                    let ty = Type::Float { width: operand(1)? };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::TYPE_VECTOR => {
+                    let ty = Type::Vector {
+                        component: operand(1)?,
+                        count: operand(2)?,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::TYPE_MATRIX => {
+                    let ty = Type::Matrix {
+                        column: operand(1)?,
+                        count: operand(2)?,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::TYPE_IMAGE => {
+                    let ty = Type::Image {
+                        dim: operand(2)?,
+                        sampled: operand(6)?,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::TYPE_SAMPLER => {
+                    module.types.insert(operand(0)?, Type::Sampler);
+                }
+                op::TYPE_SAMPLED_IMAGE => {
+                    let ty = Type::SampledImage { image: operand(1)? };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::TYPE_ARRAY => {
+                    let length_id = operand(2)?;
+                    let length = *module.constants.get(&length_id).with_context(|| {
+                        format!("Array length %{} is not a known constant", length_id)
+                    })?;
+                    let ty = Type::Array {
+                        element: operand(1)?,
+                        length,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::TYPE_RUNTIME_ARRAY => {
+                    module.types.insert(operand(0)?, Type::RuntimeArray);
+                }
+                op::TYPE_STRUCT => {
+                    let ty = Type::Struct {
+                        v_members: v_operands[1..].to_vec(),
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::TYPE_POINTER => {
+                    let ty = Type::Pointer {
+                        pointee: operand(2)?,
+                    };
+                    module.types.insert(operand(0)?, ty);
+                }
+                op::CONSTANT | op::SPEC_CONSTANT => {
+                    // Only 32-bit integers are needed, as array lengths. A
+                    // specialized length is reflected at its default value.
+                    module.constants.insert(operand(1)?, operand(2)?);
+                }
+                op::VARIABLE => {
+                    module
+                        .v_variables
+                        .push((operand(1)?, operand(0)?, operand(2)?));
+                }
+                op::DECORATE => {
+                    let decorations = module.decorations.entry(operand(0)?).or_default();
+                    match operand(1)? {
+                        decoration::DESCRIPTOR_SET => decorations.set = Some(operand(2)?),
+                        decoration::BINDING => decorations.binding = Some(operand(2)?),
+                        decoration::LOCATION => decorations.location = Some(operand(2)?),
+                        decoration::BUFFER_BLOCK => decorations.buffer_block = true,
+                        decoration::BUILT_IN => decorations.built_in = true,
+                        decoration::ARRAY_STRIDE => decorations.array_stride = Some(operand(2)?),
+                        _ => {}
+                    }
+                }
+                op::MEMBER_DECORATE => {
+                    let decorations = module
+                        .member_decorations
+                        .entry((operand(0)?, operand(1)?))
+                        .or_default();
+                    match operand(2)? {
+                        decoration::OFFSET => decorations.offset = operand(3)?,
+                        decoration::MATRIX_STRIDE => decorations.matrix_stride = Some(operand(3)?),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(module)
+    }
+
+    fn ty(&self, id: u32) -> anyhow::Result<&Type> {
+        self.types
+            .get(&id)
+            .with_context(|| format!("Unknown SPIR-V type %{}", id))
+    }
+
+    fn name(&self, id: u32) -> String {
+        match self.names.get(&id) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => format!("%{}", id),
+        }
+    }
+
+    fn pointee(&self, pointer_type: u32) -> anyhow::Result<u32> {
+        match self.ty(pointer_type)? {
+            Type::Pointer { pointee } => Ok(*pointee),
+            _ => bail!("Variable type %{} is not a pointer", pointer_type),
+        }
+    }
+
+    /// Vulkan format of a vertex input of type `id`.
+    fn format(&self, id: u32) -> anyhow::Result<vk::Format> {
+        let (component, count) = match self.ty(id)? {
+            Type::Vector { component, count } => (*component, *count),
+            _ => (id, 1),
+        };
+        let v_formats = match self.ty(component)? {
+            Type::Float { width: 32 } => [
+                vk::Format::R32_SFLOAT,
+                vk::Format::R32G32_SFLOAT,
+                vk::Format::R32G32B32_SFLOAT,
+                vk::Format::R32G32B32A32_SFLOAT,
+            ],
+            Type::Float { width: 64 } => [
+                vk::Format::R64_SFLOAT,
+                vk::Format::R64G64_SFLOAT,
+                vk::Format::R64G64B64_SFLOAT,
+                vk::Format::R64G64B64A64_SFLOAT,
+            ],
+            Type::Int {
+                width: 32,
+                signed: true,
+            } => [
+                vk::Format::R32_SINT,
+                vk::Format::R32G32_SINT,
+                vk::Format::R32G32B32_SINT,
+                vk::Format::R32G32B32A32_SINT,
+            ],
+            Type::Int {
+                width: 32,
+                signed: false,
+            } => [
+                vk::Format::R32_UINT,
+                vk::Format::R32G32_UINT,
+                vk::Format::R32G32B32_UINT,
+                vk::Format::R32G32B32A32_UINT,
+            ],
+            ty => bail!("Unsupported vertex input type {:?}", ty),
+        };
+        match (count as usize)
+            .checked_sub(1)
+            .and_then(|index| v_formats.get(index))
+        {
+            Some(format) => Ok(*format),
+            None => bail!("Unsupported vertex input vector of {} components", count),
+        }
+    }
+
+    /// Size in bytes of type `id` in a block. `matrix_stride` comes from the
+    /// member holding it.
+    fn size(&self, id: u32, matrix_stride: Option<u32>) -> anyhow::Result<u32> {
+        Ok(match self.ty(id)? {
+            Type::Float { width } | Type::Int { width, .. } => width / 8,
+            Type::Vector { component, count } => count * self.size(*component, None)?,
+            Type::Matrix { column, count } => {
+                count * matrix_stride.map_or_else(|| self.size(*column, None), Ok)?
+            }
+            Type::Array { element, length } => {
+                let stride = self.decorations.get(&id).and_then(|d| d.array_stride);
+                length * stride.map_or_else(|| self.size(*element, matrix_stride), Ok)?
+            }
+            Type::RuntimeArray => 0,
+            Type::Struct { v_members } => {
+                let mut size = 0;
+                for (index, member) in v_members.iter().enumerate() {
+                    let decorations = self.member_decorations.get(&(id, index as u32));
+                    let offset = decorations.map_or(0, |d| d.offset);
+                    let stride = decorations.and_then(|d| d.matrix_stride);
+                    size = size.max(offset + self.size(*member, stride)?);
+                }
+                size
+            }
+            ty => bail!("Type {:?} has no size in a block", ty),
+        })
+    }
+
+    /// Descriptor type and count of a resource variable pointing to `id`.
+    fn descriptor(&self, id: u32, storage_class: u32) -> anyhow::Result<(vk::DescriptorType, u32)> {
+        let (id, count) = match self.ty(id)? {
+            Type::Array { element, length } => (*element, *length),
+            Type::RuntimeArray => bail!("Runtime arrays of descriptors are not supported"),
+            _ => (id, 1),
+        };
+        let descriptor_type = match (storage_class, self.ty(id)?) {
+            (storage_class::STORAGE_BUFFER, _) => vk::DescriptorType::STORAGE_BUFFER,
+            (storage_class::UNIFORM, _) => {
+                let buffer_block = self.decorations.get(&id).is_some_and(|d| d.buffer_block);
+                if buffer_block {
+                    vk::DescriptorType::STORAGE_BUFFER
+                } else {
+                    vk::DescriptorType::UNIFORM_BUFFER
+                }
+            }
+            (_, Type::Sampler) => vk::DescriptorType::SAMPLER,
+            (_, Type::SampledImage { image }) => match self.ty(*image)? {
+                Type::Image {
+                    dim: DIM_BUFFER, ..
+                } => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                _ => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            },
+            (_, Type::Image { dim, sampled }) => match (*dim, *sampled) {
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            (_, ty) => bail!("Type {:?} is not a descriptor", ty),
+        };
+        Ok((descriptor_type, count))
+    }
+}
+
+/// A descriptor used by a shader.
+#[derive(Clone, Debug)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub stage_flags: vk::ShaderStageFlags,
+    /// Name of the variable in the shader, `%<id>` when stripped.
+    pub name: String,
+}
+
+/// An input of a vertex shader.
+#[derive(Clone, Debug)]
+pub struct VertexInput {
+    pub location: u32,
+    pub format: vk::Format,
+    pub name: String,
+}
+
+/// Interface of one shader stage.
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub v_descriptor_bindings: Vec<DescriptorBinding>,
+    /// Size of the push constant block, 0 without one.
+    pub push_constant_size: u32,
+    /// Inputs of a vertex shader sorted by location, empty for other stages.
+    pub v_vertex_inputs: Vec<VertexInput>,
+}
+
+impl ShaderReflection {
+    pub fn new(v_code: &[u32]) -> anyhow::Result<Self> {
+        let module = Module::parse(v_code)?;
+        let stage = match module.execution_model {
+            Some(0) => vk::ShaderStageFlags::VERTEX,
+            Some(1) => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+            Some(2) => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            Some(3) => vk::ShaderStageFlags::GEOMETRY,
+            Some(4) => vk::ShaderStageFlags::FRAGMENT,
+            Some(5) => vk::ShaderStageFlags::COMPUTE,
+            Some(model) => bail!("Unsupported execution model {}", model),
+            None => bail!("The module has no entry point"),
+        };
+        let mut reflection = ShaderReflection {
+            stage,
+            v_descriptor_bindings: Vec::new(),
+            push_constant_size: 0,
+            v_vertex_inputs: Vec::new(),
+        };
+        let no_decorations = Decorations::default();
+        for &(id, pointer_type, storage_class) in &module.v_variables {
+            let decorations = module.decorations.get(&id).unwrap_or(&no_decorations);
+            let pointee = module.pointee(pointer_type)?;
+            match storage_class {
+                storage_class::UNIFORM_CONSTANT
+                | storage_class::UNIFORM
+                | storage_class::STORAGE_BUFFER => {
+                    let (descriptor_type, count) = module
+                        .descriptor(pointee, storage_class)
+                        .with_context(|| format!("Cannot reflect {}", module.name(id)))?;
+                    reflection.v_descriptor_bindings.push(DescriptorBinding {
+                        set: decorations.set.unwrap_or(0),
+                        binding: decorations.binding.unwrap_or(0),
+                        descriptor_type,
+                        count,
+                        stage_flags: stage,
+                        name: module.name(id),
+                    });
+                }
+                storage_class::PUSH_CONSTANT => {
+                    reflection.push_constant_size = module
+                        .size(pointee, None)
+                        .with_context(|| format!("Cannot reflect {}", module.name(id)))?;
+                }
+                storage_class::INPUT
+                    if stage == vk::ShaderStageFlags::VERTEX && !decorations.built_in =>
+                {
+                    let location = decorations.location.with_context(|| {
+                        format!("Vertex input {} has no location", module.name(id))
+                    })?;
+                    reflection.v_vertex_inputs.push(VertexInput {
+                        location,
+                        format: module
+                            .format(pointee)
+                            .with_context(|| format!("Cannot reflect {}", module.name(id)))?,
+                        name: module.name(id),
+                    });
+                }
+                _ => {}
+            }
+        }
+        reflection
+            .v_descriptor_bindings
+            .sort_by_key(|binding| (binding.set, binding.binding));
+        reflection
+            .v_vertex_inputs
+            .sort_by_key(|input| input.location);
+        Ok(reflection)
+    }
+
+    /// Reflects a shader loaded like `create_shader_module` does.
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        ShaderReflection::new(&read_shader_code(path)?)
+            .with_context(|| format!("Cannot reflect shader {}", path))
+    }
+}
+
+/// Interface of a pipeline: the descriptors, push constants and vertex inputs
+/// of all its stages.
+#[derive(Clone, Debug)]
+pub struct PipelineReflection {
+    v_descriptor_bindings: Vec<DescriptorBinding>,
+    push_constant_range: Option<vk::PushConstantRange>,
+    v_vertex_inputs: Vec<VertexInput>,
+}
+
+impl PipelineReflection {
+    /// Merges the stages. Fails when two stages use the same binding for
+    /// different descriptors.
+    pub fn new(v_shaders: &[ShaderReflection]) -> anyhow::Result<Self> {
+        let mut reflection = PipelineReflection {
+            v_descriptor_bindings: Vec::new(),
+            push_constant_range: None,
+            v_vertex_inputs: Vec::new(),
+        };
+        for shader in v_shaders {
+            for binding in &shader.v_descriptor_bindings {
+                match reflection
+                    .v_descriptor_bindings
+                    .iter_mut()
+                    .find(|b| (b.set, b.binding) == (binding.set, binding.binding))
+                {
+                    Some(merged)
+                        if (merged.descriptor_type, merged.count)
+                            == (binding.descriptor_type, binding.count) =>
+                    {
+                        merged.stage_flags |= binding.stage_flags;
+                    }
+                    Some(merged) => bail!(
+                        "Binding {}.{} is {} {:?} in {:?} but {} {:?} in {:?}",
+                        binding.set,
+                        binding.binding,
+                        merged.count,
+                        merged.descriptor_type,
+                        merged.stage_flags,
+                        binding.count,
+                        binding.descriptor_type,
+                        binding.stage_flags
+                    ),
+                    None => reflection.v_descriptor_bindings.push(binding.clone()),
+                }
+            }
+            if shader.push_constant_size > 0 {
+                let range = reflection
+                    .push_constant_range
+                    .get_or_insert(vk::PushConstantRange::default());
+                range.stage_flags |= shader.stage;
+                range.size = range.size.max(shader.push_constant_size);
+            }
+            if shader.stage == vk::ShaderStageFlags::VERTEX {
+                reflection.v_vertex_inputs = shader.v_vertex_inputs.clone();
+            }
+        }
+        reflection
+            .v_descriptor_bindings
+            .sort_by_key(|binding| (binding.set, binding.binding));
+        Ok(reflection)
+    }
+
+    pub fn from_files(v_paths: &[&str]) -> anyhow::Result<Self> {
+        let v_shaders = v_paths
+            .iter()
+            .map(|path| ShaderReflection::from_file(path))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        PipelineReflection::new(&v_shaders)
+    }
+
+    /// Makes the uniform or storage buffer `name` dynamic: shaders do not
+    /// tell, it depends on how the descriptor is written.
+    pub fn dynamic_buffer(mut self, name: &str) -> Self {
+        let binding = self
+            .v_descriptor_bindings
+            .iter_mut()
+            .find(|binding| binding.name == name)
+            .unwrap_or_else(|| panic!("No descriptor named {}", name));
+        binding.descriptor_type = match binding.descriptor_type {
+            vk::DescriptorType::UNIFORM_BUFFER => vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            vk::DescriptorType::STORAGE_BUFFER => vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+            descriptor_type => panic!("{} is a {:?}, not a buffer", name, descriptor_type),
+        };
+        self
+    }
+
+    pub fn descriptor_bindings(&self) -> &[DescriptorBinding] {
+        &self.v_descriptor_bindings
+    }
+
+    /// The descriptor whose variable is named `name` in the shaders.
+    pub fn binding(&self, name: &str) -> Option<&DescriptorBinding> {
+        self.v_descriptor_bindings
+            .iter()
+            .find(|binding| binding.name == name)
+    }
+
+    /// Bindings of the layout of descriptor set `set`.
+    pub fn descriptor_set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding> {
+        self.v_descriptor_bindings
+            .iter()
+            .filter(|binding| binding.set == set)
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.count,
+                stage_flags: binding.stage_flags,
+                p_immutable_samplers: std::ptr::null(),
+            })
+            .collect()
+    }
+
+    /// Pool sizes for `set_count` descriptor sets of layout `set`.
+    pub fn descriptor_pool_sizes(&self, set: u32, set_count: u32) -> Vec<vk::DescriptorPoolSize> {
+        let mut v_pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
+        for binding in self.v_descriptor_bindings.iter().filter(|b| b.set == set) {
+            let descriptor_count = binding.count * set_count;
+            match v_pool_sizes
+                .iter_mut()
+                .find(|size| size.ty == binding.descriptor_type)
+            {
+                Some(size) => size.descriptor_count += descriptor_count,
+                None => v_pool_sizes.push(vk::DescriptorPoolSize {
+                    ty: binding.descriptor_type,
+                    descriptor_count,
+                }),
+            }
+        }
+        v_pool_sizes
+    }
+
+    /// One range covering the push constants of every stage, or none.
+    pub fn push_constant_ranges(&self) -> Vec<vk::PushConstantRange> {
+        self.push_constant_range.into_iter().collect()
+    }
+
+    pub fn vertex_inputs(&self) -> &[VertexInput] {
+        &self.v_vertex_inputs
+    }
+
+    /// Attributes feeding the vertex inputs from a buffer of `V` bound at
+    /// `binding`. Fails when `V` does not have one field per input, in
+    /// location order, with the input's format.
+    pub fn vertex_input_attributes<V: VertexLayout>(
+        &self,
+        binding: u32,
+    ) -> anyhow::Result<Vec<vk::VertexInputAttributeDescription>> {
+        let type_name = std::any::type_name::<V>().rsplit("::").next().unwrap();
+        let v_fields = V::fields();
+        if v_fields.len() != self.v_vertex_inputs.len() {
+            let v_field_names: Vec<&str> = v_fields.iter().map(|field| field.name).collect();
+            let v_input_names: Vec<String> = self
+                .v_vertex_inputs
+                .iter()
+                .map(|input| format!("{} (location {})", input.name, input.location))
+                .collect();
+            bail!(
+                "{} has {} fields ({}) but the vertex shader has {} inputs ({})",
+                type_name,
+                v_fields.len(),
+                v_field_names.join(", "),
+                self.v_vertex_inputs.len(),
+                v_input_names.join(", ")
+            );
+        }
+        v_fields
+            .iter()
+            .zip(&self.v_vertex_inputs)
+            .map(|(field, input)| {
+                if field.format != input.format {
+                    bail!(
+                        "Field {} of {} is {:?} but input {} (location {}) is {:?}",
+                        field.name,
+                        type_name,
+                        field.format,
+                        input.name,
+                        input.location,
+                        input.format
+                    );
+                }
+                Ok(vk::VertexInputAttributeDescription {
+                    location: input.location,
+                    binding,
+                    format: input.format,
+                    offset: field.offset,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A field of a vertex struct.
+#[derive(Clone, Copy, Debug)]
+pub struct VertexField {
+    pub name: &'static str,
+    pub offset: u32,
+    pub format: vk::Format,
+}
+
+/// Vertex structs, implemented by `vertex_layout!`.
+pub trait VertexLayout: Sized {
+    /// Fields in declaration order.
+    fn fields() -> Vec<VertexField>;
+
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+}
+
+/// Types of vertex fields, with the format of the matching shader input.
+pub trait VertexFormat {
+    const FORMAT: vk::Format;
+}
+
+macro_rules! vertex_format {
+    ($($t:ty => $format:ident),* $(,)?) => {
+        $(impl VertexFormat for $t {
+            const FORMAT: vk::Format = vk::Format::$format;
+        })*
+    };
+}
+
+vertex_format!(
+    f32 => R32_SFLOAT,
+    [f32; 2] => R32G32_SFLOAT,
+    [f32; 3] => R32G32B32_SFLOAT,
+    [f32; 4] => R32G32B32A32_SFLOAT,
+    glm::Vec2 => R32G32_SFLOAT,
+    glm::Vec3 => R32G32B32_SFLOAT,
+    glm::Vec4 => R32G32B32A32_SFLOAT,
+    i32 => R32_SINT,
+    [i32; 2] => R32G32_SINT,
+    [i32; 3] => R32G32B32_SINT,
+    [i32; 4] => R32G32B32A32_SINT,
+    u32 => R32_UINT,
+    [u32; 2] => R32G32_UINT,
+    [u32; 3] => R32G32B32_UINT,
+    [u32; 4] => R32G32B32A32_UINT,
+);
+
+/// Format of the field `field` returns, used by `vertex_layout!`.
+pub fn field_format<V, T: VertexFormat>(_field: fn(&V) -> &T) -> vk::Format {
+    T::FORMAT
+}
+
+/// Implements `VertexLayout` for a `#[repr(C)]` struct from its fields, in
+/// declaration order: `vertex_layout!(MyPointData { position, color });`
+#[macro_export]
+macro_rules! vertex_layout {
+    ($vertex:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::reflection::VertexLayout for $vertex {
+            fn fields() -> Vec<$crate::reflection::VertexField> {
+                vec![$($crate::reflection::VertexField {
+                    name: stringify!($field),
+                    offset: std::mem::offset_of!($vertex, $field) as u32,
+                    format: $crate::reflection::field_format(|vertex: &$vertex| &vertex.$field),
+                }),*]
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Module made of `v_instructions`, each one an opcode and its operands.
+    fn module(v_instructions: &[(u32, &[u32])]) -> Vec<u32> {
+        let mut v_code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        for (opcode, v_operands) in v_instructions {
+            v_code.push(((v_operands.len() as u32 + 1) << 16) | opcode);
+            v_code.extend_from_slice(v_operands);
+        }
+        v_code
+    }
+
+    #[test]
+    fn array_length_from_a_specialization_constant() {
+        let v_code = module(&[
+            (op::TYPE_INT, &[1, 32, 0]),
+            (op::SPEC_CONSTANT, &[1, 2, 64]),
+            (op::TYPE_ARRAY, &[3, 1, 2]),
+        ]);
+        let module = Module::parse(&v_code).unwrap();
+        assert_eq!(module.constants[&2], 64);
+        assert_eq!(module.size(3, None).unwrap(), 256);
+    }
+
+    #[test]
+    fn vector_of_no_component_has_no_format() {
+        let v_code = module(&[
+            (op::TYPE_FLOAT, &[1, 32]),
+            (op::TYPE_VECTOR, &[2, 1, 0]),
+            (op::TYPE_VECTOR, &[3, 1, 4]),
+        ]);
+        let module = Module::parse(&v_code).unwrap();
+        assert!(module.format(2).is_err());
+        assert_eq!(module.format(3).unwrap(), vk::Format::R32G32B32A32_SFLOAT);
+    }
+}
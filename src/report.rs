@@ -0,0 +1,504 @@
+//! Capabilities of the physical devices, as a `Json` tree that can be printed
+//! as JSON or as indented text (see `000_validation_layers report`).
+
+use crate::features;
+use crate::instance;
+use crate::instance::Instance;
+use crate::json::Json;
+use crate::selector;
+use crate::swapchain::Surface;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::ffi::CStr;
+use std::fmt::Debug;
+
+macro_rules! limit_list {
+    ($($field:ident),* $(,)?) => {
+        fn limits_json(limits: &vk::PhysicalDeviceLimits) -> Json {
+            Json::object()$(.with(stringify!($field), LimitValue::to_json(&limits.$field)))*
+        }
+    };
+}
+
+limit_list!(
+    max_image_dimension1_d,
+    max_image_dimension2_d,
+    max_image_dimension3_d,
+    max_image_dimension_cube,
+    max_image_array_layers,
+    max_texel_buffer_elements,
+    max_uniform_buffer_range,
+    max_storage_buffer_range,
+    max_push_constants_size,
+    max_memory_allocation_count,
+    max_sampler_allocation_count,
+    buffer_image_granularity,
+    sparse_address_space_size,
+    max_bound_descriptor_sets,
+    max_per_stage_descriptor_samplers,
+    max_per_stage_descriptor_uniform_buffers,
+    max_per_stage_descriptor_storage_buffers,
+    max_per_stage_descriptor_sampled_images,
+    max_per_stage_descriptor_storage_images,
+    max_per_stage_descriptor_input_attachments,
+    max_per_stage_resources,
+    max_descriptor_set_samplers,
+    max_descriptor_set_uniform_buffers,
+    max_descriptor_set_uniform_buffers_dynamic,
+    max_descriptor_set_storage_buffers,
+    max_descriptor_set_storage_buffers_dynamic,
+    max_descriptor_set_sampled_images,
+    max_descriptor_set_storage_images,
+    max_descriptor_set_input_attachments,
+    max_vertex_input_attributes,
+    max_vertex_input_bindings,
+    max_vertex_input_attribute_offset,
+    max_vertex_input_binding_stride,
+    max_vertex_output_components,
+    max_tessellation_generation_level,
+    max_tessellation_patch_size,
+    max_tessellation_control_per_vertex_input_components,
+    max_tessellation_control_per_vertex_output_components,
+    max_tessellation_control_per_patch_output_components,
+    max_tessellation_control_total_output_components,
+    max_tessellation_evaluation_input_components,
+    max_tessellation_evaluation_output_components,
+    max_geometry_shader_invocations,
+    max_geometry_input_components,
+    max_geometry_output_components,
+    max_geometry_output_vertices,
+    max_geometry_total_output_components,
+    max_fragment_input_components,
+    max_fragment_output_attachments,
+    max_fragment_dual_src_attachments,
+    max_fragment_combined_output_resources,
+    max_compute_shared_memory_size,
+    max_compute_work_group_count,
+    max_compute_work_group_invocations,
+    max_compute_work_group_size,
+    sub_pixel_precision_bits,
+    sub_texel_precision_bits,
+    mipmap_precision_bits,
+    max_draw_indexed_index_value,
+    max_draw_indirect_count,
+    max_sampler_lod_bias,
+    max_sampler_anisotropy,
+    max_viewports,
+    max_viewport_dimensions,
+    viewport_bounds_range,
+    viewport_sub_pixel_bits,
+    min_memory_map_alignment,
+    min_texel_buffer_offset_alignment,
+    min_uniform_buffer_offset_alignment,
+    min_storage_buffer_offset_alignment,
+    min_texel_offset,
+    max_texel_offset,
+    min_texel_gather_offset,
+    max_texel_gather_offset,
+    min_interpolation_offset,
+    max_interpolation_offset,
+    sub_pixel_interpolation_offset_bits,
+    max_framebuffer_width,
+    max_framebuffer_height,
+    max_framebuffer_layers,
+    framebuffer_color_sample_counts,
+    framebuffer_depth_sample_counts,
+    framebuffer_stencil_sample_counts,
+    framebuffer_no_attachments_sample_counts,
+    max_color_attachments,
+    sampled_image_color_sample_counts,
+    sampled_image_integer_sample_counts,
+    sampled_image_depth_sample_counts,
+    sampled_image_stencil_sample_counts,
+    storage_image_sample_counts,
+    max_sample_mask_words,
+    timestamp_compute_and_graphics,
+    timestamp_period,
+    max_clip_distances,
+    max_cull_distances,
+    max_combined_clip_and_cull_distances,
+    discrete_queue_priorities,
+    point_size_range,
+    line_width_range,
+    point_size_granularity,
+    line_width_granularity,
+    strict_lines,
+    standard_sample_locations,
+    optimal_buffer_copy_offset_alignment,
+    optimal_buffer_copy_row_pitch_alignment,
+    non_coherent_atom_size,
+);
+
+trait LimitValue {
+    fn to_json(&self) -> Json;
+}
+
+impl<T: Into<Json> + Copy> LimitValue for T {
+    fn to_json(&self) -> Json {
+        (*self).into()
+    }
+}
+
+impl LimitValue for vk::SampleCountFlags {
+    fn to_json(&self) -> Json {
+        flags_json(self)
+    }
+}
+
+/// Instance layers and extensions, then one entry per physical device.
+///
+/// # Safety
+///
+/// `surface` must have been created for `instance`.
+pub unsafe fn instance_report(instance: &Instance, surface: Option<&Surface>) -> Json {
+    let entry = instance.entry();
+    let layers = instance::available_instance_layers(entry).unwrap_or_default();
+    let extensions = instance::available_instance_extensions(entry, &[]).unwrap_or_default();
+    let gpus = instance.enumerate_physical_devices().unwrap_or_default();
+    Json::object()
+        .with("instance_layers", c_strings_json(&layers))
+        .with("instance_extensions", c_strings_json(&extensions))
+        .with(
+            "devices",
+            Json::Array(
+                gpus.iter()
+                    .enumerate()
+                    .map(|(index, gpu)| device_report(instance, *gpu, index, surface))
+                    .collect(),
+            ),
+        )
+}
+
+/// # Safety
+///
+/// `gpu` must have been enumerated from `instance`; same for `surface`.
+pub unsafe fn device_report(
+    instance: &Instance,
+    gpu: vk::PhysicalDevice,
+    index: usize,
+    surface: Option<&Surface>,
+) -> Json {
+    let properties = instance.get_physical_device_properties(gpu);
+    let mut report = Json::object()
+        .with("index", index)
+        .with("name", selector::device_name(&properties))
+        .with("device_type", format!("{:?}", properties.device_type))
+        .with("api_version", version_string(properties.api_version))
+        .with("driver_version", properties.driver_version)
+        .with("vendor_id", format!("{:#06x}", properties.vendor_id))
+        .with("device_id", format!("{:#06x}", properties.device_id))
+        .with(
+            "pipeline_cache_uuid",
+            uuid_string(&properties.pipeline_cache_uuid),
+        )
+        .with("limits", limits_json(&properties.limits))
+        .with(
+            "sparse_properties",
+            sparse_properties_json(&properties.sparse_properties),
+        )
+        .with(
+            "features",
+            Json::Object(
+                features::feature_flags(&instance.get_physical_device_features(gpu))
+                    .into_iter()
+                    .map(|(name, enabled)| (name.to_owned(), Json::Bool(enabled)))
+                    .collect(),
+            ),
+        )
+        .with(
+            "memory",
+            memory_json(&instance.get_physical_device_memory_properties(gpu)),
+        )
+        .with(
+            "queue_families",
+            queue_families_json(instance, gpu, surface),
+        )
+        .with(
+            "extensions",
+            Json::Array(
+                instance
+                    .enumerate_device_extension_properties(gpu)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|p| {
+                        Json::object()
+                            .with("name", c_str(&p.extension_name))
+                            .with("spec_version", p.spec_version)
+                    })
+                    .collect(),
+            ),
+        )
+        .with("formats", formats_json(instance, gpu));
+    if let Some(surface) = surface {
+        report = report.with("surface", surface_json(surface, gpu));
+    }
+    report
+}
+
+fn sparse_properties_json(sparse_properties: &vk::PhysicalDeviceSparseProperties) -> Json {
+    Json::object()
+        .with(
+            "residency_standard2_d_block_shape",
+            sparse_properties.residency_standard2_d_block_shape == vk::TRUE,
+        )
+        .with(
+            "residency_standard2_d_multisample_block_shape",
+            sparse_properties.residency_standard2_d_multisample_block_shape == vk::TRUE,
+        )
+        .with(
+            "residency_standard3_d_block_shape",
+            sparse_properties.residency_standard3_d_block_shape == vk::TRUE,
+        )
+        .with(
+            "residency_aligned_mip_size",
+            sparse_properties.residency_aligned_mip_size == vk::TRUE,
+        )
+        .with(
+            "residency_non_resident_strict",
+            sparse_properties.residency_non_resident_strict == vk::TRUE,
+        )
+}
+
+fn memory_json(memory_properties: &vk::PhysicalDeviceMemoryProperties) -> Json {
+    let heaps = memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+        .iter()
+        .enumerate()
+        .map(|(index, heap)| {
+            Json::object()
+                .with("index", index)
+                .with("size", heap.size)
+                .with("flags", flags_json(&heap.flags))
+        })
+        .collect();
+    let types = memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+        .iter()
+        .enumerate()
+        .map(|(index, memory_type)| {
+            Json::object()
+                .with("index", index)
+                .with("heap_index", memory_type.heap_index)
+                .with("property_flags", flags_json(&memory_type.property_flags))
+        })
+        .collect();
+    Json::object()
+        .with("heaps", Json::Array(heaps))
+        .with("types", Json::Array(types))
+}
+
+unsafe fn queue_families_json(
+    instance: &Instance,
+    gpu: vk::PhysicalDevice,
+    surface: Option<&Surface>,
+) -> Json {
+    Json::Array(
+        instance
+            .get_physical_device_queue_family_properties(gpu)
+            .iter()
+            .enumerate()
+            .map(|(index, properties)| {
+                let granularity = properties.min_image_transfer_granularity;
+                let mut family = Json::object()
+                    .with("index", index)
+                    .with("queue_flags", flags_json(&properties.queue_flags))
+                    .with("queue_count", properties.queue_count)
+                    .with("timestamp_valid_bits", properties.timestamp_valid_bits)
+                    .with(
+                        "min_image_transfer_granularity",
+                        [granularity.width, granularity.height, granularity.depth],
+                    );
+                if let Some(surface) = surface {
+                    family = family.with(
+                        "present_support",
+                        surface.loader().get_physical_device_surface_support(
+                            gpu,
+                            index as u32,
+                            surface.handle(),
+                        ),
+                    );
+                }
+                family
+            })
+            .collect(),
+    )
+}
+
+/// Core formats with at least one supported feature.
+unsafe fn formats_json(instance: &Instance, gpu: vk::PhysicalDevice) -> Json {
+    let last_core_format = vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw();
+    Json::Array(
+        (1..=last_core_format)
+            .map(vk::Format::from_raw)
+            .filter_map(|format| {
+                let properties = instance.get_physical_device_format_properties(gpu, format);
+                if properties.linear_tiling_features.is_empty()
+                    && properties.optimal_tiling_features.is_empty()
+                    && properties.buffer_features.is_empty()
+                {
+                    return None;
+                }
+                Some(
+                    Json::object()
+                        .with("format", format!("{:?}", format))
+                        .with(
+                            "linear_tiling_features",
+                            flags_json(&properties.linear_tiling_features),
+                        )
+                        .with(
+                            "optimal_tiling_features",
+                            flags_json(&properties.optimal_tiling_features),
+                        )
+                        .with("buffer_features", flags_json(&properties.buffer_features)),
+                )
+            })
+            .collect(),
+    )
+}
+
+unsafe fn surface_json(surface: &Surface, gpu: vk::PhysicalDevice) -> Json {
+    let loader = surface.loader();
+    let capabilities = match loader.get_physical_device_surface_capabilities(gpu, surface.handle())
+    {
+        Ok(capabilities) => capabilities,
+        Err(e) => return Json::object().with("error", e.to_string()),
+    };
+    let extent_json = |extent: vk::Extent2D| [extent.width, extent.height];
+    let formats = loader
+        .get_physical_device_surface_formats(gpu, surface.handle())
+        .unwrap_or_default()
+        .iter()
+        .map(|f| {
+            Json::object()
+                .with("format", format!("{:?}", f.format))
+                .with("color_space", format!("{:?}", f.color_space))
+        })
+        .collect();
+    let present_modes = loader
+        .get_physical_device_surface_present_modes(gpu, surface.handle())
+        .unwrap_or_default()
+        .iter()
+        .map(|m| Json::String(format!("{:?}", m)))
+        .collect();
+    Json::object()
+        .with("min_image_count", capabilities.min_image_count)
+        .with("max_image_count", capabilities.max_image_count)
+        .with("current_extent", extent_json(capabilities.current_extent))
+        .with(
+            "min_image_extent",
+            extent_json(capabilities.min_image_extent),
+        )
+        .with(
+            "max_image_extent",
+            extent_json(capabilities.max_image_extent),
+        )
+        .with(
+            "max_image_array_layers",
+            capabilities.max_image_array_layers,
+        )
+        .with(
+            "supported_transforms",
+            flags_json(&capabilities.supported_transforms),
+        )
+        .with(
+            "current_transform",
+            flags_json(&capabilities.current_transform),
+        )
+        .with(
+            "supported_composite_alpha",
+            flags_json(&capabilities.supported_composite_alpha),
+        )
+        .with(
+            "supported_usage_flags",
+            flags_json(&capabilities.supported_usage_flags),
+        )
+        .with("formats", Json::Array(formats))
+        .with("present_modes", Json::Array(present_modes))
+}
+
+/// Renders a report as indented `key: value` lines.
+pub fn to_text(report: &Json) -> String {
+    let mut output = String::new();
+    write_text(&mut output, None, report, 0);
+    output
+}
+
+fn write_text(output: &mut String, key: Option<&str>, value: &Json, indent: usize) {
+    let prefix = format!("{:1$}", "", indent);
+    let label = match key {
+        Some(key) => format!("{}{}:", prefix, key),
+        None => format!("{}-", prefix),
+    };
+    match value {
+        Json::Object(members) => {
+            if key.is_some() || indent > 0 {
+                output.push_str(&label);
+                output.push('\n');
+            }
+            let child_indent = if key.is_some() || indent > 0 {
+                indent + 2
+            } else {
+                0
+            };
+            for (k, v) in members {
+                write_text(output, Some(k), v, child_indent);
+            }
+        }
+        Json::Array(values) if values.iter().any(|v| matches!(v, Json::Object(_))) => {
+            output.push_str(&label);
+            output.push('\n');
+            for v in values {
+                write_text(output, None, v, indent + 2);
+            }
+        }
+        Json::Array(values) => {
+            let items: Vec<String> = values.iter().map(scalar_text).collect();
+            output.push_str(&format!("{} {}\n", label, items.join(", ")));
+        }
+        scalar => output.push_str(&format!("{} {}\n", label, scalar_text(scalar))),
+    }
+}
+
+fn scalar_text(value: &Json) -> String {
+    match value {
+        Json::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn flags_json<T: Debug>(flags: &T) -> Json {
+    let names = format!("{:?}", flags);
+    Json::Array(
+        names
+            .split(" | ")
+            .filter(|name| !name.is_empty())
+            .map(|name| Json::String(name.to_owned()))
+            .collect(),
+    )
+}
+
+fn c_strings_json(names: &[std::ffi::CString]) -> Json {
+    Json::Array(
+        names
+            .iter()
+            .map(|name| Json::String(name.to_string_lossy().into_owned()))
+            .collect(),
+    )
+}
+
+fn c_str(name: &[std::os::raw::c_char]) -> String {
+    unsafe { CStr::from_ptr(name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn version_string(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        ash::vk_version_major!(version),
+        ash::vk_version_minor!(version),
+        ash::vk_version_patch!(version)
+    )
+}
+
+fn uuid_string(uuid: &[u8]) -> String {
+    uuid.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,372 @@
+//! Physical device selection.
+//!
+//! Every device is checked against the sample's requirements and scored; the
+//! best accepted one wins. The choice can be forced with `--gpu <value>` on the
+//! command line or `VKCORE_GPU=<value>`, where the value is a device index
+//! (`1`), a vendor ID (`vendor:0x10de`) or a substring of the device name
+//! (`radeon`, case insensitive).
+
+use crate::features::DeviceRequirements;
+use crate::instance::Instance;
+use crate::swapchain::Surface;
+use ash::version::InstanceV1_0;
+use ash::vk;
+use std::ffi::CStr;
+
+/// Forces the choice of one device, see the module documentation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceOverride {
+    Index(usize),
+    VendorId(u32),
+    Name(String),
+}
+
+impl DeviceOverride {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if let Some(vendor_id) = value.strip_prefix("vendor:") {
+            let vendor_id = vendor_id.trim();
+            let parsed = match vendor_id.strip_prefix("0x") {
+                Some(hexadecimal) => u32::from_str_radix(hexadecimal, 16),
+                None => vendor_id.parse(),
+            };
+            return parsed
+                .map(DeviceOverride::VendorId)
+                .map_err(|_| format!("Invalid vendor ID {}", vendor_id));
+        }
+        if value.is_empty() {
+            return Err("Empty device override".to_owned());
+        }
+        match value.parse() {
+            Ok(index) => Ok(DeviceOverride::Index(index)),
+            Err(_) => Ok(DeviceOverride::Name(value.to_lowercase())),
+        }
+    }
+
+    /// Reads `--gpu <value>` (or `--gpu=<value>`) from the command line, then
+    /// `VKCORE_GPU`. Invalid values are reported and ignored.
+    pub fn from_args_or_env() -> Option<Self> {
+        let v_args: Vec<String> = std::env::args().collect();
+        let mut value = None;
+        for (index, arg) in v_args.iter().enumerate() {
+            if arg == "--gpu" {
+                value = v_args.get(index + 1).cloned();
+            } else if let Some(v) = arg.strip_prefix("--gpu=") {
+                value = Some(v.to_owned());
+            }
+        }
+        let value = value.or_else(|| std::env::var("VKCORE_GPU").ok())?;
+        match DeviceOverride::parse(&value) {
+            Ok(device_override) => Some(device_override),
+            Err(e) => {
+                eprintln!("Ignoring GPU override: {}", e);
+                None
+            }
+        }
+    }
+
+    fn matches(&self, index: usize, properties: &vk::PhysicalDeviceProperties) -> bool {
+        match self {
+            DeviceOverride::Index(i) => *i == index,
+            DeviceOverride::VendorId(vendor_id) => *vendor_id == properties.vendor_id,
+            DeviceOverride::Name(name) => device_name(properties).to_lowercase().contains(name),
+        }
+    }
+}
+
+/// The device chosen by `DeviceSelector::select`.
+pub struct SelectedDevice {
+    pub gpu: vk::PhysicalDevice,
+    pub index_of_queue_family: usize,
+    pub name: String,
+}
+
+/// Requirements used to pick a physical device.
+pub struct DeviceSelector<'a> {
+    queue_flags: vk::QueueFlags,
+    requirements: DeviceRequirements,
+    surface: Option<&'a Surface>,
+    min_device_local_memory: vk::DeviceSize,
+    device_override: Option<DeviceOverride>,
+    report: bool,
+}
+
+impl<'a> DeviceSelector<'a> {
+    /// Looks for a device with a queue family supporting `queue_flags`. The
+    /// override comes from `DeviceOverride::from_args_or_env`.
+    pub fn new(queue_flags: vk::QueueFlags) -> Self {
+        DeviceSelector {
+            queue_flags,
+            requirements: Default::default(),
+            surface: None,
+            min_device_local_memory: 0,
+            device_override: DeviceOverride::from_args_or_env(),
+            report: true,
+        }
+    }
+
+    pub fn extension(mut self, name: &'static CStr) -> Self {
+        self.requirements.required_extensions.push(name);
+        self
+    }
+
+    /// The chosen queue family must also be able to present to `surface`.
+    pub fn surface(mut self, surface: &'a Surface) -> Self {
+        self.surface = Some(surface);
+        self
+    }
+
+    pub fn features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.requirements.required_features.core = features;
+        self
+    }
+
+    /// Only the required features and extensions are checked.
+    pub fn requirements(mut self, requirements: DeviceRequirements) -> Self {
+        self.requirements = requirements;
+        self
+    }
+
+    pub fn min_device_local_memory(mut self, size: vk::DeviceSize) -> Self {
+        self.min_device_local_memory = size;
+        self
+    }
+
+    pub fn device_override(mut self, device_override: Option<DeviceOverride>) -> Self {
+        self.device_override = device_override;
+        self
+    }
+
+    /// Prints why each device was accepted or rejected (enabled by default).
+    pub fn report(mut self, report: bool) -> Self {
+        self.report = report;
+        self
+    }
+
+    /// # Safety
+    ///
+    /// `instance` must outlive the selected device.
+    pub unsafe fn select(&self, instance: &Instance) -> anyhow::Result<SelectedDevice> {
+        let gpus = instance.enumerate_physical_devices()?;
+        if gpus.is_empty() {
+            anyhow::bail!("Cannot get physical devices because none was found");
+        }
+        let mut best: Option<((u32, u64), SelectedDevice)> = None;
+        for (index, gpu) in gpus.iter().enumerate() {
+            let properties = instance.get_physical_device_properties(*gpu);
+            let name = device_name(&properties);
+            let verdict = match &self.device_override {
+                Some(o) if !o.matches(index, &properties) => {
+                    Err(format!("does not match override {:?}", o))
+                }
+                _ => self.evaluate(instance, *gpu, &properties),
+            };
+            match verdict {
+                Ok((score, index_of_queue_family)) => {
+                    if self.report {
+                        println!(
+                            "GPU {} {} ({:?}, vendor {:#x}): accepted, {} MiB, queue family {}",
+                            index,
+                            name,
+                            properties.device_type,
+                            properties.vendor_id,
+                            score.1 >> 20,
+                            index_of_queue_family
+                        );
+                    }
+                    if best.as_ref().is_none_or(|(s, _)| score > *s) {
+                        best = Some((
+                            score,
+                            SelectedDevice {
+                                gpu: *gpu,
+                                index_of_queue_family,
+                                name,
+                            },
+                        ));
+                    }
+                }
+                Err(reason) => {
+                    if self.report {
+                        println!(
+                            "GPU {} {} ({:?}, vendor {:#x}): rejected, {}",
+                            index, name, properties.device_type, properties.vendor_id, reason
+                        );
+                    }
+                }
+            }
+        }
+        match best {
+            Some((_, selected)) => {
+                if self.report {
+                    println!("Selected GPU: {}", selected.name);
+                }
+                Ok(selected)
+            }
+            None => anyhow::bail!("No physical device meets the requirements"),
+        }
+    }
+
+    /// Returns the score and the queue family to use, or why the device is rejected.
+    unsafe fn evaluate(
+        &self,
+        instance: &Instance,
+        gpu: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<((u32, u64), usize), String> {
+        let queue_family_properties = instance.get_physical_device_queue_family_properties(gpu);
+        let mut candidate_families = queue_family_properties
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.queue_count > 0 && p.queue_flags.contains(self.queue_flags))
+            .map(|(index, _)| index)
+            .peekable();
+        if candidate_families.peek().is_none() {
+            return Err(format!("no queue family supports {:?}", self.queue_flags));
+        }
+        let index_of_queue_family = match self.surface {
+            Some(surface) => candidate_families
+                .find(|index| {
+                    surface.loader().get_physical_device_surface_support(
+                        gpu,
+                        *index as u32,
+                        surface.handle(),
+                    )
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "no {:?} queue family can present to the surface",
+                        self.queue_flags
+                    )
+                })?,
+            None => candidate_families.next().unwrap(),
+        };
+
+        self.requirements
+            .resolve(instance, gpu)
+            .map_err(|unmet| unmet.reasons())?;
+
+        let device_local_memory = device_local_memory_size(instance, gpu);
+        if device_local_memory < self.min_device_local_memory {
+            return Err(format!(
+                "{} MiB of device local memory, {} MiB required",
+                device_local_memory >> 20,
+                self.min_device_local_memory >> 20
+            ));
+        }
+
+        Ok((
+            device_score(properties.device_type, device_local_memory),
+            index_of_queue_family,
+        ))
+    }
+}
+
+/// Ranks devices by type first; the device local memory only breaks ties.
+fn device_score(device_type: vk::PhysicalDeviceType, device_local_memory: u64) -> (u32, u64) {
+    let type_rank = match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 1,
+        _ => 0,
+    };
+    (type_rank, device_local_memory)
+}
+
+pub fn device_name(properties: &vk::PhysicalDeviceProperties) -> String {
+    unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Size of the largest device local heap.
+///
+/// # Safety
+///
+/// `gpu` must have been enumerated from `instance`.
+pub unsafe fn device_local_memory_size(instance: &Instance, gpu: vk::PhysicalDevice) -> u64 {
+    let memory_properties = instance.get_physical_device_memory_properties(gpu);
+    memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1 << 30;
+
+    #[test]
+    fn device_type_dominates_memory() {
+        let discrete = device_score(vk::PhysicalDeviceType::DISCRETE_GPU, 8 * GIB);
+        let integrated = device_score(vk::PhysicalDeviceType::INTEGRATED_GPU, 128 * GIB);
+        let cpu = device_score(vk::PhysicalDeviceType::CPU, 512 * GIB);
+        assert!(discrete > integrated);
+        assert!(integrated > cpu);
+        assert!(
+            device_score(vk::PhysicalDeviceType::VIRTUAL_GPU, 0)
+                > device_score(vk::PhysicalDeviceType::CPU, 512 * GIB)
+        );
+        assert!(
+            device_score(vk::PhysicalDeviceType::CPU, 0)
+                > device_score(vk::PhysicalDeviceType::OTHER, 512 * GIB)
+        );
+    }
+
+    #[test]
+    fn memory_breaks_ties() {
+        assert!(
+            device_score(vk::PhysicalDeviceType::DISCRETE_GPU, 16 * GIB)
+                > device_score(vk::PhysicalDeviceType::DISCRETE_GPU, 8 * GIB)
+        );
+    }
+
+    #[test]
+    fn parse_index() {
+        assert_eq!(DeviceOverride::parse("1"), Ok(DeviceOverride::Index(1)));
+        assert_eq!(DeviceOverride::parse(" 0 "), Ok(DeviceOverride::Index(0)));
+    }
+
+    #[test]
+    fn parse_vendor_id() {
+        assert_eq!(
+            DeviceOverride::parse("vendor:0x10de"),
+            Ok(DeviceOverride::VendorId(0x10de))
+        );
+        assert_eq!(
+            DeviceOverride::parse("vendor: 4098"),
+            Ok(DeviceOverride::VendorId(4098))
+        );
+        assert!(DeviceOverride::parse("vendor:0xzz").is_err());
+        assert!(DeviceOverride::parse("vendor:").is_err());
+    }
+
+    #[test]
+    fn parse_name() {
+        assert_eq!(
+            DeviceOverride::parse("Radeon RX"),
+            Ok(DeviceOverride::Name("radeon rx".to_owned()))
+        );
+        assert!(DeviceOverride::parse("  ").is_err());
+    }
+
+    #[test]
+    fn name_matches_case_insensitively() {
+        let mut properties = vk::PhysicalDeviceProperties {
+            vendor_id: 0x1002,
+            ..Default::default()
+        };
+        for (dst, src) in properties.device_name.iter_mut().zip(b"AMD Radeon RX 6800") {
+            *dst = *src as std::os::raw::c_char;
+        }
+        assert!(DeviceOverride::Name("radeon".to_owned()).matches(3, &properties));
+        assert!(!DeviceOverride::Name("geforce".to_owned()).matches(3, &properties));
+        assert!(DeviceOverride::VendorId(0x1002).matches(3, &properties));
+        assert!(DeviceOverride::Index(3).matches(3, &properties));
+        assert!(!DeviceOverride::Index(0).matches(3, &properties));
+    }
+}
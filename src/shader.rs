@@ -0,0 +1,48 @@
+use crate::device::Device;
+use crate::objects::ShaderModule;
+use crate::shader_compiler::ShaderCompiler;
+use anyhow::Context;
+use ash::vk;
+use std::rc::Rc;
+
+/// Loads a shader module. `.spv` files are read as precompiled SPIR-V,
+/// `.vert`, `.frag` and `.comp` files are compiled (see `ShaderCompiler`).
+///
+/// # Safety
+///
+/// The SPIR-V must be valid for the features enabled on `logical_device`.
+pub unsafe fn create_shader_module(
+    logical_device: &Rc<Device>,
+    shader_path: &str,
+) -> anyhow::Result<ShaderModule> {
+    create_shader_module_from_code(logical_device, &read_shader_code(shader_path)?)
+}
+
+/// SPIR-V words of a shader, read or compiled like `create_shader_module` does.
+pub fn read_shader_code(shader_path: &str) -> anyhow::Result<Vec<u32>> {
+    if !shader_path.ends_with(".spv") {
+        return ShaderCompiler::new()
+            .compile_file(shader_path)
+            .with_context(|| format!("Cannot compile shader {}", shader_path));
+    }
+    let mut shader_file = std::fs::File::open(shader_path)
+        .with_context(|| format!("Cannot open shader {}", shader_path))?;
+    ash::util::read_spv(&mut shader_file)
+        .with_context(|| format!("Cannot read SPIR-V from {}", shader_path))
+}
+
+/// Creates a shader module from SPIR-V words, e.g. from `ShaderCompiler`.
+///
+/// # Safety
+///
+/// `v_code` must be valid SPIR-V for the features enabled on `logical_device`.
+pub unsafe fn create_shader_module_from_code(
+    logical_device: &Rc<Device>,
+    v_code: &[u32],
+) -> anyhow::Result<ShaderModule> {
+    let shader_module_create_info = vk::ShaderModuleCreateInfo::builder().code(v_code);
+    Ok(ShaderModule::new(
+        logical_device,
+        &shader_module_create_info,
+    )?)
+}
@@ -0,0 +1,561 @@
+//! GLSL to SPIR-V compilation at runtime, with shaderc (glslang).
+//!
+//! Sources may `#include "file"` (relative to the including file, then to the
+//! include directories) or `#include <file>` (include directories only), and
+//! get the defines of the compiler. Errors point at the file and line they
+//! come from, including files pulled in by `#include`.
+
+use anyhow::Context;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Maximum depth of nested `#include`.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Stage of a GLSL file, from its extension.
+pub fn shader_stage(path: &Path) -> Option<shaderc::ShaderKind> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(shaderc::ShaderKind::Vertex),
+        "frag" => Some(shaderc::ShaderKind::Fragment),
+        "comp" => Some(shaderc::ShaderKind::Compute),
+        _ => None,
+    }
+}
+
+/// One error, located in the file it comes from.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    /// 1-based, 0 when the error has no location.
+    pub line: u32,
+    /// 1-based, 0 when only the line is known.
+    pub column: u32,
+    pub message: String,
+    /// Text of the line, to show under the message.
+    pub source_line: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            return write!(f, "{}: error: {}", self.path.display(), self.message);
+        }
+        if self.column == 0 {
+            write!(
+                f,
+                "{}:{}: error: {}",
+                self.path.display(),
+                self.line,
+                self.message
+            )?;
+            if let Some(source_line) = &self.source_line {
+                write!(f, "\n    {}", source_line)?;
+            }
+            return Ok(());
+        }
+        write!(
+            f,
+            "{}:{}:{}: error: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )?;
+        if let Some(source_line) = &self.source_line {
+            write!(
+                f,
+                "\n    {}\n    {:>width$}",
+                source_line,
+                "^",
+                width = self.column as usize
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Every error of a failed compilation.
+#[derive(Debug)]
+pub struct ShaderCompileError {
+    pub v_diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, diagnostic) in self.v_diagnostics.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderCompileError {}
+
+/// A source with its includes expanded, and where each of its lines comes from.
+struct ExpandedSource {
+    text: String,
+    /// File index and 1-based line in that file, per line of `text`.
+    v_line_origins: Vec<(usize, u32)>,
+    v_paths: Vec<PathBuf>,
+}
+
+impl ExpandedSource {
+    /// `line` is a line of `text`, `None` when the error has no location.
+    fn diagnostic(&self, message: String, line: Option<u32>) -> Diagnostic {
+        let line = match line.filter(|&line| line > 0) {
+            Some(line) => line,
+            None => {
+                return Diagnostic {
+                    path: self.v_paths[0].clone(),
+                    line: 0,
+                    column: 0,
+                    message,
+                    source_line: None,
+                }
+            }
+        };
+        let index = line as usize - 1;
+        let (file, line) = self.v_line_origins.get(index).copied().unwrap_or((0, line));
+        Diagnostic {
+            path: self.v_paths[file].clone(),
+            line,
+            column: 0,
+            message,
+            source_line: self.text.lines().nth(index).map(str::to_owned),
+        }
+    }
+
+    /// Turns the `<name>:<line>: error: <message>` lines of a shaderc error
+    /// into diagnostics located in the original files.
+    fn diagnostics(&self, name: &str, message: &str) -> Vec<Diagnostic> {
+        let mut v_diagnostics = Vec::new();
+        for line in message.lines() {
+            let rest = match line.strip_prefix(name).and_then(|r| r.strip_prefix(':')) {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let (line_number, rest) = match rest.split_once(':') {
+                Some((number, text)) => match number.parse::<u32>() {
+                    Ok(number) => (Some(number), text),
+                    Err(_) => (None, rest),
+                },
+                None => (None, rest),
+            };
+            if let Some(text) = rest.trim_start().strip_prefix("error:") {
+                v_diagnostics.push(self.diagnostic(text.trim().to_owned(), line_number));
+            }
+        }
+        if v_diagnostics.is_empty() {
+            v_diagnostics.push(self.diagnostic(message.trim().to_owned(), None));
+        }
+        v_diagnostics
+    }
+}
+
+/// Compiles GLSL files to SPIR-V 1.0 that any Vulkan 1.0 device accepts.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderCompiler {
+    v_include_dirs: Vec<PathBuf>,
+    v_defines: Vec<(String, String)>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Directory searched by `#include`, after the directory of the includer.
+    pub fn include_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.v_include_dirs.push(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Same as `#define name value` at the top of every source.
+    pub fn define<V: ToString>(mut self, name: &str, value: V) -> Self {
+        self.v_defines.push((name.to_owned(), value.to_string()));
+        self
+    }
+
+    /// Compiles a `.vert`, `.frag` or `.comp` file. Compilation errors are
+    /// returned as a `ShaderCompileError`.
+    pub fn compile_file<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<Vec<u32>> {
+        let path = path.as_ref();
+        let stage = shader_stage(path).with_context(|| {
+            format!(
+                "Unknown shader stage of {}, expected .vert, .frag or .comp",
+                path.display()
+            )
+        })?;
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read shader {}", path.display()))?;
+        self.compile_source(&text, path, stage)
+    }
+
+    /// Files `path` is made of: itself and every file it includes, found the
+    /// same way as when compiling.
+    pub fn dependencies<P: AsRef<Path>>(&self, path: P) -> Vec<PathBuf> {
+        let path = path.as_ref();
+        let mut source = ExpandedSource {
+            text: String::new(),
+            v_line_origins: Vec::new(),
+            v_paths: Vec::new(),
+        };
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                self.expand(&text, path, &mut Vec::new(), &mut source, &mut Vec::new());
+                source.v_paths
+            }
+            Err(_) => vec![path.to_owned()],
+        }
+    }
+
+    /// Compiles `text`, whose includes are looked up relative to `path`.
+    pub fn compile_source(
+        &self,
+        text: &str,
+        path: &Path,
+        stage: shaderc::ShaderKind,
+    ) -> anyhow::Result<Vec<u32>> {
+        let mut source = ExpandedSource {
+            text: String::new(),
+            v_line_origins: Vec::new(),
+            v_paths: Vec::new(),
+        };
+        let mut v_diagnostics = Vec::new();
+        self.expand(text, path, &mut Vec::new(), &mut source, &mut v_diagnostics);
+        if !v_diagnostics.is_empty() {
+            return Err(ShaderCompileError { v_diagnostics }.into());
+        }
+
+        let mut compiler = shaderc::Compiler::new().context("Cannot initialize shaderc")?;
+        let mut options =
+            shaderc::CompileOptions::new().context("Cannot create shaderc options")?;
+        options.set_target_env(
+            shaderc::TargetEnv::Vulkan,
+            shaderc::EnvVersion::Vulkan1_0 as u32,
+        );
+        for (name, value) in &self.v_defines {
+            options.add_macro_definition(name, Some(value));
+        }
+        let name = path.display().to_string();
+        match compiler.compile_into_spirv(&source.text, stage, &name, "main", Some(&options)) {
+            Ok(artifact) => Ok(artifact.as_binary().to_vec()),
+            Err(shaderc::Error::CompilationError(_, message)) => Err(ShaderCompileError {
+                v_diagnostics: source.diagnostics(&name, &message),
+            }
+            .into()),
+            Err(e) => Err(e).with_context(|| format!("Cannot compile {}", path.display())),
+        }
+    }
+
+    /// Appends `text` to `source`, replacing `#include` lines by the included
+    /// files. `v_stack` holds the files being expanded, to detect cycles.
+    fn expand(
+        &self,
+        text: &str,
+        path: &Path,
+        v_stack: &mut Vec<PathBuf>,
+        source: &mut ExpandedSource,
+        v_diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let file = source.v_paths.len();
+        source.v_paths.push(path.to_owned());
+        v_stack.push(std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned()));
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let directive = line.trim_start();
+            let error = |message: String| Diagnostic {
+                path: path.to_owned(),
+                line: line_number,
+                column: (line.len() - directive.len()) as u32 + 1,
+                message,
+                source_line: Some(line.to_owned()),
+            };
+            let name = match directive.strip_prefix("#include") {
+                Some(name) => name.trim(),
+                None => {
+                    source.text.push_str(line);
+                    source.text.push('\n');
+                    source.v_line_origins.push((file, line_number));
+                    continue;
+                }
+            };
+            let (name, is_relative) =
+                if name.len() >= 2 && name.starts_with('"') && name.ends_with('"') {
+                    (&name[1..name.len() - 1], true)
+                } else if name.len() >= 2 && name.starts_with('<') && name.ends_with('>') {
+                    (&name[1..name.len() - 1], false)
+                } else {
+                    v_diagnostics.push(error(format!(
+                        "Expected #include \"file\" or #include <file>, found {}",
+                        directive
+                    )));
+                    continue;
+                };
+            let included_path = match self.resolve_include(name, path, is_relative) {
+                Some(included_path) => included_path,
+                None => {
+                    v_diagnostics.push(error(format!("Cannot find included file {}", name)));
+                    continue;
+                }
+            };
+            let canonical_path =
+                std::fs::canonicalize(&included_path).unwrap_or_else(|_| included_path.clone());
+            if v_stack.len() >= MAX_INCLUDE_DEPTH || v_stack.contains(&canonical_path) {
+                v_diagnostics.push(error(format!(
+                    "Recursive #include of {}",
+                    included_path.display()
+                )));
+                continue;
+            }
+            match std::fs::read_to_string(&included_path) {
+                Ok(included_text) => self.expand(
+                    &included_text,
+                    &included_path,
+                    v_stack,
+                    source,
+                    v_diagnostics,
+                ),
+                Err(e) => v_diagnostics.push(error(format!(
+                    "Cannot read {}: {}",
+                    included_path.display(),
+                    e
+                ))),
+            }
+        }
+        v_stack.pop();
+    }
+
+    fn resolve_include(&self, name: &str, includer: &Path, is_relative: bool) -> Option<PathBuf> {
+        let includer_dir = includer.parent().map(Path::to_owned);
+        includer_dir
+            .filter(|_| is_relative)
+            .into_iter()
+            .chain(self.v_include_dirs.iter().cloned())
+            .map(|dir| dir.join(name))
+            .find(|candidate| candidate.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory of the test `name` in the system temporary directory.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "vkcore-shader-compiler-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, text: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn expand(compiler: &ShaderCompiler, path: &Path) -> (ExpandedSource, Vec<Diagnostic>) {
+        let mut source = ExpandedSource {
+            text: String::new(),
+            v_line_origins: Vec::new(),
+            v_paths: Vec::new(),
+        };
+        let mut v_diagnostics = Vec::new();
+        let text = std::fs::read_to_string(path).unwrap();
+        compiler.expand(
+            &text,
+            path,
+            &mut Vec::new(),
+            &mut source,
+            &mut v_diagnostics,
+        );
+        (source, v_diagnostics)
+    }
+
+    #[test]
+    fn expands_quoted_includes_relative_to_the_includer() {
+        let dir = test_dir("quoted");
+        write(&dir.join("main.comp"), "a\n#include \"lib/b.glsl\"\nd\n");
+        write(&dir.join("lib/b.glsl"), "b\n#include \"c.glsl\"\n");
+        write(&dir.join("lib/c.glsl"), "c\n");
+        let (source, v_diagnostics) = expand(&ShaderCompiler::new(), &dir.join("main.comp"));
+        assert!(v_diagnostics.is_empty());
+        assert_eq!(source.text, "a\nb\nc\nd\n");
+        assert_eq!(
+            source.v_paths,
+            [
+                dir.join("main.comp"),
+                dir.join("lib/b.glsl"),
+                dir.join("lib/c.glsl")
+            ]
+        );
+        assert_eq!(source.v_line_origins, [(0, 1), (1, 1), (2, 1), (0, 3)]);
+    }
+
+    #[test]
+    fn angle_includes_only_search_the_include_dirs() {
+        let dir = test_dir("angle");
+        write(&dir.join("main.comp"), "#include <common.glsl>\n");
+        write(&dir.join("common.glsl"), "next to main\n");
+        write(&dir.join("include/common.glsl"), "from include dir\n");
+        let compiler = ShaderCompiler::new().include_dir(dir.join("include"));
+        let (source, v_diagnostics) = expand(&compiler, &dir.join("main.comp"));
+        assert!(v_diagnostics.is_empty());
+        assert_eq!(source.text, "from include dir\n");
+
+        let (_, v_diagnostics) = expand(&ShaderCompiler::new(), &dir.join("main.comp"));
+        assert_eq!(v_diagnostics.len(), 1);
+        assert_eq!(
+            v_diagnostics[0].message,
+            "Cannot find included file common.glsl"
+        );
+    }
+
+    #[test]
+    fn reports_malformed_and_missing_includes_where_they_are() {
+        let dir = test_dir("malformed");
+        write(
+            &dir.join("main.comp"),
+            "ok\n  #include common.glsl\n#include \"missing.glsl\"\n",
+        );
+        let (_, v_diagnostics) = expand(&ShaderCompiler::new(), &dir.join("main.comp"));
+        assert_eq!(v_diagnostics.len(), 2);
+        assert_eq!(v_diagnostics[0].path, dir.join("main.comp"));
+        assert_eq!((v_diagnostics[0].line, v_diagnostics[0].column), (2, 3));
+        assert_eq!(v_diagnostics[1].line, 3);
+        assert_eq!(
+            v_diagnostics[1].message,
+            "Cannot find included file missing.glsl"
+        );
+    }
+
+    #[test]
+    fn rejects_include_cycles() {
+        let dir = test_dir("cycle");
+        write(&dir.join("a.glsl"), "#include \"b.glsl\"\n");
+        write(&dir.join("b.glsl"), "b\n#include \"a.glsl\"\n");
+        let (source, v_diagnostics) = expand(&ShaderCompiler::new(), &dir.join("a.glsl"));
+        assert_eq!(source.text, "b\n");
+        assert_eq!(v_diagnostics.len(), 1);
+        assert_eq!(v_diagnostics[0].path, dir.join("b.glsl"));
+        assert_eq!(v_diagnostics[0].line, 2);
+        assert!(v_diagnostics[0].message.starts_with("Recursive #include"));
+    }
+
+    #[test]
+    fn limits_the_include_depth() {
+        let dir = test_dir("depth");
+        for index in 0..MAX_INCLUDE_DEPTH + 8 {
+            write(
+                &dir.join(format!("{}.glsl", index)),
+                &format!("#include \"{}.glsl\"\n", index + 1),
+            );
+        }
+        let (source, v_diagnostics) = expand(&ShaderCompiler::new(), &dir.join("0.glsl"));
+        assert_eq!(source.v_paths.len(), MAX_INCLUDE_DEPTH);
+        assert_eq!(v_diagnostics.len(), 1);
+        assert_eq!(
+            v_diagnostics[0].path,
+            dir.join(format!("{}.glsl", MAX_INCLUDE_DEPTH - 1))
+        );
+    }
+
+    #[test]
+    fn dependencies_list_every_included_file() {
+        let dir = test_dir("dependencies");
+        write(
+            &dir.join("main.frag"),
+            "#include \"a.glsl\"\n#include \"b.glsl\"\n",
+        );
+        write(&dir.join("a.glsl"), "\n");
+        write(&dir.join("b.glsl"), "\n");
+        assert_eq!(
+            ShaderCompiler::new().dependencies(dir.join("main.frag")),
+            [
+                dir.join("main.frag"),
+                dir.join("a.glsl"),
+                dir.join("b.glsl")
+            ]
+        );
+        assert_eq!(
+            ShaderCompiler::new().dependencies(dir.join("none.frag")),
+            [dir.join("none.frag")]
+        );
+    }
+
+    #[test]
+    fn maps_shaderc_errors_back_to_included_files() {
+        let dir = test_dir("mapping");
+        write(&dir.join("main.comp"), "a\n#include \"b.glsl\"\nd\n");
+        write(&dir.join("b.glsl"), "b\nc\n");
+        let (source, _) = expand(&ShaderCompiler::new(), &dir.join("main.comp"));
+        let v_diagnostics = source.diagnostics(
+            "main.comp",
+            "main.comp:3: error: 'c' : undeclared identifier\n\
+             main.comp:4: warning: unused\n\
+             main.comp:4: error: 'd' : syntax error\n\
+             main.comp: error: missing entry point\n",
+        );
+        assert_eq!(v_diagnostics.len(), 3);
+        assert_eq!(v_diagnostics[0].path, dir.join("b.glsl"));
+        assert_eq!(v_diagnostics[0].line, 2);
+        assert_eq!(v_diagnostics[0].message, "'c' : undeclared identifier");
+        assert_eq!(v_diagnostics[0].source_line.as_deref(), Some("c"));
+        assert_eq!(v_diagnostics[1].path, dir.join("main.comp"));
+        assert_eq!(v_diagnostics[1].line, 3);
+        assert_eq!(v_diagnostics[2].line, 0);
+        assert_eq!(v_diagnostics[2].message, "missing entry point");
+    }
+
+    #[test]
+    fn compile_errors_point_at_the_included_file() {
+        let dir = test_dir("compile");
+        write(
+            &dir.join("main.comp"),
+            "#version 450\n#include \"common.glsl\"\nvoid main() {}\n",
+        );
+        write(
+            &dir.join("common.glsl"),
+            "layout (local_size_x = 1) in;\nfloat broken() { return undeclared; }\n",
+        );
+        let error = ShaderCompiler::new()
+            .compile_file(dir.join("main.comp"))
+            .unwrap_err();
+        let error = error.downcast::<ShaderCompileError>().unwrap();
+        assert_eq!(error.v_diagnostics[0].path, dir.join("common.glsl"));
+        assert_eq!(error.v_diagnostics[0].line, 2);
+        assert!(error.v_diagnostics[0].message.contains("undeclared"));
+    }
+
+    #[test]
+    fn compiles_specialization_constants_and_defines() {
+        const OP_SPEC_CONSTANT: u32 = 50;
+        let v_words = ShaderCompiler::new()
+            .define("VALUE", 3)
+            .compile_source(
+                "#version 450\n\
+                 layout (local_size_x_id = 0) in;\n\
+                 layout (binding = 0) buffer _data { uint value[]; } data;\n\
+                 void main() { data.value[gl_GlobalInvocationID.x] = VALUE; }\n",
+                Path::new("spec.comp"),
+                shaderc::ShaderKind::Compute,
+            )
+            .unwrap();
+        assert_eq!(v_words[0], 0x0723_0203);
+        let mut v_opcodes = Vec::new();
+        let mut offset = 5;
+        while offset < v_words.len() {
+            v_opcodes.push(v_words[offset] & 0xffff);
+            offset += (v_words[offset] >> 16) as usize;
+        }
+        assert!(v_opcodes.contains(&OP_SPEC_CONSTANT));
+    }
+}
@@ -0,0 +1,507 @@
+use crate::device::Device;
+use crate::instance::Instance;
+use crate::objects::Framebuffer;
+use crate::objects::ImageView;
+use anyhow::Context;
+use ash::extensions::khr;
+use ash::version::DeviceV1_0;
+use ash::vk;
+use std::fmt;
+use std::rc::Rc;
+
+/// Looks for a `B8G8R8A8_UNORM` / `SRGB_NONLINEAR` surface format.
+pub fn search_format(
+    v_surface_formats: &[vk::SurfaceFormatKHR],
+) -> Result<&vk::SurfaceFormatKHR, &'static str> {
+    v_surface_formats
+        .iter()
+        .find(|format| {
+            format.format == vk::Format::B8G8R8A8_UNORM
+                && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+        })
+        .ok_or("Cannot find surface format")
+}
+
+/// Upper bound of `--frames-in-flight`; more frames only add latency.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 8;
+
+/// Presentation options, read from the command line.
+#[derive(Clone, Debug)]
+pub struct PresentOptions {
+    /// Requested present mode; `None` prefers MAILBOX.
+    pub present_mode: Option<vk::PresentModeKHR>,
+    /// Requested minimum number of swapchain images; `None` asks for one more
+    /// than the surface's minimum.
+    pub image_count: Option<u32>,
+    /// Number of frames the CPU records ahead of the GPU.
+    pub frames_in_flight: usize,
+}
+
+impl PresentOptions {
+    /// `--present-mode <fifo|fifo-relaxed|mailbox|immediate>`,
+    /// `--image-count <count>` and `--frames-in-flight <count>` (2 by default, at
+    /// most `MAX_FRAMES_IN_FLIGHT`).
+    pub fn from_args() -> Self {
+        let v_args: Vec<String> = std::env::args().collect();
+        let value_of = |name: &str| {
+            v_args
+                .iter()
+                .position(|arg| arg == name)
+                .map(|index| match v_args.get(index + 1) {
+                    Some(value) => value.clone(),
+                    None => panic!("Missing value after {}", name),
+                })
+        };
+        let count_of = |name: &str, max: u32| {
+            value_of(name).map(|value| {
+                value
+                    .parse()
+                    .ok()
+                    .filter(|&count: &u32| count > 0 && count <= max)
+                    .unwrap_or_else(|| {
+                        panic!("Invalid value {} after {}, 1 to {}", value, name, max)
+                    })
+            })
+        };
+        PresentOptions {
+            present_mode: value_of("--present-mode").map(|value| {
+                parse_present_mode(&value)
+                    .unwrap_or_else(|| panic!("Unknown present mode {}", value))
+            }),
+            image_count: count_of("--image-count", u32::MAX),
+            frames_in_flight: count_of("--frames-in-flight", MAX_FRAMES_IN_FLIGHT as u32)
+                .unwrap_or(2) as usize,
+        }
+    }
+}
+
+fn parse_present_mode(name: &str) -> Option<vk::PresentModeKHR> {
+    match name.to_ascii_lowercase().replace('_', "-").as_str() {
+        "fifo" => Some(vk::PresentModeKHR::FIFO),
+        "fifo-relaxed" => Some(vk::PresentModeKHR::FIFO_RELAXED),
+        "mailbox" => Some(vk::PresentModeKHR::MAILBOX),
+        "immediate" => Some(vk::PresentModeKHR::IMMEDIATE),
+        _ => None,
+    }
+}
+
+/// Returns `requested` when the surface supports it, or else FIFO, which every
+/// implementation supports. IMMEDIATE first falls back to MAILBOX and
+/// FIFO_RELAXED to FIFO; without a request, MAILBOX is preferred.
+pub fn choose_swapchain_present_mode(
+    v_present_modes: &[vk::PresentModeKHR],
+    requested: Option<vk::PresentModeKHR>,
+) -> vk::PresentModeKHR {
+    let v_fallbacks: &[vk::PresentModeKHR] = match requested {
+        Some(vk::PresentModeKHR::FIFO_RELAXED) => {
+            &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO]
+        }
+        Some(vk::PresentModeKHR::IMMEDIATE) => {
+            &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX]
+        }
+        Some(vk::PresentModeKHR::FIFO) => &[vk::PresentModeKHR::FIFO],
+        _ => &[vk::PresentModeKHR::MAILBOX],
+    };
+    v_fallbacks
+        .iter()
+        .find(|mode| v_present_modes.contains(mode))
+        .copied()
+        .unwrap_or(vk::PresentModeKHR::FIFO)
+}
+
+/// `requested` (by default one more than the minimum) clamped to the image
+/// counts the surface supports; a `max_image_count` of 0 means no maximum.
+pub fn choose_image_count(
+    surface_capabilities: &vk::SurfaceCapabilitiesKHR,
+    requested: Option<u32>,
+) -> u32 {
+    let image_count = requested
+        .unwrap_or(surface_capabilities.min_image_count + 1)
+        .max(surface_capabilities.min_image_count);
+    if surface_capabilities.max_image_count > 0 {
+        image_count.min(surface_capabilities.max_image_count)
+    } else {
+        image_count
+    }
+}
+
+/// What the surface accepted of the `PresentOptions`.
+#[derive(Clone, Debug)]
+pub struct PresentSettings {
+    options: PresentOptions,
+    pub present_mode: vk::PresentModeKHR,
+    /// `min_image_count` of the swapchain; the driver can create more images.
+    pub image_count: u32,
+    pub frames_in_flight: usize,
+}
+
+impl PresentSettings {
+    pub fn negotiate(
+        options: &PresentOptions,
+        surface_capabilities: &vk::SurfaceCapabilitiesKHR,
+        v_present_modes: &[vk::PresentModeKHR],
+    ) -> Self {
+        PresentSettings {
+            options: options.clone(),
+            present_mode: choose_swapchain_present_mode(v_present_modes, options.present_mode),
+            image_count: choose_image_count(surface_capabilities, options.image_count),
+            frames_in_flight: options.frames_in_flight,
+        }
+    }
+}
+
+impl fmt::Display for PresentSettings {
+    /// The negotiated settings, with what was requested when it differs.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Present mode {:?}", self.present_mode)?;
+        match self.options.present_mode {
+            Some(mode) if mode != self.present_mode => write!(f, " ({:?} is not supported)", mode)?,
+            _ => {}
+        }
+        write!(f, ", at least {} swapchain images", self.image_count)?;
+        match self.options.image_count {
+            Some(count) if count != self.image_count => write!(f, " ({} requested)", count)?,
+            _ => {}
+        }
+        write!(f, ", {} frames in flight", self.frames_in_flight)
+    }
+}
+
+/// Extent of the swapchain images: the surface's current extent, or the
+/// window's drawable size clamped to what the surface supports when the
+/// swapchain decides. Zero while the window is minimized on some platforms.
+pub fn choose_extent(
+    surface_capabilities: &vk::SurfaceCapabilitiesKHR,
+    drawable_size: (u32, u32),
+) -> vk::Extent2D {
+    if surface_capabilities.current_extent.width != u32::MAX {
+        return surface_capabilities.current_extent;
+    }
+    vk::Extent2D {
+        width: drawable_size.0.clamp(
+            surface_capabilities.min_image_extent.width,
+            surface_capabilities.max_image_extent.width,
+        ),
+        height: drawable_size.1.clamp(
+            surface_capabilities.min_image_extent.height,
+            surface_capabilities.max_image_extent.height,
+        ),
+    }
+}
+
+/// Owned `VkSurfaceKHR` together with its extension loader.
+pub struct Surface {
+    instance: Rc<Instance>,
+    loader: khr::Surface,
+    handle: vk::SurfaceKHR,
+}
+
+impl Surface {
+    /// Takes ownership of a surface created by the windowing library.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must have been created for `instance` and must not be destroyed elsewhere: the
+    /// wrapper destroys it when dropped.
+    pub unsafe fn from_raw(instance: &Rc<Instance>, handle: vk::SurfaceKHR) -> Self {
+        Surface {
+            instance: instance.clone(),
+            loader: khr::Surface::new(instance.entry(), instance.raw()),
+            handle,
+        }
+    }
+
+    pub fn handle(&self) -> vk::SurfaceKHR {
+        self.handle
+    }
+
+    pub fn loader(&self) -> &khr::Surface {
+        &self.loader
+    }
+
+    pub fn instance(&self) -> &Rc<Instance> {
+        &self.instance
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader.destroy_surface(self.handle, None);
+        }
+    }
+}
+
+/// Owned `VkSwapchainKHR` together with its extension loader.
+///
+/// The surface must outlive the swapchain; the caller keeps it alive.
+pub struct Swapchain {
+    device: Rc<Device>,
+    loader: khr::Swapchain,
+    handle: vk::SwapchainKHR,
+}
+
+impl Swapchain {
+    /// # Safety
+    ///
+    /// `create_info` and everything it points to must be valid for `device`, including its surface
+    /// and old swapchain.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        create_info: &vk::SwapchainCreateInfoKHR,
+    ) -> ash::prelude::VkResult<Self> {
+        let loader = khr::Swapchain::new(device.instance().raw(), device.raw());
+        let handle = loader.create_swapchain(create_info, None)?;
+        Ok(Swapchain {
+            device: device.clone(),
+            loader,
+            handle,
+        })
+    }
+
+    pub fn handle(&self) -> vk::SwapchainKHR {
+        self.handle
+    }
+
+    /// # Safety
+    ///
+    /// The swapchain must still be alive.
+    pub unsafe fn get_images(&self) -> ash::prelude::VkResult<Vec<vk::Image>> {
+        self.loader.get_swapchain_images(self.handle)
+    }
+
+    /// Returns the index of the acquired image and whether the swapchain is suboptimal.
+    ///
+    /// # Safety
+    ///
+    /// `semaphore` and `fence` must come from the swapchain's device and must be unsignaled, one of
+    /// them may be null.
+    pub unsafe fn acquire_next_image(
+        &self,
+        timeout: u64,
+        semaphore: vk::Semaphore,
+        fence: vk::Fence,
+    ) -> ash::prelude::VkResult<(u32, bool)> {
+        self.loader
+            .acquire_next_image(self.handle, timeout, semaphore, fence)
+    }
+
+    /// Presents `image_index` once `wait_semaphore` is signaled and returns whether the
+    /// swapchain is suboptimal.
+    ///
+    /// # Safety
+    ///
+    /// `queue` must support presenting to the surface and `image_index` must have been acquired.
+    pub unsafe fn queue_present(
+        &self,
+        queue: vk::Queue,
+        wait_semaphore: vk::Semaphore,
+        image_index: u32,
+    ) -> ash::prelude::VkResult<bool> {
+        let present_info = vk::PresentInfoKHR {
+            s_type: vk::StructureType::PRESENT_INFO_KHR,
+            p_next: std::ptr::null(),
+            wait_semaphore_count: 1,
+            p_wait_semaphores: &wait_semaphore,
+            swapchain_count: 1,
+            p_swapchains: &self.handle,
+            p_image_indices: &image_index,
+            p_results: std::ptr::null_mut(),
+        };
+        self.loader.queue_present(queue, &present_info)
+    }
+
+    pub fn loader(&self) -> &khr::Swapchain {
+        &self.loader
+    }
+
+    pub fn device(&self) -> &Rc<Device> {
+        &self.device
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader.destroy_swapchain(self.handle, None);
+        }
+    }
+}
+
+/// A swapchain with a view, a framebuffer and a primary command buffer per
+/// image: everything that has to be created again when the window is resized.
+///
+/// The command pool must outlive the `SwapchainFramebuffers`.
+pub struct SwapchainFramebuffers {
+    command_pool: vk::CommandPool,
+    v_command_buffers: Vec<vk::CommandBuffer>,
+    // Dropped in this order: the framebuffers use the views, which use the
+    // images of the swapchain.
+    v_framebuffers: Vec<Framebuffer>,
+    v_image_views: Vec<ImageView>,
+    swapchain: Swapchain,
+    extent: vk::Extent2D,
+}
+
+impl SwapchainFramebuffers {
+    /// Creates the swapchain described by `create_info`, a framebuffer per
+    /// image for `render_pass`, whose only attachment is the image, and a
+    /// command buffer per image from `command_pool`.
+    ///
+    /// # Safety
+    ///
+    /// Same as `Swapchain::new`; `render_pass` and `command_pool` must come from `device`.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        create_info: &vk::SwapchainCreateInfoKHR,
+        render_pass: vk::RenderPass,
+        command_pool: vk::CommandPool,
+    ) -> anyhow::Result<Self> {
+        let swapchain = Swapchain::new(device, create_info).context("Cannot create swapchain")?;
+        let v_images = swapchain
+            .get_images()
+            .context("Cannot get swapchain images")?;
+        let mut v_image_views = Vec::with_capacity(v_images.len());
+        let mut v_framebuffers = Vec::with_capacity(v_images.len());
+        for image in v_images {
+            let image_view_create_info = vk::ImageViewCreateInfo {
+                s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                image,
+                view_type: vk::ImageViewType::TYPE_2D,
+                format: create_info.image_format,
+                components: vk::ComponentMapping {
+                    r: vk::ComponentSwizzle::IDENTITY,
+                    g: vk::ComponentSwizzle::IDENTITY,
+                    b: vk::ComponentSwizzle::IDENTITY,
+                    a: vk::ComponentSwizzle::IDENTITY,
+                },
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+            };
+            let image_view = ImageView::new(device, &image_view_create_info)
+                .context("Cannot create image view")?;
+            let image_view_handle = image_view.handle();
+            let framebuffer_create_info = vk::FramebufferCreateInfo {
+                s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: Default::default(),
+                render_pass,
+                attachment_count: 1,
+                p_attachments: &image_view_handle,
+                width: create_info.image_extent.width,
+                height: create_info.image_extent.height,
+                layers: 1,
+            };
+            v_framebuffers.push(
+                Framebuffer::new(device, &framebuffer_create_info)
+                    .context("Cannot create framebuffer")?,
+            );
+            v_image_views.push(image_view);
+        }
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+            p_next: std::ptr::null(),
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: v_framebuffers.len() as u32,
+        };
+        let v_command_buffers = device
+            .allocate_command_buffers(&command_buffer_allocate_info)
+            .context("Cannot allocate command buffers")?;
+        Ok(SwapchainFramebuffers {
+            command_pool,
+            v_command_buffers,
+            v_framebuffers,
+            v_image_views,
+            swapchain,
+            extent: create_info.image_extent,
+        })
+    }
+
+    /// Creates the swapchain again for the current size of `surface`, from
+    /// `create_info` with an up to date extent and transform. Returns `None`
+    /// while the surface has no area (minimized window). The device must be
+    /// idle; `self` can be dropped once the new swapchain is created.
+    ///
+    /// The new swapchain can have another number of images: resources kept
+    /// per image have to follow `image_count()`.
+    ///
+    /// # Safety
+    ///
+    /// Same as `new`; `surface` must be the surface of `create_info`.
+    pub unsafe fn recreate(
+        &self,
+        surface: &Surface,
+        create_info: &vk::SwapchainCreateInfoKHR,
+        drawable_size: (u32, u32),
+        render_pass: vk::RenderPass,
+    ) -> anyhow::Result<Option<Self>> {
+        let surface_capabilities = surface
+            .loader()
+            .get_physical_device_surface_capabilities(
+                self.swapchain.device().gpu(),
+                surface.handle(),
+            )
+            .context("Cannot get surface capabilities")?;
+        let extent = choose_extent(&surface_capabilities, drawable_size);
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(None);
+        }
+        let create_info = vk::SwapchainCreateInfoKHR {
+            image_extent: extent,
+            pre_transform: surface_capabilities.current_transform,
+            old_swapchain: self.swapchain.handle(),
+            ..*create_info
+        };
+        SwapchainFramebuffers::new(
+            self.swapchain.device(),
+            &create_info,
+            render_pass,
+            self.command_pool,
+        )
+        .map(Some)
+    }
+
+    pub fn swapchain(&self) -> &Swapchain {
+        &self.swapchain
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Number of swapchain images.
+    pub fn image_count(&self) -> usize {
+        self.v_framebuffers.len()
+    }
+
+    pub fn image_view(&self, index: usize) -> vk::ImageView {
+        self.v_image_views[index].handle()
+    }
+
+    pub fn framebuffer(&self, index: usize) -> vk::Framebuffer {
+        self.v_framebuffers[index].handle()
+    }
+
+    /// One command buffer per image, to record the rendering to `framebuffer`.
+    pub fn command_buffers(&self) -> &[vk::CommandBuffer] {
+        &self.v_command_buffers
+    }
+}
+
+impl Drop for SwapchainFramebuffers {
+    fn drop(&mut self) {
+        unsafe {
+            self.swapchain
+                .device()
+                .free_command_buffers(self.command_pool, &self.v_command_buffers);
+        }
+    }
+}
@@ -0,0 +1,616 @@
+//! One-shot uploads from staging buffers.
+//!
+//! When the transfer queue is not in the family of the destination queue (the
+//! main queue by default, see `Uploader::for_queue`), the copy runs on the
+//! transfer queue, then the buffer or image is released by the transfer family
+//! and acquired by the destination family with a pair of barriers, the acquire
+//! waiting on a semaphore signaled by the release. Otherwise everything is
+//! recorded in a single command buffer.
+//!
+//! `submit_one_time` runs any other one-shot command buffer the same way.
+
+use crate::device::Device;
+use crate::device::Queue;
+use crate::objects::CommandPool;
+use crate::objects::Fence;
+use crate::objects::Semaphore;
+use crate::profiler::GpuProfiler;
+use ash::prelude::VkResult;
+use ash::version::DeviceV1_0;
+use ash::vk;
+use std::rc::Rc;
+
+const COLOR_SUBRESOURCE_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask: vk::ImageAspectFlags::COLOR,
+    base_mip_level: 0,
+    level_count: 1,
+    base_array_layer: 0,
+    layer_count: 1,
+};
+
+/// Resource whose ownership moves from the transfer family to the destination family.
+#[derive(Clone, Copy)]
+enum Resource {
+    Buffer(vk::Buffer),
+    Image {
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    },
+}
+
+/// Layout of an image on one side of a barrier, with the stages and accesses
+/// waited for or waiting on it.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageState {
+    pub layout: vk::ImageLayout,
+    pub stage: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+}
+
+pub struct Uploader {
+    device: Rc<Device>,
+    destination_queue: Queue,
+    transfer_command_pool: CommandPool,
+    // Only created when the transfer queue is in another family.
+    acquire_command_pool: Option<CommandPool>,
+    semaphore_transfer_done: Semaphore,
+    fence_upload_done: Fence,
+    // Times the copies when the profiler is for the transfer queue family.
+    profiler: Option<(Rc<GpuProfiler>, usize)>,
+}
+
+impl Uploader {
+    /// # Safety
+    ///
+    /// `device` must outlive the uploader.
+    pub unsafe fn new(device: &Rc<Device>) -> VkResult<Self> {
+        Uploader::for_queue(device, device.main_queue())
+    }
+
+    /// Uploads resources for `destination_queue`, e.g. `Device::compute_queue`:
+    /// they end up owned by its family.
+    ///
+    /// # Safety
+    ///
+    /// `destination_queue` must be a queue of `device`, which must outlive the uploader.
+    pub unsafe fn for_queue(device: &Rc<Device>, destination_queue: Queue) -> VkResult<Self> {
+        let create_command_pool = |queue: Queue| {
+            let command_pool_create_info = vk::CommandPoolCreateInfo {
+                s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: vk::CommandPoolCreateFlags::TRANSIENT,
+                queue_family_index: queue.family_index,
+            };
+            CommandPool::new(device, &command_pool_create_info)
+        };
+        let transfer_command_pool = create_command_pool(device.transfer_queue())?;
+        let acquire_command_pool =
+            if device.transfer_queue().family_index != destination_queue.family_index {
+                Some(create_command_pool(destination_queue)?)
+            } else {
+                None
+            };
+        let semaphore_create_info = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+        };
+        let fence_create_info = vk::FenceCreateInfo {
+            s_type: vk::StructureType::FENCE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: Default::default(),
+        };
+        Ok(Uploader {
+            device: device.clone(),
+            destination_queue,
+            transfer_command_pool,
+            acquire_command_pool,
+            semaphore_transfer_done: Semaphore::new(device, &semaphore_create_info)?,
+            fence_upload_done: Fence::new(device, &fence_create_info)?,
+            profiler: None,
+        })
+    }
+
+    /// Wraps every copy in an "upload" scope of `slot`. Ignored when the
+    /// profiler is not for the family of `Device::transfer_queue`.
+    pub fn with_profiler(mut self, profiler: Rc<GpuProfiler>, slot: usize) -> Self {
+        self.profiler = Some((profiler, slot));
+        self
+    }
+
+    /// Copies `regions` of `src` into `dst` and waits for the copy. Afterwards
+    /// `dst` belongs to the destination queue family and is visible to `dst_access`
+    /// at `dst_stage`.
+    ///
+    /// # Safety
+    ///
+    /// `src` and `dst` must come from the uploader's device and must not be in use by the device.
+    pub unsafe fn copy_buffer(
+        &self,
+        src: vk::Buffer,
+        dst: vk::Buffer,
+        regions: &[vk::BufferCopy],
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) -> VkResult<()> {
+        self.upload(
+            |command_buffer| {
+                self.device
+                    .cmd_copy_buffer(command_buffer, src, dst, regions)
+            },
+            Resource::Buffer(dst),
+            dst_stage,
+            dst_access,
+        )
+    }
+
+    /// Copies `src` into the first mip level of a color `image`, whose content is
+    /// discarded, and leaves it in the `dst` state owned by the destination queue
+    /// family.
+    ///
+    /// # Safety
+    ///
+    /// `src` and `image` must come from the uploader's device and must not be in use by the device.
+    pub unsafe fn copy_buffer_to_image(
+        &self,
+        src: vk::Buffer,
+        image: vk::Image,
+        width: u32,
+        height: u32,
+        dst: ImageState,
+    ) -> VkResult<()> {
+        let buffer_image_copy_region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+        };
+        self.upload(
+            |command_buffer| {
+                let image_memory_barrier = vk::ImageMemoryBarrier {
+                    s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+                    p_next: std::ptr::null(),
+                    src_access_mask: vk::AccessFlags::empty(),
+                    dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    image,
+                    subresource_range: COLOR_SUBRESOURCE_RANGE,
+                };
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                    Default::default(),
+                    &[],
+                    &[],
+                    &[image_memory_barrier],
+                );
+                self.device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    src,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[buffer_image_copy_region],
+                );
+            },
+            Resource::Image {
+                image,
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: dst.layout,
+            },
+            dst.stage,
+            dst.access,
+        )
+    }
+
+    /// Transitions a color image on the destination queue and waits for it.
+    ///
+    /// # Safety
+    ///
+    /// `image` must come from the uploader's device, be in the `src` state and not be in use by the
+    /// device.
+    pub unsafe fn change_image_layout(
+        &self,
+        image: vk::Image,
+        src: ImageState,
+        dst: ImageState,
+    ) -> VkResult<()> {
+        let command_pool = self
+            .acquire_command_pool
+            .as_ref()
+            .unwrap_or(&self.transfer_command_pool);
+        let command_buffer = begin_one_time(&self.device, command_pool)?;
+        let image_memory_barrier = vk::ImageMemoryBarrier {
+            s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+            p_next: std::ptr::null(),
+            src_access_mask: src.access,
+            dst_access_mask: dst.access,
+            old_layout: src.layout,
+            new_layout: dst.layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image,
+            subresource_range: COLOR_SUBRESOURCE_RANGE,
+        };
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            src.stage,
+            dst.stage,
+            Default::default(),
+            &[],
+            &[],
+            &[image_memory_barrier],
+        );
+        self.device.end_command_buffer(command_buffer)?;
+        let result = self.submit_and_wait(self.destination_queue, command_buffer);
+        self.device
+            .free_command_buffers(command_pool.handle(), &[command_buffer]);
+        result
+    }
+
+    /// Records `record_copy` then the barriers making `resource` available to
+    /// `dst_access` at `dst_stage` on the destination queue, submits and waits.
+    unsafe fn upload(
+        &self,
+        record_copy: impl FnOnce(vk::CommandBuffer),
+        resource: Resource,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) -> VkResult<()> {
+        let transfer_queue = self.device.transfer_queue();
+        let destination_queue = self.destination_queue;
+        let transfer_command_buffer = begin_one_time(&self.device, &self.transfer_command_pool)?;
+        let profiler = self
+            .profiler
+            .as_ref()
+            .filter(|(profiler, _)| profiler.queue_family_index() == transfer_queue.family_index);
+        match profiler {
+            Some((profiler, slot)) => {
+                profiler.record_reset(transfer_command_buffer, *slot);
+                let scope = profiler.begin_scope(transfer_command_buffer, *slot, "upload");
+                record_copy(transfer_command_buffer);
+                profiler.end_scope(transfer_command_buffer, scope);
+            }
+            None => record_copy(transfer_command_buffer),
+        }
+
+        let acquire_command_pool = match &self.acquire_command_pool {
+            None => {
+                self.record_barrier(
+                    transfer_command_buffer,
+                    resource,
+                    (vk::PipelineStageFlags::TRANSFER, dst_stage),
+                    (vk::AccessFlags::TRANSFER_WRITE, dst_access),
+                    (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+                );
+                let result = self
+                    .device
+                    .end_command_buffer(transfer_command_buffer)
+                    .and_then(|()| self.submit_and_wait(transfer_queue, transfer_command_buffer));
+                self.device.free_command_buffers(
+                    self.transfer_command_pool.handle(),
+                    &[transfer_command_buffer],
+                );
+                if let (Ok(()), Some((profiler, slot))) = (result, profiler) {
+                    profiler.collect(*slot)?;
+                }
+                return result;
+            }
+            Some(command_pool) => command_pool,
+        };
+
+        // Release: only the source half of the barrier matters on this queue.
+        let families = (transfer_queue.family_index, destination_queue.family_index);
+        self.record_barrier(
+            transfer_command_buffer,
+            resource,
+            (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            ),
+            (vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::empty()),
+            families,
+        );
+        let semaphore_transfer_done = self.semaphore_transfer_done.handle();
+        let release_submit_info = vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            p_next: std::ptr::null(),
+            wait_semaphore_count: 0,
+            p_wait_semaphores: std::ptr::null(),
+            p_wait_dst_stage_mask: std::ptr::null(),
+            command_buffer_count: 1,
+            p_command_buffers: &transfer_command_buffer,
+            signal_semaphore_count: 1,
+            p_signal_semaphores: &semaphore_transfer_done,
+        };
+        let release = self
+            .device
+            .end_command_buffer(transfer_command_buffer)
+            .and_then(|()| {
+                self.device.queue_submit(
+                    transfer_queue.handle,
+                    &[release_submit_info],
+                    vk::Fence::null(),
+                )
+            });
+        if let Err(e) = release {
+            self.device.free_command_buffers(
+                self.transfer_command_pool.handle(),
+                &[transfer_command_buffer],
+            );
+            return Err(e);
+        }
+
+        let mut acquire_command_buffer = None;
+        let result = match self.submit_acquire(
+            acquire_command_pool,
+            &mut acquire_command_buffer,
+            resource,
+            families,
+            dst_stage,
+            dst_access,
+        ) {
+            // The acquire waited for the release, so both command buffers are done.
+            Ok(()) => self.wait_upload_done(),
+            // Nothing waits on the release's signal yet: consume it, or the next
+            // upload's release would signal a semaphore that is already signaled.
+            Err(e) => self.consume_transfer_done().and(Err(e)),
+        };
+        self.device.free_command_buffers(
+            self.transfer_command_pool.handle(),
+            &[transfer_command_buffer],
+        );
+        if let Some(acquire_command_buffer) = acquire_command_buffer {
+            self.device
+                .free_command_buffers(acquire_command_pool.handle(), &[acquire_command_buffer]);
+        }
+        if let (Ok(()), Some((profiler, slot))) = (result, profiler) {
+            profiler.collect(*slot)?;
+        }
+        result
+    }
+
+    /// Acquire: only the destination half of the barrier matters on this queue.
+    /// Its source stage is the semaphore wait stage, which chains it after the
+    /// release.
+    unsafe fn submit_acquire(
+        &self,
+        command_pool: &CommandPool,
+        command_buffer: &mut Option<vk::CommandBuffer>,
+        resource: Resource,
+        families: (u32, u32),
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) -> VkResult<()> {
+        let acquire_command_buffer =
+            *command_buffer.insert(begin_one_time(&self.device, command_pool)?);
+        self.record_barrier(
+            acquire_command_buffer,
+            resource,
+            (dst_stage, dst_stage),
+            (vk::AccessFlags::empty(), dst_access),
+            families,
+        );
+        self.device.end_command_buffer(acquire_command_buffer)?;
+        let semaphore_transfer_done = self.semaphore_transfer_done.handle();
+        let acquire_submit_info = vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            p_next: std::ptr::null(),
+            wait_semaphore_count: 1,
+            p_wait_semaphores: &semaphore_transfer_done,
+            p_wait_dst_stage_mask: &dst_stage,
+            command_buffer_count: 1,
+            p_command_buffers: &acquire_command_buffer,
+            signal_semaphore_count: 0,
+            p_signal_semaphores: std::ptr::null(),
+        };
+        self.device.queue_submit(
+            self.destination_queue.handle,
+            &[acquire_submit_info],
+            self.fence_upload_done.handle(),
+        )
+    }
+
+    /// Waits on `semaphore_transfer_done` with an empty batch on the destination
+    /// queue, which also waits for the release to be done.
+    unsafe fn consume_transfer_done(&self) -> VkResult<()> {
+        let semaphore_transfer_done = self.semaphore_transfer_done.handle();
+        let wait_stage = vk::PipelineStageFlags::ALL_COMMANDS;
+        let submit_info = vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            p_next: std::ptr::null(),
+            wait_semaphore_count: 1,
+            p_wait_semaphores: &semaphore_transfer_done,
+            p_wait_dst_stage_mask: &wait_stage,
+            command_buffer_count: 0,
+            p_command_buffers: std::ptr::null(),
+            signal_semaphore_count: 0,
+            p_signal_semaphores: std::ptr::null(),
+        };
+        self.device.queue_submit(
+            self.destination_queue.handle,
+            &[submit_info],
+            self.fence_upload_done.handle(),
+        )?;
+        self.wait_upload_done()
+    }
+
+    unsafe fn record_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        resource: Resource,
+        (src_stage, dst_stage): (vk::PipelineStageFlags, vk::PipelineStageFlags),
+        (src_access, dst_access): (vk::AccessFlags, vk::AccessFlags),
+        (src_family, dst_family): (u32, u32),
+    ) {
+        match resource {
+            Resource::Buffer(buffer) => {
+                let buffer_memory_barrier = vk::BufferMemoryBarrier {
+                    s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+                    p_next: std::ptr::null(),
+                    src_access_mask: src_access,
+                    dst_access_mask: dst_access,
+                    src_queue_family_index: src_family,
+                    dst_queue_family_index: dst_family,
+                    buffer,
+                    offset: 0,
+                    size: vk::WHOLE_SIZE,
+                };
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    src_stage,
+                    dst_stage,
+                    Default::default(),
+                    &[],
+                    &[buffer_memory_barrier],
+                    &[],
+                );
+            }
+            Resource::Image {
+                image,
+                old_layout,
+                new_layout,
+            } => {
+                let image_memory_barrier = vk::ImageMemoryBarrier {
+                    s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+                    p_next: std::ptr::null(),
+                    src_access_mask: src_access,
+                    dst_access_mask: dst_access,
+                    old_layout,
+                    new_layout,
+                    src_queue_family_index: src_family,
+                    dst_queue_family_index: dst_family,
+                    image,
+                    subresource_range: COLOR_SUBRESOURCE_RANGE,
+                };
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    src_stage,
+                    dst_stage,
+                    Default::default(),
+                    &[],
+                    &[],
+                    &[image_memory_barrier],
+                );
+            }
+        }
+    }
+
+    unsafe fn submit_and_wait(
+        &self,
+        queue: Queue,
+        command_buffer: vk::CommandBuffer,
+    ) -> VkResult<()> {
+        let submit_info = vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            p_next: std::ptr::null(),
+            wait_semaphore_count: 0,
+            p_wait_semaphores: std::ptr::null(),
+            p_wait_dst_stage_mask: std::ptr::null(),
+            command_buffer_count: 1,
+            p_command_buffers: &command_buffer,
+            signal_semaphore_count: 0,
+            p_signal_semaphores: std::ptr::null(),
+        };
+        self.device.queue_submit(
+            queue.handle,
+            &[submit_info],
+            self.fence_upload_done.handle(),
+        )?;
+        self.wait_upload_done()
+    }
+
+    unsafe fn wait_upload_done(&self) -> VkResult<()> {
+        let fence = self.fence_upload_done.handle();
+        self.device.wait_for_fences(&[fence], true, u64::MAX)?;
+        self.device.reset_fences(&[fence])
+    }
+}
+
+/// Records `record` in a command buffer of a transient pool of the family of
+/// `queue`, submits it and waits for the device. Returns the signaled fence of
+/// the submission, e.g. to map the buffers it wrote.
+///
+/// # Safety
+///
+/// `queue` must be a queue of `device`, and `record` must only record valid commands for its
+/// family.
+pub unsafe fn submit_one_time(
+    device: &Rc<Device>,
+    queue: Queue,
+    record: impl FnOnce(vk::CommandBuffer),
+) -> VkResult<Fence> {
+    let command_pool_create_info = vk::CommandPoolCreateInfo {
+        s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+        p_next: std::ptr::null(),
+        flags: vk::CommandPoolCreateFlags::TRANSIENT,
+        queue_family_index: queue.family_index,
+    };
+    let command_pool = CommandPool::new(device, &command_pool_create_info)?;
+    let command_buffer = begin_one_time(device, &command_pool)?;
+    record(command_buffer);
+    device.end_command_buffer(command_buffer)?;
+
+    let fence_create_info = vk::FenceCreateInfo {
+        s_type: vk::StructureType::FENCE_CREATE_INFO,
+        p_next: std::ptr::null(),
+        flags: Default::default(),
+    };
+    let fence = Fence::new(device, &fence_create_info)?;
+    let submit_info = vk::SubmitInfo {
+        s_type: vk::StructureType::SUBMIT_INFO,
+        p_next: std::ptr::null(),
+        wait_semaphore_count: 0,
+        p_wait_semaphores: std::ptr::null(),
+        p_wait_dst_stage_mask: std::ptr::null(),
+        command_buffer_count: 1,
+        p_command_buffers: &command_buffer,
+        signal_semaphore_count: 0,
+        p_signal_semaphores: std::ptr::null(),
+    };
+    device.queue_submit(queue.handle, &[submit_info], fence.handle())?;
+    device.wait_for_fences(&[fence.handle()], true, u64::MAX)?;
+    Ok(fence)
+}
+
+/// Allocates a primary command buffer from `command_pool` and begins it for a
+/// single submission.
+unsafe fn begin_one_time(
+    device: &Device,
+    command_pool: &CommandPool,
+) -> VkResult<vk::CommandBuffer> {
+    let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
+        s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+        p_next: std::ptr::null(),
+        command_pool: command_pool.handle(),
+        level: vk::CommandBufferLevel::PRIMARY,
+        command_buffer_count: 1,
+    };
+    let command_buffer = device.allocate_command_buffers(&command_buffer_allocate_info)?[0];
+    let command_buffer_begin_info = vk::CommandBufferBeginInfo {
+        s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+        p_next: std::ptr::null(),
+        flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+        p_inheritance_info: std::ptr::null(),
+    };
+    if let Err(e) = device.begin_command_buffer(command_buffer, &command_buffer_begin_info) {
+        device.free_command_buffers(command_pool.handle(), &[command_buffer]);
+        return Err(e);
+    }
+    Ok(command_buffer)
+}
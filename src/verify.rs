@@ -0,0 +1,239 @@
+//! Compares what a compute shader wrote against a CPU reference, so the compute
+//! samples can run as regression tests (`--verify`, non-zero exit code on
+//! mismatch).
+
+use std::fmt;
+
+/// Allowed difference between a GPU and a CPU float: `absolute`, plus
+/// `relative` times the expected value. Integers must be equal.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            absolute: 1e-6,
+            relative: 1e-5,
+        }
+    }
+}
+
+/// Element types that can be compared under a `Tolerance`.
+pub trait Compare: Copy + fmt::Display {
+    fn matches(self, expected: Self, tolerance: Tolerance) -> bool;
+}
+
+macro_rules! compare_exact {
+    ($($t:ty),*) => {
+        $(impl Compare for $t {
+            fn matches(self, expected: Self, _: Tolerance) -> bool {
+                self == expected
+            }
+        })*
+    };
+}
+
+compare_exact!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+macro_rules! compare_float {
+    ($($t:ty),*) => {
+        $(impl Compare for $t {
+            fn matches(self, expected: Self, tolerance: Tolerance) -> bool {
+                if self.is_nan() || expected.is_nan() {
+                    return self.is_nan() && expected.is_nan();
+                }
+                let difference = (self as f64 - expected as f64).abs();
+                difference <= tolerance.absolute + tolerance.relative * (expected as f64).abs()
+            }
+        })*
+    };
+}
+
+compare_float!(f32, f64);
+
+/// Options of the `--verify` mode, read from the command line.
+#[derive(Clone, Copy, Debug)]
+pub struct VerifyOptions {
+    pub tolerance: Tolerance,
+    /// Number of mismatches printed, the others are only counted.
+    pub max_reported: usize,
+}
+
+impl VerifyOptions {
+    /// Returns `Some` when `--verify` is on the command line, with
+    /// `--tolerance <absolute>[,<relative>]` and `--max-mismatches <n>` applied.
+    pub fn from_args() -> Option<Self> {
+        let v_args: Vec<String> = std::env::args().collect();
+        if !v_args.iter().any(|arg| arg == "--verify") {
+            return None;
+        }
+        let mut options = VerifyOptions {
+            tolerance: Tolerance::default(),
+            max_reported: 10,
+        };
+        let value_of = |name: &str| {
+            v_args
+                .iter()
+                .position(|arg| arg == name)
+                .map(|index| match v_args.get(index + 1) {
+                    Some(value) => value.as_str(),
+                    None => panic!("Missing value after {}", name),
+                })
+        };
+        if let Some(value) = value_of("--tolerance") {
+            let parse = |text: &str| {
+                text.parse::<f64>()
+                    .unwrap_or_else(|_| panic!("Invalid tolerance {}", value))
+            };
+            match value.split_once(',') {
+                Some((absolute, relative)) => {
+                    options.tolerance.absolute = parse(absolute);
+                    options.tolerance.relative = parse(relative);
+                }
+                None => options.tolerance.absolute = parse(value),
+            }
+        }
+        if let Some(value) = value_of("--max-mismatches") {
+            options.max_reported = value
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid mismatch count {}", value));
+        }
+        Some(options)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Mismatch<T> {
+    pub index: usize,
+    pub expected: T,
+    pub actual: T,
+}
+
+/// Result of `compare`, printed as a compact diff.
+#[derive(Clone, Debug)]
+pub struct Comparison<T> {
+    pub name: String,
+    pub expected_len: usize,
+    pub actual_len: usize,
+    pub mismatch_count: usize,
+    /// The first `max_reported` mismatches.
+    pub mismatches: Vec<Mismatch<T>>,
+}
+
+impl<T> Comparison<T> {
+    pub fn is_ok(&self) -> bool {
+        self.mismatch_count == 0 && self.expected_len == self.actual_len
+    }
+}
+
+pub fn compare<T: Compare>(
+    name: &str,
+    expected: &[T],
+    actual: &[T],
+    options: &VerifyOptions,
+) -> Comparison<T> {
+    let mut comparison = Comparison {
+        name: name.to_owned(),
+        expected_len: expected.len(),
+        actual_len: actual.len(),
+        mismatch_count: 0,
+        mismatches: Vec::new(),
+    };
+    for (index, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+        if !actual.matches(*expected, options.tolerance) {
+            comparison.mismatch_count += 1;
+            if comparison.mismatches.len() < options.max_reported {
+                comparison.mismatches.push(Mismatch {
+                    index,
+                    expected: *expected,
+                    actual: *actual,
+                });
+            }
+        }
+    }
+    comparison
+}
+
+impl<T: fmt::Display> fmt::Display for Comparison<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return writeln!(f, "{}: {} values match", self.name, self.expected_len);
+        }
+        if self.expected_len != self.actual_len {
+            writeln!(
+                f,
+                "{}: expected {} values, got {}",
+                self.name, self.expected_len, self.actual_len
+            )?;
+        }
+        if self.mismatch_count > 0 {
+            writeln!(
+                f,
+                "{}: {} of {} values differ",
+                self.name,
+                self.mismatch_count,
+                self.expected_len.min(self.actual_len)
+            )?;
+        }
+        for mismatch in &self.mismatches {
+            writeln!(
+                f,
+                "  [{}] expected {}, got {}",
+                mismatch.index, mismatch.expected, mismatch.actual
+            )?;
+        }
+        if self.mismatch_count > self.mismatches.len() {
+            writeln!(
+                f,
+                "  ... and {} more",
+                self.mismatch_count - self.mismatches.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTIONS: VerifyOptions = VerifyOptions {
+        tolerance: Tolerance {
+            absolute: 1e-6,
+            relative: 1e-5,
+        },
+        max_reported: 2,
+    };
+
+    #[test]
+    fn nan_only_matches_nan() {
+        assert!(compare("nan", &[f32::NAN], &[f32::NAN], &OPTIONS).is_ok());
+        assert!(!compare("nan", &[f32::NAN], &[1.0], &OPTIONS).is_ok());
+        assert!(!compare("nan", &[1.0], &[f32::NAN], &OPTIONS).is_ok());
+    }
+
+    #[test]
+    fn floats_match_within_tolerance() {
+        assert!(compare("close", &[1000.0f64], &[1000.005], &OPTIONS).is_ok());
+        assert!(!compare("far", &[1000.0f64], &[1000.02], &OPTIONS).is_ok());
+    }
+
+    #[test]
+    fn length_mismatch_fails() {
+        let comparison = compare("length", &[1u32, 2, 3], &[1, 2], &OPTIONS);
+        assert!(!comparison.is_ok());
+        assert_eq!(comparison.mismatch_count, 0);
+        assert_eq!((comparison.expected_len, comparison.actual_len), (3, 2));
+    }
+
+    #[test]
+    fn only_the_first_mismatches_are_reported() {
+        let comparison = compare("values", &[1u32, 2, 3, 4], &[0, 2, 0, 0], &OPTIONS);
+        assert_eq!(comparison.mismatch_count, 3);
+        let v_indices: Vec<usize> = comparison.mismatches.iter().map(|m| m.index).collect();
+        assert_eq!(v_indices, [0, 2]);
+    }
+}